@@ -0,0 +1,157 @@
+//! dedicated server, pairs clients as they arrive or through its lobby and
+//! referees their matches
+
+use blocks::board;
+use clap::Parser;
+
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::mpsc;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Port clients join, with `--join` or from a browser
+    #[arg(long, default_value_t = 7878)]
+    port: u16,
+
+    /// Seed of the first board of the first match, each match gets its own
+    #[arg(long, value_parser = board::parse_seed)]
+    seed: Option<u64>,
+
+    /// Play best of N boards matches, players take turns moving first
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    num_games: u16,
+
+    /// Columns of the boards
+    #[arg(long, default_value_t = 14)]
+    board_width: usize,
+
+    /// Rows of the boards
+    #[arg(long, default_value_t = 7)]
+    board_height: usize,
+
+    /// Colors dealt on the boards
+    #[arg(long, default_value_t = 4)]
+    num_colors: usize,
+
+    /// Comma separated rule toggles, e.g. `allow-pass,forbid-previous-color`
+    #[arg(long, default_value = "standard")]
+    rules: board::RuleSet,
+
+    /// Clock of the matches, e.g. `total=300,increment=5` or `per-move=10`
+    #[arg(long)]
+    clock: Option<board::ClockSetup>,
+
+    /// Run a lobby where clients list, open and join games with their own
+    /// settings, those above are used for quick matches
+    #[arg(long)]
+    lobby: bool,
+
+    /// Every board and match result is appended to this file
+    #[arg(long, default_value = "blocks-results.log")]
+    results: String,
+}
+
+fn main() {
+    let args = Args::parse();
+    bevy::log::tracing_subscriber::fmt().init();
+
+    let offer = board::GameOffer {
+        board_width: args.board_width,
+        board_height: args.board_height,
+        num_colors: args.num_colors,
+        num_games: args.num_games as usize,
+        rules: args.rules.clone(),
+        clock: args.clock,
+    };
+    if let Err(err) = offer.check() {
+        eprintln!("invalid settings: {}", err);
+        std::process::exit(1);
+    }
+    let listener = match TcpListener::bind(("0.0.0.0", args.port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("can't listen on {}: {}", args.port, err);
+            std::process::exit(1);
+        }
+    };
+    let mut results_file = match std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.results)
+    {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("can't open {}: {}", args.results, err);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("waiting for players on port {}", args.port);
+
+    // one thread greets each client, the main one pairs them
+    let (seat_sender, seat_receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("can't accept a player: {}", err);
+                    continue;
+                }
+            };
+            let seat_sender = seat_sender.clone();
+            std::thread::spawn(move || match board::greet(stream) {
+                Ok(seat) => {
+                    let _ = seat_sender.send(seat);
+                }
+                Err(err) => eprintln!("player turned away: {}", err),
+            });
+        }
+    });
+
+    let (result_sender, result_receiver) = mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for line in result_receiver {
+            if let Err(err) = writeln!(results_file, "{line}") {
+                eprintln!("can't write to the results: {}", err);
+            }
+        }
+    });
+
+    let base_seed = args.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    if args.lobby {
+        board::Lobby::new(offer, base_seed, result_sender).run(seat_receiver);
+        return;
+    }
+
+    let mut waiting: Option<board::Seat> = None;
+    let mut game_id = 0;
+    for seat in seat_receiver {
+        eprintln!("{} is looking for a game", seat.name);
+        let first_seat = match waiting.take() {
+            Some(first_seat) if !first_seat.has_left() => first_seat,
+            Some(first_seat) => {
+                eprintln!("{} left before being paired", first_seat.name);
+                waiting = Some(seat);
+                continue;
+            }
+            None => {
+                waiting = Some(seat);
+                continue;
+            }
+        };
+
+        game_id += 1;
+        let setup = offer.to_setup(board::game_seed(base_seed, game_id));
+        let result_sender = result_sender.clone();
+        std::thread::spawn(move || {
+            board::referee(game_id, setup, [first_seat, seat], result_sender);
+        });
+    }
+}
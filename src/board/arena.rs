@@ -0,0 +1,589 @@
+//! HTTP/JSON API on localhost, so that bots written in any language can play
+//! the seats set to `bot`
+
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::game_record::GameRecordResource;
+use super::game_record::parse_player_name;
+use super::game_record::player_name;
+use super::match_play::MatchResource;
+use super::move_check;
+use super::move_check::MoveSource;
+use super::move_check::RejectedMove;
+use super::network::parse_tile;
+use super::replay_viewer::ReplayResource;
+use super::setup::Controller;
+use super::setup::check_board;
+use super::setup::parse_seed;
+use super::utils;
+use super::websocket::JsonValue;
+use super::websocket::format_json_object;
+use super::websocket::get_count;
+use super::websocket::get_string;
+use super::websocket::parse_json_object;
+
+use std::io::BufRead;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::time::Duration;
+
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ArenaResource>();
+    }
+}
+
+/// A request is dropped when the game doesn't answer in time, e.g. while the
+/// window is minimized on some platforms.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BODY_LEN: usize = 4096;
+
+/// A request read by a connection thread, answered by the game with a status
+/// code and a JSON body.
+struct ArenaRequest {
+    method: String,
+    path: String,
+    body: String,
+    answer: mpsc::Sender<(u16, String)>,
+}
+
+/// Requests of the bots, `requests` is only set with `--arena`.
+#[derive(Resource, Default)]
+pub struct ArenaResource {
+    requests: Option<Mutex<mpsc::Receiver<ArenaRequest>>>,
+    /// Counts the games dealt through the API, bots tell restarts apart.
+    game_id: usize,
+}
+
+/// Serve the API on `127.0.0.1:port`, one thread per request:
+///
+/// ```text
+/// GET  /game        the position, whose turn it is and the legal colors
+/// POST /game        deal a new game, e.g. {"seed":"0x2a","player_one":"bot","player_two":"human"}
+/// POST /game/move   play for a bot seat, e.g. {"seat":"P2","color":"R"}
+/// ```
+///
+/// The position is a flat object, e.g.
+///
+/// ```text
+/// {"game":1,"state":"waiting","to_move":"P1","winner":null,"match_winner":null,
+///  "player_one":"bot","player_two":"human","width":14,"height":7,
+///  "board":"RGBY.../GBYR...","owners":"1...../.....2","count_one":1,"count_two":1,
+///  "legal":"GB","num_moves":0,"num_played_games":0,"num_games":1,"rules":"standard"}
+/// ```
+///
+/// Rows of `board` and `owners` are separated by `/`, owners are `1`, `2` or
+/// `.` and a legal `-` is a pass. `state` is one of `dealing`, `waiting`,
+/// `playing` or `over`, with `winner` set to `P1`, `P2` or `draw` once over.
+pub fn open_arena(setup: &mut GameSetup, port: u16) -> Result<ArenaResource, String> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|err| format!("can't listen on {port}: {err}"))?;
+    eprintln!("arena open on http://127.0.0.1:{port}/game");
+
+    // bots see the whole board, and arena games aren't resumed
+    setup.fog_radius = None;
+    setup.save_path = None;
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("can't accept a bot: {}", err);
+                    continue;
+                }
+            };
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = serve(stream, &sender) {
+                    warn!("arena: {}", err);
+                }
+            });
+        }
+    });
+    Ok(ArenaResource {
+        requests: Some(Mutex::new(receiver)),
+        game_id: 1,
+    })
+}
+
+/// A request as read off the socket, before it goes to the game.
+#[derive(Debug, PartialEq)]
+enum HttpRequest {
+    /// The path is without its query string, which is not used.
+    Valid {
+        method: String,
+        path: String,
+        body: String,
+    },
+    /// Answered right away with this status and reason.
+    Refused(u16, &'static str),
+}
+
+/// Read the request line, the headers and the body.
+fn read_request(reader: &mut impl BufRead) -> Result<HttpRequest, String> {
+    let io_err = |err: std::io::Error| err.to_string();
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(io_err)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(format!("invalid request {:?}", request_line.trim_end()));
+    };
+    let mut content_length = 0;
+    let mut has_origin = false;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(io_err)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((key, value)) = header.split_once(':') else {
+            continue;
+        };
+        if key.trim().eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .trim()
+                .parse()
+                .map_err(|err| format!("invalid content length {value:?}: {err}"))?;
+        }
+        if key.trim().eq_ignore_ascii_case("origin") {
+            has_origin = true;
+        }
+    }
+
+    // browsers name the page a request comes from, bots don't: a web page
+    // open on this machine doesn't get to play
+    if has_origin {
+        return Ok(HttpRequest::Refused(
+            403,
+            "requests from web pages are refused",
+        ));
+    }
+    if content_length > MAX_BODY_LEN {
+        return Ok(HttpRequest::Refused(413, "the body is too long"));
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).map_err(io_err)?;
+    Ok(HttpRequest::Valid {
+        method: method.into(),
+        path: path.split('?').next().unwrap_or_default().into(),
+        body: String::from_utf8_lossy(&body).into(),
+    })
+}
+
+/// Read one request, hand it to the game and write back its answer.
+fn serve(stream: TcpStream, requests: &mpsc::Sender<ArenaRequest>) -> Result<(), String> {
+    let io_err = |err: std::io::Error| err.to_string();
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(io_err)?;
+    let mut reader = std::io::BufReader::new(stream.try_clone().map_err(io_err)?);
+
+    let (status, body) = match read_request(&mut reader)? {
+        HttpRequest::Refused(status, reason) => (status, error_json(reason)),
+        HttpRequest::Valid { method, path, body } => {
+            let (answer, answer_receiver) = mpsc::channel();
+            let request = ArenaRequest {
+                method,
+                path,
+                body,
+                answer,
+            };
+            let answered = requests
+                .send(request)
+                .ok()
+                .and_then(|_| answer_receiver.recv_timeout(ANSWER_TIMEOUT).ok());
+            answered.unwrap_or_else(|| (503, error_json("the game isn't answering")))
+        }
+    };
+
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Service Unavailable",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    (&stream)
+        .write_all(response.as_bytes())
+        .map_err(|err| format!("can't answer: {err}"))
+}
+
+fn error_json(reason: &str) -> String {
+    format_json_object(&[("error", JsonValue::String(reason.into()))])
+}
+
+/// Answer the requests of the bots, playing their moves once checked.
+#[allow(clippy::too_many_arguments)]
+pub fn receive_requests(
+    mut commands: Commands,
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    standing: Res<MatchResource>,
+    records: Res<GameRecordResource>,
+    replay: Res<ReplayResource>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    mut setup: ResMut<GameSetup>,
+    mut arena: ResMut<ArenaResource>,
+) {
+    let requests: Vec<ArenaRequest> = match &arena.requests {
+        Some(requests) => requests.lock().unwrap().try_iter().collect(),
+        None => return,
+    };
+    for request in requests {
+        let answer = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/game") => Ok(position_json(
+                &ui_cards, &ui_backs, &board, &standing, &setup, &state, &arena,
+            )),
+            ("POST", "/game") => match deal_game(&request.body, &mut setup) {
+                Ok(()) => {
+                    arena.game_id += 1;
+                    info!("arena game {} {:#x}", arena.game_id, setup.seed);
+                    commands.run_system_cached(utils::rebuild_board);
+                    Ok(format_json_object(&[
+                        ("game", JsonValue::Number(arena.game_id as f64)),
+                        ("seed", JsonValue::String(format!("{:#x}", setup.seed))),
+                    ]))
+                }
+                Err(err) => Err((400, err)),
+            },
+            ("POST", "/game/move") => {
+                let is_busy = !records.replay_moves.is_empty()
+                    || replay.is_active()
+                    || matches!(*next_state, NextState::Pending(_));
+                match parse_move(&request.body, arena.game_id) {
+                    Ok(_) if is_busy => Err((409, "the board is busy, try again".into())),
+                    Ok((player, tile)) => check_move(&board, &setup, &state, &player, &tile)
+                        .map(|()| {
+                            info!("arena {:?} plays {:?}", player, tile);
+                            next_state.set(BoardState::PlayingMove(player, tile));
+                            format_json_object(&[("accepted", JsonValue::Bool(true))])
+                        })
+                        .map_err(|err| (409, err)),
+                    Err(err) => Err((400, err)),
+                }
+            }
+            (_, "/game" | "/game/move") => Err((405, "method not allowed".into())),
+            _ => Err((404, format!("no such endpoint {:?}", request.path))),
+        };
+        let answer = match answer {
+            Ok(body) => (200, body),
+            Err((status, err)) => (status, error_json(&err)),
+        };
+        let _ = request.answer.send(answer);
+    }
+}
+
+/// Parse `{"seat":"P1","color":"R"}`, with an optional `"game"` that must be
+/// the current one.
+fn parse_move(body: &str, game_id: usize) -> Result<(Player, Tile), String> {
+    let fields = parse_json_object(body)?;
+    if let Some(id) = get_count(&fields, "game")?
+        && id != game_id
+    {
+        return Err(format!("game {id} is over, this is game {game_id}"));
+    }
+    let seat = get_string(&fields, "seat")?.ok_or("missing seat")?;
+    let color = get_string(&fields, "color")?.ok_or("missing color")?;
+    Ok((parse_player_name(seat)?, parse_tile(color)?))
+}
+
+/// Only bot seats are played from here, a refused move is recorded.
+fn check_move(
+    board: &BoardResource,
+    setup: &GameSetup,
+    state: &BoardState,
+    player: &Player,
+    tile: &Tile,
+) -> Result<(), String> {
+    if setup.controller(player) != Controller::Bot {
+        return Err(format!("{} is not a bot seat", player_name(player)));
+    }
+    move_check::check_move(board, setup, state, player, tile).inspect_err(|reason| {
+        let source = MoveSource::Engine(format!("arena bot {}", player_name(player)));
+        let rejected = RejectedMove::new(source, board, player, tile, reason);
+        move_check::record_rejected_move(setup, &rejected);
+    })
+}
+
+/// Change the setup from the fields given, the others are kept. Checked
+/// before anything changes.
+fn deal_game(body: &str, setup: &mut GameSetup) -> Result<(), String> {
+    let fields = if body.trim().is_empty() {
+        Default::default()
+    } else {
+        parse_json_object(body)?
+    };
+    let seed = match fields.get("seed") {
+        Some(JsonValue::String(seed)) => Some(parse_seed(seed)?),
+        Some(JsonValue::Number(_)) => get_count(&fields, "seed")?.map(|seed| seed as u64),
+        _ => None,
+    };
+    let board_width = get_count(&fields, "board_width")?.unwrap_or(setup.board_width);
+    let board_height = get_count(&fields, "board_height")?.unwrap_or(setup.board_height);
+    let num_colors = get_count(&fields, "num_colors")?.unwrap_or(setup.num_colors);
+    let num_games = get_count(&fields, "num_games")?.unwrap_or(setup.num_games);
+    check_board(board_width, board_height, num_colors)?;
+    if num_games == 0 {
+        return Err("a match needs at least one game".into());
+    }
+    let rules = match get_string(&fields, "rules")? {
+        Some(rules) => rules.parse()?,
+        None => setup.rules.clone(),
+    };
+    let parse_controller = |key: &str, default: Controller| -> Result<Controller, String> {
+        match get_string(&fields, key)? {
+            Some(name) => clap::ValueEnum::from_str(name, true),
+            None => Ok(default),
+        }
+    };
+    let player_one = parse_controller("player_one", setup.player_one)?;
+    let player_two = parse_controller("player_two", setup.player_two)?;
+
+    setup.seed = seed.unwrap_or_else(|| setup.seed.wrapping_add(1));
+    setup.board_width = board_width;
+    setup.board_height = board_height;
+    setup.num_colors = num_colors;
+    setup.num_games = num_games;
+    setup.rules = rules;
+    setup.player_one = player_one;
+    setup.player_two = player_two;
+    Ok(())
+}
+
+/// The position as the bots see it: the board rows with their owners, whose
+/// turn it is, the legal moves, the seats, the scores and the match standing.
+fn position_json(
+    ui_cards: &Query<(&UiCard, Entity)>,
+    ui_backs: &Query<&UiBack>,
+    board: &BoardResource,
+    standing: &MatchResource,
+    setup: &GameSetup,
+    state: &BoardState,
+    arena: &ArenaResource,
+) -> String {
+    let mut cells: Vec<(usize, usize, char, char)> = ui_cards
+        .iter()
+        .map(|(ui_card, card)| {
+            let back = board.card_to_backs.get(&card).unwrap();
+            let owner = match ui_backs.get(*back).unwrap().player {
+                Player::One => '1',
+                Player::Two => '2',
+                Player::Undef => '.',
+            };
+            (ui_card.row, ui_card.column, ui_card.tile.to_letter(), owner)
+        })
+        .collect();
+    cells.sort();
+    let mut rows = vec![String::new(); setup.board_height];
+    let mut owner_rows = vec![String::new(); setup.board_height];
+    for (row, _, letter, owner) in cells {
+        rows[row].push(letter);
+        owner_rows[row].push(owner);
+    }
+
+    let (state_name, to_move, winner) = match state {
+        BoardState::Init => ("dealing", None, None),
+        BoardState::WaitingForMove(player) => ("waiting", Some(player.clone()), None),
+        BoardState::PlayingMove(..) | BoardState::ResolvingMove(_) => ("playing", None, None),
+        BoardState::Victory(player) => ("over", None, Some(player.clone())),
+    };
+    let legal: String = match &to_move {
+        Some(player) => {
+            let mut legal: String = board
+                .player_to_playable_tiles
+                .get(player)
+                .map(|tiles| tiles.iter().map(Tile::to_letter).collect())
+                .unwrap_or_default();
+            if setup.rules.allow_pass {
+                legal.push(Tile::Undef.to_letter());
+            }
+            legal
+        }
+        None => String::new(),
+    };
+    let seat_name = |player: &Option<Player>| match player {
+        Some(Player::Undef) => JsonValue::String("draw".into()),
+        Some(player) => JsonValue::String(player_name(player).into()),
+        None => JsonValue::Null,
+    };
+    let controller = |player: &Player| {
+        let name = clap::ValueEnum::to_possible_value(&setup.controller(player))
+            .map(|value| value.get_name().to_string())
+            .unwrap_or("remote".into());
+        JsonValue::String(name)
+    };
+    let count = |player: &Player| {
+        JsonValue::Number(*board.player_to_counts.get(player).unwrap_or(&0) as f64)
+    };
+    format_json_object(&[
+        ("game", JsonValue::Number(arena.game_id as f64)),
+        ("state", JsonValue::String(state_name.into())),
+        ("to_move", seat_name(&to_move)),
+        ("winner", seat_name(&winner)),
+        ("match_winner", seat_name(&standing.winner)),
+        ("player_one", controller(&Player::One)),
+        ("player_two", controller(&Player::Two)),
+        ("width", JsonValue::Number(setup.board_width as f64)),
+        ("height", JsonValue::Number(setup.board_height as f64)),
+        ("board", JsonValue::String(rows.join("/"))),
+        ("owners", JsonValue::String(owner_rows.join("/"))),
+        ("count_one", count(&Player::One)),
+        ("count_two", count(&Player::Two)),
+        ("legal", JsonValue::String(legal)),
+        (
+            "num_moves",
+            JsonValue::Number(board.num_resolved_moves as f64),
+        ),
+        (
+            "num_played_games",
+            JsonValue::Number(standing.num_played_games as f64),
+        ),
+        ("num_games", JsonValue::Number(setup.num_games as f64)),
+        ("rules", JsonValue::String(setup.rules.to_string())),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &str) -> Result<HttpRequest, String> {
+        read_request(&mut request.as_bytes())
+    }
+
+    #[test]
+    fn requests_are_read() {
+        let body = r#"{"seat":"P2","color":"R"}"#;
+        let request = format!(
+            "POST /game/move?verbose=1 HTTP/1.1\r\nHost: 127.0.0.1\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        assert_eq!(
+            read(&request),
+            Ok(HttpRequest::Valid {
+                method: "POST".into(),
+                path: "/game/move".into(),
+                body: body.into(),
+            })
+        );
+        assert_eq!(
+            read("GET /game HTTP/1.1\r\n\r\n"),
+            Ok(HttpRequest::Valid {
+                method: "GET".into(),
+                path: "/game".into(),
+                body: "".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn web_pages_and_long_bodies_are_refused() {
+        let request =
+            "POST /game HTTP/1.1\r\nOrigin: http://example.com\r\nContent-Length: 2\r\n\r\n{}";
+        assert!(matches!(read(request), Ok(HttpRequest::Refused(403, _))));
+        // the body isn't read, only announced
+        let request = format!(
+            "POST /game HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        );
+        assert!(matches!(read(&request), Ok(HttpRequest::Refused(413, _))));
+    }
+
+    #[test]
+    fn broken_requests_are_refused() {
+        for request in [
+            "",
+            "GET\r\n\r\n",
+            "POST /game HTTP/1.1\r\nContent-Length: lots\r\n\r\n",
+            "POST /game HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}",
+        ] {
+            assert!(read(request).is_err(), "{request:?}");
+        }
+    }
+
+    #[test]
+    fn moves_are_parsed_and_checked() {
+        assert_eq!(
+            parse_move(r#"{"seat":"P2","color":"R","game":3}"#, 3),
+            Ok((Player::Two, Tile::Red))
+        );
+        for body in [
+            r#"{"seat":"P2","color":"R","game":2}"#,
+            r#"{"color":"R"}"#,
+            r#"{"seat":"P3","color":"R"}"#,
+            r#"{"seat":"P2","color":"X"}"#,
+            "seat=P2",
+        ] {
+            assert!(parse_move(body, 3).is_err(), "{body:?}");
+        }
+
+        let mut board = BoardResource::default();
+        board
+            .player_to_playable_tiles
+            .insert(Player::Two, [Tile::Red].into());
+        let setup = GameSetup {
+            player_two: Controller::Bot,
+            ..GameSetup::default()
+        };
+        let state = BoardState::WaitingForMove(Player::Two);
+        assert_eq!(
+            check_move(&board, &setup, &state, &Player::Two, &Tile::Red),
+            Ok(())
+        );
+        assert!(check_move(&board, &setup, &state, &Player::Two, &Tile::Blue).is_err());
+        let state = BoardState::WaitingForMove(Player::One);
+        assert!(check_move(&board, &setup, &state, &Player::One, &Tile::Red).is_err());
+    }
+
+    #[test]
+    fn games_are_dealt_from_the_fields_given() {
+        let mut setup = GameSetup::default();
+        deal_game(r#"{"seed":"0x2a","player_two":"bot"}"#, &mut setup).unwrap();
+        assert_eq!(setup.seed, 0x2a);
+        assert_eq!(setup.player_two, Controller::Bot);
+        assert_eq!(setup.board_width, GameSetup::default().board_width);
+        deal_game(r#"{"seed":42,"num_games":3}"#, &mut setup).unwrap();
+        assert_eq!((setup.seed, setup.num_games), (42, 3));
+        deal_game("", &mut setup).unwrap();
+        assert_eq!(setup.seed, 43);
+
+        for body in [
+            r#"{"board_width":2}"#,
+            r#"{"num_games":0}"#,
+            r#"{"player_one":"robot"}"#,
+            r#"{"seed":"0xnothex"}"#,
+        ] {
+            assert!(deal_game(body, &mut setup).is_err(), "{body:?}");
+        }
+        assert_eq!((setup.seed, setup.num_games), (43, 3));
+    }
+}
@@ -3,11 +3,15 @@ use bevy::prelude::*;
 use super::BoardResource;
 use super::BoardState;
 use super::Direction;
+use super::GameSetup;
 use super::Player;
 use super::Tile;
 
 use super::BOARD_BLOCK;
+use super::fog_of_war::FogOfWarResource;
 use super::player::PLAYER_COLOR_DATA;
+use super::rules::MoveContext;
+use super::setup::Preferences;
 use super::tile::TILE_COLOR_DATA;
 
 use std::cmp::Ordering;
@@ -20,12 +24,12 @@ use std::collections::HashSet;
 pub struct UiCard {
     pub tile: Tile,
     pub row: usize,
-    column: usize,
+    pub column: usize,
 }
 
 #[derive(Component)]
 pub struct UiBack {
-    player: Player,
+    pub player: Player,
 }
 
 #[derive(Clone, Eq, PartialEq, PartialOrd)]
@@ -126,11 +130,7 @@ pub fn make_pair(
     (card_entity.unwrap(), back.id())
 }
 
-pub fn compute_neighborhoods(
-    ui_cards: Query<(&UiCard, Entity)>,
-    mut board: ResMut<BoardResource>,
-    mut next_state: ResMut<NextState<BoardState>>,
-) {
+pub fn compute_neighborhoods(ui_cards: Query<(&UiCard, Entity)>, mut board: ResMut<BoardResource>) {
     let mut coord_to_cards = HashMap::new();
     for (ui_card, card) in ui_cards.iter() {
         coord_to_cards.insert((ui_card.row, ui_card.column), card);
@@ -142,26 +142,25 @@ pub fn compute_neighborhoods(
         if let Some(neighbor) = coord_to_cards.get(&(ui_card.row + 1, ui_card.column)) {
             neighbors.insert(Direction::South, *neighbor);
         }
-        if let Some(neighbor) = coord_to_cards.get(&(ui_card.row - 1, ui_card.column)) {
+        if let Some(neighbor) = coord_to_cards.get(&(ui_card.row.wrapping_sub(1), ui_card.column)) {
             neighbors.insert(Direction::North, *neighbor);
         }
         if let Some(neighbor) = coord_to_cards.get(&(ui_card.row, ui_card.column + 1)) {
             neighbors.insert(Direction::East, *neighbor);
         }
-        if let Some(neighbor) = coord_to_cards.get(&(ui_card.row, ui_card.column - 1)) {
+        if let Some(neighbor) = coord_to_cards.get(&(ui_card.row, ui_card.column.wrapping_sub(1))) {
             neighbors.insert(Direction::West, *neighbor);
         }
         card_to_neighbors.insert(entity, neighbors);
     }
     board.card_to_neighbors = card_to_neighbors;
-
-    next_state.set(BoardState::WaitingForMove(Player::One));
 }
 
 pub fn update_counts_and_playable_tiles(
     ui_backs: Query<&UiBack>,
     ui_cards: Query<(&UiCard, Entity)>,
     mut board: ResMut<BoardResource>,
+    setup: Res<GameSetup>,
 ) {
     let mut player_to_counts = BTreeMap::new();
     for ui_back in ui_backs.iter() {
@@ -172,13 +171,13 @@ pub fn update_counts_and_playable_tiles(
     }
     board.player_to_counts = player_to_counts;
 
-    let mut player_to_playable_tiles = BTreeMap::new();
-    player_to_playable_tiles.insert(Player::One, BTreeSet::new());
-    player_to_playable_tiles.insert(Player::Two, BTreeSet::new());
+    let mut player_to_adjacent_tiles = BTreeMap::new();
+    player_to_adjacent_tiles.insert(Player::One, BTreeSet::new());
+    player_to_adjacent_tiles.insert(Player::Two, BTreeSet::new());
     for (ui_card, card_entity) in ui_cards.iter() {
         let back_entity = *board.card_to_backs.get(&card_entity).unwrap();
         let ui_back = ui_backs.get(back_entity).unwrap();
-        if let Some(playable_tiles) = player_to_playable_tiles.get_mut(&ui_back.player) {
+        if let Some(adjacent_tiles) = player_to_adjacent_tiles.get_mut(&ui_back.player) {
             assert!(ui_card.tile != Tile::Undef);
             for (_, card_entity_) in board.card_to_neighbors.get(&card_entity).unwrap().iter() {
                 let card_entity_ = *card_entity_;
@@ -186,29 +185,41 @@ pub fn update_counts_and_playable_tiles(
                 let ui_card_ = ui_cards.get(card_entity_).unwrap().0;
                 let ui_back_ = ui_backs.get(back_entity_).unwrap();
                 if ui_back_.player != ui_back.player && ui_card_.tile != Tile::Undef {
-                    assert!(ui_card.tile != ui_card_.tile);
-                    playable_tiles.insert(ui_card_.tile.clone());
+                    adjacent_tiles.insert(ui_card_.tile.clone());
                 }
             }
         }
     }
 
-    {
-        let mut remove_other_player_tile = |player: Player, player_: Player| {
-            let card_entity = match player {
-                Player::One => board.player_one_card.unwrap(),
-                Player::Two => board.player_two_card.unwrap(),
-                Player::Undef => unreachable!(),
-            };
-            let back_entity = *board.card_to_backs.get(&card_entity).unwrap();
-            let ui_card = ui_cards.get(card_entity).unwrap().0;
-            let ui_back = ui_backs.get(back_entity).unwrap();
-            assert!(ui_back.player == player || ui_back.player == Player::Undef);
-            let playable_tiles = player_to_playable_tiles.get_mut(&player_).unwrap();
-            playable_tiles.remove(&ui_card.tile);
+    let player_tile = |player: &Player| -> Tile {
+        let card_entity = match player {
+            Player::One => board.player_one_card.unwrap(),
+            Player::Two => board.player_two_card.unwrap(),
+            Player::Undef => unreachable!(),
         };
-        remove_other_player_tile(Player::One, Player::Two);
-        remove_other_player_tile(Player::Two, Player::One);
+        let back_entity = *board.card_to_backs.get(&card_entity).unwrap();
+        let ui_back = ui_backs.get(back_entity).unwrap();
+        assert!(ui_back.player == *player || ui_back.player == Player::Undef);
+        ui_cards.get(card_entity).unwrap().0.tile.clone()
+    };
+
+    let mut player_to_playable_tiles = BTreeMap::new();
+    for (player, adjacent_tiles) in player_to_adjacent_tiles {
+        let opponent = match player {
+            Player::One => Player::Two,
+            Player::Two => Player::One,
+            Player::Undef => unreachable!(),
+        };
+        let context = MoveContext {
+            adjacent_tiles,
+            own_tile: player_tile(&player),
+            opponent_tile: player_tile(&opponent),
+            previous_tile: board.player_to_previous_tiles.get(&player).cloned(),
+            opponent_last_pick: board.player_to_last_picks.get(&opponent).cloned(),
+            num_color_ban_waivers: *board.player_to_color_ban_waivers.get(&player).unwrap_or(&0),
+        };
+        let playable_tiles = setup.rules.playable_tiles(&context);
+        player_to_playable_tiles.insert(player, playable_tiles);
     }
 
     for (player, playable_tiles) in player_to_playable_tiles.iter() {
@@ -220,7 +231,9 @@ pub fn update_counts_and_playable_tiles(
 
 pub fn play_and_resolve_move(
     mut ui_cards: Query<&mut UiCard>,
+    ui_backs: Query<&UiBack>,
     mut board: ResMut<BoardResource>,
+    setup: Res<GameSetup>,
     state: Res<State<BoardState>>,
     mut next_state: ResMut<NextState<BoardState>>,
 ) {
@@ -231,12 +244,37 @@ pub fn play_and_resolve_move(
     assert!(board.card_to_backs.len() == board.card_to_neighbors.len());
 
     if let BoardState::PlayingMove(player, tile) = state.get() {
-        let player_card = match player {
-            Player::One => board.player_one_card.unwrap(),
-            Player::Two => board.player_two_card.unwrap(),
+        let (player_card, other_player_card) = match player {
+            Player::One => (
+                board.player_one_card.unwrap(),
+                board.player_two_card.unwrap(),
+            ),
+            Player::Two => (
+                board.player_two_card.unwrap(),
+                board.player_one_card.unwrap(),
+            ),
             _ => unreachable!(),
         };
 
+        // a move without color is a pass
+        if *tile == Tile::Undef {
+            assert!(setup.rules.allow_pass);
+            board.num_consecutive_passes += 1;
+            board
+                .player_to_last_picks
+                .insert(player.clone(), Tile::Undef);
+            next_state.set(BoardState::ResolvingMove(player.clone()));
+            return;
+        }
+        board.num_consecutive_passes = 0;
+
+        // picking the banned opponent color uses up a waiver
+        let other_player_tile = ui_cards.get(other_player_card).unwrap().tile.clone();
+        if setup.rules.uses_color_ban_waiver(tile, &other_player_tile) {
+            let num_waivers = board.player_to_color_ban_waivers.get_mut(player).unwrap();
+            assert!(*num_waivers > 0);
+            *num_waivers -= 1;
+        }
         let mut done = HashSet::new();
         let mut queue = priority_queue::PriorityQueue::new();
         queue.push(
@@ -247,6 +285,12 @@ pub fn play_and_resolve_move(
             },
         );
         let current_tile = ui_cards.get(player_card).unwrap().tile.clone();
+        board
+            .player_to_previous_tiles
+            .insert(player.clone(), current_tile.clone());
+        board
+            .player_to_last_picks
+            .insert(player.clone(), tile.clone());
         while let Some((current_card, current_priority)) = queue.pop() {
             assert!(!done.contains(&current_card));
 
@@ -258,6 +302,10 @@ pub fn play_and_resolve_move(
                 if next_tile != current_tile {
                     continue;
                 }
+                let next_back = board.card_to_backs.get(next_card).unwrap();
+                if ui_backs.get(*next_back).unwrap().player != *player {
+                    continue;
+                }
                 let mut next_priority = current_priority.clone();
                 next_priority.distance += 1;
                 queue.push(*next_card, next_priority);
@@ -278,24 +326,38 @@ pub fn play_and_resolve_move(
         let winning_player = match player_one_score.cmp(player_two_score) {
             Ordering::Less => Player::Two,
             Ordering::Equal => Player::Undef,
-            Ordering::Greater => Player::Two,
+            Ordering::Greater => Player::One,
         };
-        let next_player = match player {
+        let other_player = match player {
             Player::One => Player::Two,
             Player::Two => Player::One,
             Player::Undef => unreachable!(),
         };
         board.num_resolved_moves += 1;
-        let state =
-            if let Some(next_playable_tiles) = board.player_to_playable_tiles.get(&next_player) {
-                if !next_playable_tiles.is_empty() {
-                    BoardState::WaitingForMove(next_player)
-                } else {
-                    BoardState::Victory(winning_player.clone())
-                }
-            } else {
-                BoardState::Victory(winning_player.clone())
-            };
+
+        // extra moves from a handicap are played in a row
+        let can_play_again = board
+            .player_to_playable_tiles
+            .get(player)
+            .is_some_and(|playable_tiles| !playable_tiles.is_empty());
+        let num_extra_moves = board.player_to_extra_moves.get_mut(player).unwrap();
+        let next_player = if *num_extra_moves > 0 && can_play_again {
+            *num_extra_moves -= 1;
+            player.clone()
+        } else {
+            other_player
+        };
+
+        let next_can_move = setup.rules.allow_pass
+            || board
+                .player_to_playable_tiles
+                .get(&next_player)
+                .is_some_and(|playable_tiles| !playable_tiles.is_empty());
+        let state = if next_can_move && board.num_consecutive_passes < 2 {
+            BoardState::WaitingForMove(next_player)
+        } else {
+            BoardState::Victory(winning_player.clone())
+        };
         next_state.set(state);
     }
 }
@@ -311,8 +373,11 @@ pub fn update_backs(
     assert!(!board.card_to_backs.is_empty());
     assert!(board.card_to_backs.len() == board.card_to_neighbors.len());
 
-    // reset player
-    for mut ui_back in ui_backs.iter_mut() {
+    // reset player, remembering owned cards so that touching territories never merge
+    let mut card_to_previous_players = HashMap::new();
+    for (card, back) in board.card_to_backs.iter() {
+        let mut ui_back = ui_backs.get_mut(*back).unwrap();
+        card_to_previous_players.insert(*card, ui_back.player.clone());
         ui_back.player = Player::Undef;
     }
 
@@ -345,6 +410,10 @@ pub fn update_backs(
             if next_tile != current_tile {
                 continue;
             }
+            let previous_player = card_to_previous_players.get(next_card).unwrap();
+            if *previous_player != Player::Undef && *previous_player != current_priority.player {
+                continue;
+            }
             let mut next_priority = current_priority.clone();
             next_priority.distance += 1;
             queue.push(*next_card, next_priority);
@@ -362,6 +431,7 @@ pub fn animate_backs(
     mut ui_backs: Query<(&UiBack, &mut BackgroundColor, &mut BorderColor, &mut Node)>,
     ui_cards: Query<Entity, With<UiCard>>,
     board: Res<BoardResource>,
+    fog: Res<FogOfWarResource>,
 ) {
     assert!(board.player_one_card.is_some());
     assert!(board.player_two_card.is_some());
@@ -369,24 +439,36 @@ pub fn animate_backs(
     assert!(!board.card_to_backs.is_empty());
     assert!(board.card_to_backs.len() == board.card_to_neighbors.len());
 
+    // hidden cards look unowned
+    let displayed_player = |ui_card: &Entity, player: &Player| -> Player {
+        if fog.hidden_cards.contains(ui_card) {
+            Player::Undef
+        } else {
+            player.clone()
+        }
+    };
+
     // update colors
-    for (ui_back, mut back_color, mut border_color, _) in ui_backs.iter_mut() {
-        let player_index: usize = ui_back.player.clone().into();
+    for ui_card in ui_cards.iter() {
+        let ui_back = board.card_to_backs.get(&ui_card).unwrap();
+        let (ui_back, mut back_color, mut border_color, _) = ui_backs.get_mut(*ui_back).unwrap();
+        let player = displayed_player(&ui_card, &ui_back.player);
+        let player_index: usize = player.into();
         let (bg_color, fg_color) = PLAYER_COLOR_DATA[player_index].clone();
         *back_color = bg_color.into();
         *border_color = fg_color.into();
     }
 
     // update border
-    for ui_card in ui_cards {
+    for ui_card in ui_cards.iter() {
         let ui_back = board.card_to_backs.get(&ui_card).unwrap();
-        let player = ui_backs.get(*ui_back).unwrap().0.player.clone();
+        let player = displayed_player(&ui_card, &ui_backs.get(*ui_back).unwrap().0.player);
         let next_cards = board.card_to_neighbors.get(&ui_card).unwrap();
         let check_neighbor = |direction: &Direction| -> Val {
             if let Some(ui_card_) = next_cards.get(direction) {
                 let ui_back_ = board.card_to_backs.get(&ui_card_).unwrap();
                 let ui_back_ = ui_backs.get(*ui_back_).unwrap().0;
-                if ui_back_.player == player {
+                if displayed_player(ui_card_, &ui_back_.player) == player {
                     Val::Px(0.0)
                 } else {
                     Val::Px(2.0)
@@ -420,12 +502,28 @@ pub fn animate_backs(
 }
 
 pub fn animate_cards(
-    mut ui_cards: Query<(&UiCard, &Children, &mut BorderColor, &mut BackgroundColor), With<Button>>,
+    mut ui_cards: Query<
+        (
+            &UiCard,
+            Entity,
+            &Children,
+            &mut BorderColor,
+            &mut BackgroundColor,
+        ),
+        With<Button>,
+    >,
     mut buttons: Query<&mut ImageNode>,
+    fog: Res<FogOfWarResource>,
+    preferences: Res<Preferences>,
 ) {
-    for (ui_card, children, mut border_color, mut back_color) in ui_cards.iter_mut() {
-        let tile_index: usize = ui_card.tile.clone().into();
-        let (bg_color, fg_color, atlas_index) = TILE_COLOR_DATA[tile_index].clone();
+    let tile_color_data = preferences.palette.tile_color_data();
+    for (ui_card, card_entity, children, mut border_color, mut back_color) in ui_cards.iter_mut() {
+        let tile_index: usize = if fog.hidden_cards.contains(&card_entity) {
+            Tile::Undef.into()
+        } else {
+            ui_card.tile.clone().into()
+        };
+        let (bg_color, fg_color, atlas_index) = tile_color_data[tile_index];
         let bg_color: Color = bg_color.into();
         let fg_color: Color = fg_color.into();
         *border_color = fg_color.into();
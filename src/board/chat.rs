@@ -0,0 +1,364 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::input::ButtonState;
+use bevy::input::InputSystem;
+use bevy::input::keyboard::Key;
+use bevy::input::keyboard::KeyboardInput;
+use bevy::prelude::*;
+
+use super::GameSetup;
+use super::Player;
+use super::network::Message;
+use super::network::NetworkResource;
+use super::network::opponent;
+use super::setup::Controller;
+use super::setup::Preferences;
+use super::utils;
+
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatResource>();
+        app.add_systems(Startup, populate);
+        app.add_systems(
+            PreUpdate,
+            type_message
+                .run_if(is_chatting)
+                .after(InputSystem)
+                .before(utils::start_board),
+        );
+        app.add_systems(
+            Update,
+            (
+                receive_chat.run_if(is_chatting),
+                animate_chat,
+                animate_bubbles,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Quick emotes, sent with the digit keys.
+const EMOTES: [&str; 4] = ["good move", "gg", "oops", "thinking"];
+const EMOTE_KEYS: [KeyCode; 4] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+];
+
+/// Longer lines are cut, on both ends.
+const MAX_CHAT_LEN: usize = 80;
+const MAX_CHAT_LINES: usize = 6;
+/// Seconds a chat line or emote stays beside the clock of its sender.
+const BUBBLE_DURATION: f32 = 4.0;
+
+/// At most `MAX_BURST` messages every `BURST_SECONDS`, the rest is dropped.
+const MAX_BURST: usize = 3;
+const BURST_SECONDS: f32 = 5.0;
+
+/// Times of the last messages of a sender, to keep chat from flooding.
+#[derive(Default)]
+pub struct RateLimit {
+    sent_times: VecDeque<f32>,
+}
+
+impl RateLimit {
+    /// Whether a message at `now` seconds fits, it counts if it does.
+    pub fn allow(&mut self, now: f32) -> bool {
+        while self
+            .sent_times
+            .front()
+            .is_some_and(|sent_time| now - sent_time >= BURST_SECONDS)
+        {
+            self.sent_times.pop_front();
+        }
+        if self.sent_times.len() >= MAX_BURST {
+            return false;
+        }
+        self.sent_times.push_back(now);
+        true
+    }
+}
+
+/// Chat of an online game between two seats.
+#[derive(Resource, Default)]
+struct ChatResource {
+    lines: VecDeque<String>,
+    /// Line being typed, `Enter` sends it and `Escape` drops it.
+    draft: Option<String>,
+    outgoing: RateLimit,
+    incoming: RateLimit,
+    /// Opponents without a profile name are only muted for this game.
+    is_muted: bool,
+    player_to_bubbles: BTreeMap<Player, (String, f32)>,
+}
+
+impl ChatResource {
+    fn push_line(&mut self, line: String) {
+        self.lines.push_back(line);
+        while self.lines.len() > MAX_CHAT_LINES {
+            self.lines.pop_front();
+        }
+    }
+}
+
+/// Chat is between the two players, spectators and local games have none.
+fn is_chatting(setup: Res<GameSetup>, network: Option<Res<NetworkResource>>) -> bool {
+    setup.is_online() && !setup.is_spectating() && network.is_some()
+}
+
+fn local_player(setup: &GameSetup) -> Player {
+    if setup.player_one == Controller::Remote {
+        Player::Two
+    } else {
+        Player::One
+    }
+}
+
+fn is_muted(chat: &ChatResource, preferences: &Preferences, setup: &GameSetup) -> bool {
+    let remote_player = opponent(&local_player(setup));
+    match setup.profile(&remote_player) {
+        Some(name) => preferences.muted_players.contains(name),
+        None => chat.is_muted,
+    }
+}
+
+fn cut(text: &str) -> String {
+    text.chars()
+        .filter(|character| !character.is_control())
+        .take(MAX_CHAT_LEN)
+        .collect()
+}
+
+/// `Enter` starts a line, the digit keys send an emote and `M` mutes or
+/// unmutes the opponent. While typing no other key reaches the game.
+fn type_message(
+    mut chat: ResMut<ChatResource>,
+    mut network: ResMut<NetworkResource>,
+    mut preferences: ResMut<Preferences>,
+    mut keyboard: ResMut<ButtonInput<KeyCode>>,
+    mut key_events: EventReader<KeyboardInput>,
+    setup: Res<GameSetup>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let local_player = local_player(&setup);
+    let Some(mut draft) = chat.draft.take() else {
+        key_events.clear();
+        if keyboard.just_pressed(KeyCode::Enter) {
+            chat.draft = Some(String::new());
+            keyboard.reset_all();
+            return;
+        }
+        for (key, emote) in EMOTE_KEYS.iter().zip(EMOTES) {
+            if !keyboard.just_pressed(*key) || !chat.outgoing.allow(now) {
+                continue;
+            }
+            network.send_chat(Message::Emote(emote.into()));
+            chat.player_to_bubbles
+                .insert(local_player.clone(), (emote.into(), now));
+        }
+        if keyboard.just_pressed(KeyCode::KeyM) {
+            let remote_player = opponent(&local_player);
+            match setup.profile(&remote_player) {
+                Some(name) if preferences.muted_players.contains(name) => {
+                    preferences.muted_players.remove(name);
+                }
+                Some(name) => {
+                    preferences.muted_players.insert(name.clone());
+                }
+                None => chat.is_muted = !chat.is_muted,
+            }
+        }
+        return;
+    };
+
+    let mut is_done = false;
+    for key_event in key_events.read() {
+        if key_event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &key_event.logical_key {
+            Key::Enter => {
+                let text = cut(draft.trim());
+                if !text.is_empty() && chat.outgoing.allow(now) {
+                    network.send_chat(Message::Chat(text.clone()));
+                    let line = format!("{}: {}", setup.player_name(&local_player), text);
+                    chat.push_line(line);
+                    chat.player_to_bubbles
+                        .insert(local_player.clone(), (text, now));
+                } else if !text.is_empty() {
+                    chat.push_line("slow down, the line was not sent".into());
+                }
+                is_done = true;
+            }
+            Key::Escape => is_done = true,
+            Key::Backspace => {
+                draft.pop();
+            }
+            _ => {
+                if let Some(text) = &key_event.text
+                    && draft.chars().count() < MAX_CHAT_LEN
+                {
+                    draft.extend(text.chars().filter(|character| !character.is_control()));
+                }
+            }
+        }
+        if is_done {
+            break;
+        }
+    }
+    if !is_done {
+        chat.draft = Some(draft);
+    }
+    keyboard.reset_all();
+}
+
+/// Show what the opponent says, unless muted or flooding.
+fn receive_chat(
+    mut chat: ResMut<ChatResource>,
+    mut network: ResMut<NetworkResource>,
+    preferences: Res<Preferences>,
+    setup: Res<GameSetup>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    let remote_player = opponent(&local_player(&setup));
+    for message in network.take_chat() {
+        if is_muted(&chat, &preferences, &setup) || !chat.incoming.allow(now) {
+            continue;
+        }
+        let text = match message {
+            Message::Chat(text) => cut(&text),
+            Message::Emote(text) if EMOTES.contains(&text.as_str()) => text,
+            _ => continue,
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let line = format!("{}: {}", setup.player_name(&remote_player), text);
+        chat.push_line(line);
+        chat.player_to_bubbles
+            .insert(remote_player.clone(), (text, now));
+    }
+}
+
+#[derive(Component)]
+struct ChatDiv;
+
+#[derive(Component)]
+struct ChatText;
+
+/// Last chat line or emote of a player, beside their clock.
+#[derive(Component)]
+pub struct UiChatBubble {
+    pub player: Player,
+}
+
+const CHAT_BG_COLOR: Srgba = Srgba::new(0.0, 0.0, 0.0, 0.6);
+const CHAT_FG_COLOR: Srgba = GRAY_100;
+
+fn populate(mut commands: Commands) {
+    let mut frame = commands.spawn((
+        ChatDiv,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(40.0),
+            left: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(6.0)),
+            ..default()
+        },
+        BackgroundColor(CHAT_BG_COLOR.into()),
+        Visibility::Hidden,
+        ZIndex(2),
+    ));
+    frame.with_child((
+        ChatText,
+        TextColor(CHAT_FG_COLOR.into()),
+        TextFont::from_font_size(14.0),
+        Text::new(""),
+    ));
+}
+
+fn animate_chat(
+    mut chat_div: Single<&mut Visibility, With<ChatDiv>>,
+    mut chat_text: Single<&mut Text, With<ChatText>>,
+    chat: Res<ChatResource>,
+    preferences: Res<Preferences>,
+    setup: Res<GameSetup>,
+    network: Option<Res<NetworkResource>>,
+) {
+    if !setup.is_online() || setup.is_spectating() || network.is_none() {
+        **chat_div = Visibility::Hidden;
+        return;
+    }
+    **chat_div = Visibility::Visible;
+    if !chat.is_changed() && !preferences.is_changed() {
+        return;
+    }
+
+    let mut lines: Vec<String> = chat.lines.iter().cloned().collect();
+    match &chat.draft {
+        Some(draft) => lines.push(format!("> {draft}_")),
+        None => {
+            let remote_player = opponent(&local_player(&setup));
+            let name = setup.player_name(&remote_player);
+            let mute = if is_muted(&chat, &preferences, &setup) {
+                format!("[m] unmute {name}")
+            } else {
+                format!("[m] mute {name}")
+            };
+            lines.push(format!(
+                "[enter] chat  [1-4] {}  {}",
+                EMOTES.join(", "),
+                mute
+            ));
+        }
+    }
+    **chat_text = lines.join("\n").into();
+}
+
+fn animate_bubbles(
+    mut ui_bubbles: Query<(&UiChatBubble, &mut Text)>,
+    chat: Res<ChatResource>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    for (ui_bubble, mut text) in ui_bubbles.iter_mut() {
+        let bubble = match chat.player_to_bubbles.get(&ui_bubble.player) {
+            Some((bubble, since)) if now - since < BUBBLE_DURATION => bubble.as_str(),
+            _ => "",
+        };
+        if text.0 != bubble {
+            text.0 = bubble.into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_are_limited() {
+        let mut limit = RateLimit::default();
+        assert!(limit.allow(0.0));
+        assert!(limit.allow(1.0));
+        assert!(limit.allow(2.0));
+        // a dropped message doesn't count
+        assert!(!limit.allow(3.0));
+        assert!(!limit.allow(4.9));
+        // the window slides one message at a time
+        assert!(limit.allow(5.0));
+        assert!(!limit.allow(5.5));
+        assert!(limit.allow(6.0));
+        assert!(limit.allow(7.0));
+        assert!(!limit.allow(9.9));
+        assert!(limit.allow(10.0));
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::computer_player;
+use super::fog_of_war::FogOfWarResource;
+use super::player::PLAYER_COLOR_DATA;
+use super::setup::ClockSetup;
+use super::setup::Controller;
+
+use std::collections::BTreeMap;
+
+pub struct ChessClockPlugin;
+
+impl Plugin for ChessClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChessClockResource>();
+        app.add_systems(PreUpdate, reset_clocks.run_if(on_event::<StartBoard>));
+        app.add_systems(OnEnter(BoardState::ResolvingMove(Player::One)), press_clock);
+        app.add_systems(OnEnter(BoardState::ResolvingMove(Player::Two)), press_clock);
+        app.add_systems(Update, animate_clocks);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ChessClockResource {
+    pub player_to_remaining: BTreeMap<Player, f32>,
+    pub flagged_player: Option<Player>,
+}
+
+#[derive(Component)]
+pub struct UiClockBlock {
+    pub player: Player,
+}
+
+pub fn reset_clocks(setup: Res<GameSetup>, mut clocks: ResMut<ChessClockResource>) {
+    clocks.player_to_remaining.clear();
+    clocks.flagged_player = None;
+    let remaining = match setup.clock {
+        None => return,
+        Some(ClockSetup::Fischer { total, .. }) => total,
+        Some(ClockSetup::PerMove { seconds }) => seconds,
+    };
+    clocks.player_to_remaining.insert(Player::One, remaining);
+    clocks.player_to_remaining.insert(Player::Two, remaining);
+}
+
+pub fn press_clock(
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut clocks: ResMut<ChessClockResource>,
+) {
+    let BoardState::ResolvingMove(player) = state.get() else {
+        unreachable!();
+    };
+    if let Some(remaining) = clocks.player_to_remaining.get_mut(player) {
+        match setup.clock {
+            Some(ClockSetup::Fischer { increment, .. }) => *remaining += increment,
+            Some(ClockSetup::PerMove { seconds }) => *remaining = seconds,
+            None => unreachable!(),
+        }
+    }
+}
+
+/// Run the clock of the player to move, and end the game or play for them on
+/// flag fall.
+#[allow(clippy::too_many_arguments)]
+pub fn tick(
+    ui_cards: Query<&UiCard>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    fog: Res<FogOfWarResource>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    mut clocks: ResMut<ChessClockResource>,
+    time: Res<Time>,
+) {
+    let BoardState::WaitingForMove(player) = state.get() else {
+        return;
+    };
+    if fog.handover_pending || matches!(*next_state, NextState::Pending(_)) {
+        return;
+    }
+    let Some(remaining) = clocks.player_to_remaining.get_mut(player) else {
+        return;
+    };
+
+    *remaining = (*remaining - time.delta_secs()).max(0.0);
+    if *remaining > 0.0 {
+        return;
+    }
+    // the other instance tells when its own flag falls
+    if setup.controller(player) == Controller::Remote {
+        return;
+    }
+
+    if setup.auto_move_on_timeout
+        && let Some(tile) = computer_player::best_move(&ui_cards, &ui_backs, &board, &fog, player)
+    {
+        info!("flag fall {:?} auto plays {:?}", player, tile);
+        next_state.set(BoardState::PlayingMove(player.clone(), tile));
+        return;
+    }
+
+    info!("flag fall {:?}", player);
+    let winning_player = match player {
+        Player::One => Player::Two,
+        Player::Two => Player::One,
+        Player::Undef => unreachable!(),
+    };
+    clocks.flagged_player = Some(player.clone());
+    next_state.set(BoardState::Victory(winning_player));
+}
+
+fn animate_clocks(
+    mut ui_clocks: Query<(&UiClockBlock, &mut Text, &mut TextColor)>,
+    clocks: Res<ChessClockResource>,
+    state: Res<State<BoardState>>,
+) {
+    for (ui_clock, mut text, mut text_color) in ui_clocks.iter_mut() {
+        let Some(remaining) = clocks.player_to_remaining.get(&ui_clock.player) else {
+            **text = "".into();
+            continue;
+        };
+        let label = if *remaining < 10.0 {
+            format!("{:.1}", remaining)
+        } else {
+            let seconds = remaining.ceil() as usize;
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        };
+        let player_index: usize = ui_clock.player.clone().into();
+        let (_, fg_color) = PLAYER_COLOR_DATA[player_index];
+        let is_running = *state.get() == BoardState::WaitingForMove(ui_clock.player.clone());
+        let fg_color = if clocks.flagged_player.as_ref() == Some(&ui_clock.player) {
+            RED_600
+        } else if is_running && *remaining < 10.0 {
+            RED_400
+        } else {
+            fg_color
+        };
+        **text = label;
+        *text_color = fg_color.into();
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::fog_of_war::FogOfWarResource;
+use super::setup::Controller;
+
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+const THINKING_DELAY: f32 = 0.6;
+
+#[allow(clippy::too_many_arguments)]
+pub fn play_move(
+    ui_cards: Query<&UiCard>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    fog: Res<FogOfWarResource>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    mut thinking_since: Local<Option<f32>>,
+    time: Res<Time>,
+) {
+    let BoardState::WaitingForMove(player) = state.get() else {
+        *thinking_since = None;
+        return;
+    };
+    if setup.controller(player) != Controller::Computer {
+        *thinking_since = None;
+        return;
+    }
+
+    let now = time.elapsed().as_secs_f32();
+    let since = *thinking_since.get_or_insert(now);
+    if now - since < THINKING_DELAY {
+        return;
+    }
+    *thinking_since = None;
+
+    // with no color to pick, a pass keeps the game going
+    let tile = match best_move(&ui_cards, &ui_backs, &board, &fog, player) {
+        Some(tile) => tile,
+        None if setup.rules.allow_pass => Tile::Undef,
+        None => return,
+    };
+    info!("computer {:?} plays {:?}", player, tile);
+    next_state.set(BoardState::PlayingMove(player.clone(), tile));
+}
+
+/// Pick the playable color that grows the player territory the most.
+pub fn best_move(
+    ui_cards: &Query<&UiCard>,
+    ui_backs: &Query<&UiBack>,
+    board: &BoardResource,
+    fog: &FogOfWarResource,
+    player: &Player,
+) -> Option<Tile> {
+    let playable_tiles = board.player_to_playable_tiles.get(player)?;
+    let mut best_move = None;
+    for tile in playable_tiles.iter() {
+        let gain = evaluate_gain(ui_cards, ui_backs, board, fog, player, tile);
+        match best_move {
+            Some((_, best_gain)) if best_gain >= gain => {}
+            _ => best_move = Some((tile.clone(), gain)),
+        }
+    }
+    best_move.map(|(tile, _)| tile)
+}
+
+/// Count the cards of the given color that would join the player territory,
+/// only walking through cards visible to that player.
+fn evaluate_gain(
+    ui_cards: &Query<&UiCard>,
+    ui_backs: &Query<&UiBack>,
+    board: &BoardResource,
+    fog: &FogOfWarResource,
+    player: &Player,
+    tile: &Tile,
+) -> usize {
+    let owner = |card: &Entity| -> Player {
+        let back = board.card_to_backs.get(card).unwrap();
+        ui_backs.get(*back).unwrap().player.clone()
+    };
+
+    let mut done = HashSet::new();
+    let mut queue = VecDeque::new();
+    for card in board.card_to_backs.keys() {
+        if owner(card) == *player {
+            done.insert(*card);
+            queue.push_back(*card);
+        }
+    }
+
+    let mut gain = 0;
+    while let Some(current_card) = queue.pop_front() {
+        for next_card in board.card_to_neighbors.get(&current_card).unwrap().values() {
+            if done.contains(next_card) || !fog.is_visible_to(player, next_card) {
+                continue;
+            }
+            if owner(next_card) != Player::Undef {
+                continue;
+            }
+            if ui_cards.get(*next_card).unwrap().tile != *tile {
+                continue;
+            }
+            done.insert(*next_card);
+            queue.push_back(*next_card);
+            gain += 1;
+        }
+    }
+
+    gain
+}
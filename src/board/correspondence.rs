@@ -0,0 +1,673 @@
+//! correspondence games, played one turn at a time by exchanging turn files
+
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::game_record;
+use super::game_record::GameRecord;
+use super::game_record::GameRecordResource;
+use super::game_result::GameResultResource;
+use super::move_check;
+use super::move_check::MoveSource;
+use super::move_check::RejectedMove;
+use super::move_history::MoveHistoryResource;
+use super::network::opponent;
+use super::profiles::parse_profile_name;
+use super::setup::Controller;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub struct CorrespondencePlugin;
+
+impl Plugin for CorrespondencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CorrespondenceResource>();
+        app.add_systems(Startup, populate);
+        app.add_systems(Update, (check_turn, send_turn, animate_status).chain());
+    }
+}
+
+/// Bumped whenever the meaning of an existing tag changes.
+const TURN_VERSION: u32 = 1;
+
+/// The game so far as a game record, with the game it belongs to, who sent
+/// it and a digest of the moves, e.g.
+///
+/// ```text
+/// [Version "1"]
+/// [Correspondence "0x5f3a9c2e81d4b706"]
+/// [Sender "P1"]
+/// [ProfileOne "alice"]
+/// [ProfileTwo "bob"]
+/// [Digest "0x3b9f6a2c81d4e057"]
+/// [Board "14x7"]
+/// ...
+///
+/// 1. R B 2. G
+/// ```
+///
+/// The digest covers the board and every move. It is unkeyed, anyone can
+/// compute it again, so it only catches a file damaged on the way or a typo
+/// made by hand. What keeps past moves from being changed is the ledger: a
+/// reply is checked against the digest of the turn sent, kept on this
+/// machine.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnFile {
+    pub version: u32,
+    pub game_id: u64,
+    pub sender: Player,
+    pub player_one_profile: Option<String>,
+    pub player_two_profile: Option<String>,
+    pub record: GameRecord,
+    pub digest: u64,
+}
+
+impl TurnFile {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        content.parse()
+    }
+
+    /// FNV-1a hash of the game and its first `num_moves` moves, a checksum
+    /// rather than a signature. The result
+    /// is left out as it only follows from the moves, and so are the profiles
+    /// as the first turn doesn't know the name of the opponent.
+    pub fn compute_digest(&self, num_moves: usize) -> u64 {
+        let record = GameRecord {
+            moves: self.record.moves[..num_moves.min(self.record.moves.len())].to_vec(),
+            result: None,
+            ..self.record.clone()
+        };
+        let text = format!("{:#x}\n{}", self.game_id, record);
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in text.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+}
+
+impl std::fmt::Display for TurnFile {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(ff, "[Version \"{}\"]", self.version)?;
+        writeln!(ff, "[Correspondence \"{:#x}\"]", self.game_id)?;
+        writeln!(
+            ff,
+            "[Sender \"{}\"]",
+            game_record::player_name(&self.sender)
+        )?;
+        if let Some(name) = &self.player_one_profile {
+            writeln!(ff, "[ProfileOne \"{}\"]", name)?;
+        }
+        if let Some(name) = &self.player_two_profile {
+            writeln!(ff, "[ProfileTwo \"{}\"]", name)?;
+        }
+        writeln!(ff, "[Digest \"{:#018x}\"]", self.digest)?;
+        write!(ff, "{}", self.record)
+    }
+}
+
+fn parse_hex(key: &str, value: &str) -> Result<u64, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u64::from_str_radix(digits, 16).map_err(|err| format!("invalid {key} {value:?}: {err}"))
+}
+
+impl std::str::FromStr for TurnFile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (record, other_tags) = GameRecord::parse_with_other_tags(value)?;
+        let mut version = 0;
+        let mut game_id = None;
+        let mut sender = None;
+        let mut player_one_profile = None;
+        let mut player_two_profile = None;
+        let mut digest = None;
+        for (key, tag_value) in other_tags {
+            let tag_value = tag_value.as_str();
+            match key.as_str() {
+                "Version" => {
+                    version = tag_value
+                        .parse()
+                        .map_err(|err| format!("invalid version {tag_value:?}: {err}"))?
+                }
+                "Correspondence" => game_id = Some(parse_hex(&key, tag_value)?),
+                "Sender" => sender = Some(game_record::parse_player_name(tag_value)?),
+                "ProfileOne" => player_one_profile = Some(parse_profile_name(tag_value)?),
+                "ProfileTwo" => player_two_profile = Some(parse_profile_name(tag_value)?),
+                "Digest" => digest = Some(parse_hex(&key, tag_value)?),
+                _ => warn!("ignoring turn file tag {key:?}"),
+            }
+        }
+
+        if version > TURN_VERSION {
+            return Err(format!(
+                "written with format version {version}, this build reads up to {TURN_VERSION}"
+            ));
+        }
+        Ok(Self {
+            version,
+            game_id: game_id.ok_or("missing game, not a turn file")?,
+            sender: sender.ok_or("missing sender")?,
+            player_one_profile,
+            player_two_profile,
+            record,
+            digest: digest.ok_or("missing digest")?,
+        })
+    }
+}
+
+/// Last turn sent of a game, the reply must start with the same moves.
+#[derive(Debug, Clone, PartialEq)]
+struct LedgerEntry {
+    local_player: Player,
+    num_moves: usize,
+    digest: u64,
+}
+
+/// The ledger holds one line per game, the local seat, the number of moves
+/// sent and their digest, e.g.
+///
+/// ```text
+/// 0x5f3a9c2e81d4b706 = P1 7 0x3b9f6a2c81d4e057
+/// ```
+fn parse_ledger(content: &str) -> Result<BTreeMap<u64, LedgerEntry>, String> {
+    let mut game_to_entries = BTreeMap::new();
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (game_id, entry) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected game = entry, got {line:?}"))?;
+        let parts: Vec<&str> = entry.split_whitespace().collect();
+        let [local_player, num_moves, digest] = parts[..] else {
+            return Err(format!("expected seat, moves and digest, got {entry:?}"));
+        };
+        let entry = LedgerEntry {
+            local_player: game_record::parse_player_name(local_player)?,
+            num_moves: num_moves
+                .parse()
+                .map_err(|err| format!("invalid number of moves {num_moves:?}: {err}"))?,
+            digest: parse_hex("digest", digest)?,
+        };
+        game_to_entries.insert(parse_hex("game", game_id.trim())?, entry);
+    }
+    Ok(game_to_entries)
+}
+
+fn load_ledger(path: &Option<PathBuf>) -> Result<BTreeMap<u64, LedgerEntry>, String> {
+    let Some(path) = path else {
+        return Ok(BTreeMap::new());
+    };
+    match std::fs::read_to_string(path) {
+        Ok(content) => parse_ledger(&content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(format!("can't read {}: {}", path.display(), err)),
+    }
+}
+
+fn write_ledger(path: &Path, game_to_entries: &BTreeMap<u64, LedgerEntry>) -> Result<(), String> {
+    let mut content = String::new();
+    for (game_id, entry) in game_to_entries.iter() {
+        content += &format!(
+            "{:#x} = {} {} {:#018x}\n",
+            game_id,
+            game_record::player_name(&entry.local_player),
+            entry.num_moves,
+            entry.digest
+        );
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("can't create {}: {}", parent.display(), err))?;
+    }
+    std::fs::write(path, content).map_err(|err| format!("can't write {}: {}", path.display(), err))
+}
+
+/// Game played through turn files, `path` is only set with
+/// `--correspondence`.
+#[derive(Resource, Default)]
+pub struct CorrespondenceResource {
+    path: Option<String>,
+    ledger_path: Option<PathBuf>,
+    game_id: u64,
+    local_player: Player,
+    /// Moves of the file opened, they are replayed before the local turn.
+    num_received_moves: usize,
+    /// Moves already checked against the ledger, those after must be the
+    /// sender's. Unknown for a game seen here for the first time.
+    num_known_moves: Option<usize>,
+    is_checked: bool,
+    is_sent: bool,
+    halted: bool,
+    status: String,
+}
+
+impl CorrespondenceResource {
+    fn is_open(&self) -> bool {
+        self.path.is_some()
+    }
+
+    fn halt(&mut self, reason: String) {
+        error!("correspondence: {}", reason);
+        self.status = reason;
+        self.halted = true;
+    }
+}
+
+/// Open the turn file at `path`, checking it against the ledger, or start a
+/// new game to be written there once the local turn is played.
+pub fn open_turn_file(setup: &mut GameSetup, path: &str) -> Result<CorrespondenceResource, String> {
+    let ledger = load_ledger(&setup.correspondence_path)?;
+    let local_name = setup.player_one_profile.take();
+    setup.num_games = 1;
+    setup.clock = None;
+    setup.fog_radius = None;
+    setup.save_path = None;
+
+    if !Path::new(path).exists() {
+        let game_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or_default()
+            ^ setup.seed;
+        let local_player = setup.first_player.clone();
+        seat(setup, &local_player, local_name, None);
+        eprintln!("new correspondence game, your turn goes to {path}");
+        return Ok(CorrespondenceResource {
+            path: Some(path.into()),
+            ledger_path: setup.correspondence_path.clone(),
+            game_id,
+            local_player,
+            is_checked: true,
+            status: format!("new game, play your move, it goes to {path}"),
+            ..default()
+        });
+    }
+
+    let turn = TurnFile::load(path)?;
+    let num_moves = turn.record.moves.len();
+    if turn.digest != turn.compute_digest(num_moves) {
+        return Err("the turn file is damaged, its digest doesn't match".into());
+    }
+    let local_player = opponent(&turn.sender);
+    let num_known_moves = match ledger.get(&turn.game_id) {
+        Some(entry) if entry.local_player == turn.sender => {
+            return Err("this is a turn you sent, open the reply instead".into());
+        }
+        Some(entry) if num_moves <= entry.num_moves => {
+            return Err(format!(
+                "no new move since the turn you sent, move {}",
+                entry.num_moves
+            ));
+        }
+        Some(entry) if turn.compute_digest(entry.num_moves) != entry.digest => {
+            return Err(format!(
+                "the first {} moves differ from the turn you sent",
+                entry.num_moves
+            ));
+        }
+        Some(entry) => Some(entry.num_moves),
+        None => None,
+    };
+
+    turn.record.apply_to(setup, None);
+    setup.replay_source = Some(MoveSource::File(path.into()));
+    let remote_name = match turn.sender {
+        Player::One => turn.player_one_profile.clone(),
+        _ => turn.player_two_profile.clone(),
+    };
+    seat(setup, &local_player, local_name, remote_name);
+    eprintln!(
+        "correspondence game {:#x}, move {} from {}",
+        turn.game_id,
+        num_moves,
+        setup.player_name(&turn.sender)
+    );
+    Ok(CorrespondenceResource {
+        path: Some(path.into()),
+        ledger_path: setup.correspondence_path.clone(),
+        game_id: turn.game_id,
+        local_player,
+        num_received_moves: num_moves,
+        num_known_moves,
+        status: "checking the moves received".into(),
+        ..default()
+    })
+}
+
+/// The local seat plays in the window, the other one in turn files.
+fn seat(
+    setup: &mut GameSetup,
+    local_player: &Player,
+    local_name: Option<String>,
+    remote_name: Option<String>,
+) {
+    (
+        setup.player_one,
+        setup.player_two,
+        setup.player_one_profile,
+        setup.player_two_profile,
+    ) = match local_player {
+        Player::One => (Controller::Human, Controller::Mail, local_name, remote_name),
+        _ => (Controller::Mail, Controller::Human, remote_name, local_name),
+    };
+}
+
+pub fn is_not_halted(correspondence: Res<CorrespondenceResource>) -> bool {
+    !correspondence.halted
+}
+
+fn is_settled(
+    state: &BoardState,
+    next_state: &NextState<BoardState>,
+    records: &GameRecordResource,
+) -> bool {
+    matches!(
+        state,
+        BoardState::WaitingForMove(_) | BoardState::Victory(_)
+    ) && matches!(next_state, NextState::Unchanged)
+        && records.replay_moves.is_empty()
+}
+
+/// Once the received moves are replayed, check they were all legal and that
+/// the new ones were played by the sender.
+fn check_turn(
+    board: Res<BoardResource>,
+    history: Res<MoveHistoryResource>,
+    records: Res<GameRecordResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    mut correspondence: ResMut<CorrespondenceResource>,
+) {
+    if !correspondence.is_open() || correspondence.is_checked || correspondence.halted {
+        return;
+    }
+    if !is_settled(state.get(), &next_state, &records) {
+        return;
+    }
+    correspondence.is_checked = true;
+
+    if history.moves.len() != correspondence.num_received_moves {
+        let reason = format!(
+            "move {} of the turn file is not playable",
+            history.moves.len() + 1
+        );
+        correspondence.halt(reason);
+        return;
+    }
+    // without a turn sent from here, only the last move is known to be new
+    let num_known_moves = correspondence
+        .num_known_moves
+        .unwrap_or(history.moves.len().saturating_sub(1));
+    let sender = opponent(&correspondence.local_player);
+    let moves = history.moves.iter().enumerate();
+    if let Some((index, record)) = moves
+        .skip(num_known_moves)
+        .find(|(_, record)| record.player != sender)
+    {
+        let reason = format!("move {} of the turn file plays for you", index + 1);
+        let source = MoveSource::File(correspondence.path.clone().unwrap_or_default());
+        let rejected = RejectedMove {
+            move_number: index + 1,
+            ..RejectedMove::new(source, &board, &record.player, &record.tile, &reason)
+        };
+        move_check::record_rejected_move(&setup, &rejected);
+        correspondence.halt(reason);
+        return;
+    }
+    correspondence.status = match state.get() {
+        BoardState::WaitingForMove(player) if *player == sender => {
+            correspondence.halt(format!(
+                "the turn of {} isn't over",
+                setup.player_name(player)
+            ));
+            return;
+        }
+        BoardState::Victory(_) => "the game is over".into(),
+        _ => format!(
+            "{} played move {}, your turn",
+            setup.player_name(&sender),
+            history.moves.len()
+        ),
+    };
+    if correspondence.num_known_moves.is_none() && history.moves.len() > 1 {
+        warn!("first turn of this game seen here, earlier moves can't be checked");
+        correspondence.status += " (earlier moves unchecked)";
+    }
+}
+
+/// Write the turn file and the ledger once the local turn is over.
+#[allow(clippy::too_many_arguments)]
+fn send_turn(
+    board: Res<BoardResource>,
+    history: Res<MoveHistoryResource>,
+    records: Res<GameRecordResource>,
+    game_results: Res<GameResultResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    mut correspondence: ResMut<CorrespondenceResource>,
+) {
+    if !correspondence.is_checked || correspondence.is_sent || correspondence.halted {
+        return;
+    }
+    if !is_settled(state.get(), &next_state, &records)
+        || history.moves.len() <= correspondence.num_received_moves
+    {
+        return;
+    }
+    let remote_player = opponent(&correspondence.local_player);
+    let is_over = matches!(state.get(), BoardState::Victory(_));
+    if !is_over && *state.get() != BoardState::WaitingForMove(remote_player.clone()) {
+        return;
+    }
+    let Some(path) = correspondence.path.clone() else {
+        return;
+    };
+
+    let record = GameRecord {
+        seed: board.seed,
+        board_width: setup.board_width,
+        board_height: setup.board_height,
+        num_colors: setup.num_colors,
+        rules: setup.rules.clone(),
+        player_one: setup.player_one,
+        player_two: setup.player_two,
+        player_one_handicap: setup.player_one_handicap.clone(),
+        player_two_handicap: setup.player_two_handicap.clone(),
+        first_player: board.first_player.clone(),
+        moves: history
+            .moves
+            .iter()
+            .map(|record| record.tile.clone())
+            .collect(),
+        result: game_results
+            .results
+            .last()
+            .filter(|_| is_over)
+            .map(|result| result.to_string()),
+    };
+    let mut turn = TurnFile {
+        version: TURN_VERSION,
+        game_id: correspondence.game_id,
+        sender: correspondence.local_player.clone(),
+        player_one_profile: setup.player_one_profile.clone(),
+        player_two_profile: setup.player_two_profile.clone(),
+        record,
+        digest: 0,
+    };
+    let num_moves = turn.record.moves.len();
+    turn.digest = turn.compute_digest(num_moves);
+    correspondence.is_sent = true;
+
+    if let Err(err) = std::fs::write(&path, turn.to_string()) {
+        correspondence.halt(format!("can't write {path}: {err}"));
+        return;
+    }
+    if let Some(ledger_path) = &correspondence.ledger_path {
+        let written = load_ledger(&correspondence.ledger_path).and_then(|mut ledger| {
+            let entry = LedgerEntry {
+                local_player: correspondence.local_player.clone(),
+                num_moves,
+                digest: turn.digest,
+            };
+            ledger.insert(correspondence.game_id, entry);
+            write_ledger(ledger_path, &ledger)
+        });
+        if let Err(err) = written {
+            error!("correspondence ledger: {}", err);
+        }
+    }
+    info!("turn written to {}", path);
+    correspondence.status = format!(
+        "{}, send {} to {}",
+        if is_over { "game over" } else { "turn done" },
+        path,
+        setup.player_name(&remote_player)
+    );
+}
+
+#[derive(Component)]
+struct CorrespondenceText;
+
+const CORRESPONDENCE_FG_COLOR: Srgba = GRAY_100;
+const CORRESPONDENCE_ERROR_COLOR: Srgba = RED_400;
+
+fn populate(mut commands: Commands, correspondence: Res<CorrespondenceResource>) {
+    if !correspondence.is_open() {
+        return;
+    }
+    let mut frame = commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        },
+        ZIndex(1),
+    ));
+    frame.with_child((
+        CorrespondenceText,
+        TextColor(CORRESPONDENCE_FG_COLOR.into()),
+        Text::new(""),
+    ));
+}
+
+fn animate_status(
+    correspondence_text: Option<Single<(&mut Text, &mut TextColor), With<CorrespondenceText>>>,
+    correspondence: Res<CorrespondenceResource>,
+) {
+    let Some(mut correspondence_text) = correspondence_text else {
+        return;
+    };
+    let (text, text_color) = &mut *correspondence_text;
+    if correspondence.is_changed() {
+        **text = correspondence.status.clone().into();
+    }
+    if correspondence.halted {
+        text_color.0 = CORRESPONDENCE_ERROR_COLOR.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Tile;
+    use super::*;
+
+    fn turn_file() -> TurnFile {
+        let setup = GameSetup {
+            seed: 0x1234,
+            replay_moves: vec![Tile::Red, Tile::Blue, Tile::Green],
+            ..GameSetup::default()
+        };
+        let mut turn = TurnFile {
+            version: TURN_VERSION,
+            game_id: 0x5f3a9c2e81d4b706,
+            sender: Player::One,
+            player_one_profile: Some("alice".into()),
+            player_two_profile: Some("bob".into()),
+            record: GameRecord::from_setup(&setup),
+            digest: 0,
+        };
+        turn.digest = turn.compute_digest(3);
+        turn
+    }
+
+    #[test]
+    fn turn_file_round_trips() {
+        let turn = turn_file();
+        assert_eq!(turn.to_string().parse::<TurnFile>(), Ok(turn));
+        let turn = TurnFile {
+            player_two_profile: None,
+            ..turn_file()
+        };
+        assert_eq!(turn.to_string().parse::<TurnFile>(), Ok(turn));
+    }
+
+    #[test]
+    fn digest_covers_the_moves_but_not_the_profiles() {
+        let turn = turn_file();
+        let mut other = turn_file();
+        other.player_two_profile = None;
+        assert_eq!(other.compute_digest(3), turn.digest);
+        other.record.moves[1] = Tile::Yellow;
+        assert_ne!(other.compute_digest(3), turn.digest);
+        assert_eq!(other.compute_digest(1), turn.compute_digest(1));
+    }
+
+    #[test]
+    fn broken_turn_files_are_refused() {
+        let record = turn_file().record.to_string();
+        assert!(record.parse::<TurnFile>().is_err());
+        let newer = format!(
+            "[Version \"{}\"]\n[Correspondence \"0x1\"]\n[Sender \"P1\"]\n[Digest \"0x1\"]\n{}",
+            TURN_VERSION + 1,
+            record
+        );
+        assert!(newer.parse::<TurnFile>().is_err());
+        let no_digest = format!("[Correspondence \"0x1\"]\n[Sender \"P1\"]\n{record}");
+        assert!(no_digest.parse::<TurnFile>().is_err());
+        let reserved_profile = format!(
+            "[Correspondence \"0x1\"]\n[Sender \"P1\"]\n[ProfileTwo \"computer\"]\n[Digest \"0x1\"]\n{record}"
+        );
+        assert!(reserved_profile.parse::<TurnFile>().is_err());
+    }
+
+    #[test]
+    fn ledger_round_trips() {
+        let game_to_entries = BTreeMap::from([
+            (
+                0x5f3a9c2e81d4b706,
+                LedgerEntry {
+                    local_player: Player::One,
+                    num_moves: 7,
+                    digest: 0x3b9f6a2c81d4e057,
+                },
+            ),
+            (
+                0x1,
+                LedgerEntry {
+                    local_player: Player::Two,
+                    num_moves: 0,
+                    digest: 0,
+                },
+            ),
+        ]);
+        let path = std::env::temp_dir().join(format!("blocks-ledger-{}.cfg", std::process::id()));
+        write_ledger(&path, &game_to_entries).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(parse_ledger(&content), Ok(game_to_entries));
+    }
+}
@@ -0,0 +1,381 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::game_result;
+use super::game_result::GameResultResource;
+use super::profiles::MAX_PROFILE_NAME_LEN;
+use super::setup::Controller;
+use super::utils::ConfigFile;
+
+use std::collections::BTreeMap;
+
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DailyResource>();
+        app.add_systems(Startup, (load_leaderboard, populate).chain());
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::One)),
+            update_leaderboard.after(game_result::record_result),
+        );
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::Two)),
+            update_leaderboard.after(game_result::record_result),
+        );
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::Undef)),
+            update_leaderboard.after(game_result::record_result),
+        );
+        app.add_systems(Update, (toggle_leaderboard, animate_leaderboard).chain());
+    }
+}
+
+const MAX_SHOWN_SCORES: usize = 10;
+
+/// Local date as `YYYY-MM-DD`, unknown on the web.
+#[cfg(not(target_family = "wasm"))]
+pub fn today() -> Option<String> {
+    Some(jiff::Zoned::now().date().to_string())
+}
+
+#[cfg(target_family = "wasm")]
+pub fn today() -> Option<String> {
+    None
+}
+
+/// FNV-1a hash of the date, unlike the std hashers it never changes between
+/// builds so that everyone gets the same board.
+pub fn daily_seed(date: &str) -> u64 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in date.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash as u64
+}
+
+/// Turn the setup into the challenge of the given date: the board of the day
+/// with the default size and rules, a ranked single game against the computer.
+/// The first seat profile and the file paths are kept.
+pub fn setup_daily(setup: &mut GameSetup, date: String) {
+    *setup = GameSetup {
+        seed: daily_seed(&date),
+        player_one: Controller::Human,
+        player_two: Controller::Computer,
+        player_one_profile: setup.player_one_profile.clone(),
+        profiles_path: setup.profiles_path.clone(),
+        daily_path: setup.daily_path.clone(),
+        record_path: setup.record_path.clone(),
+        ranked: true,
+        daily: Some(date),
+        ..GameSetup::default()
+    };
+}
+
+/// Best game of a player on a given day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyScore {
+    pub territory: usize,
+    pub opponent_territory: usize,
+    pub num_moves: usize,
+    pub num_attempts: usize,
+}
+
+impl DailyScore {
+    fn margin(&self) -> isize {
+        self.territory as isize - self.opponent_territory as isize
+    }
+
+    /// Wider margins first, then shorter games.
+    fn beats(&self, other: &Self) -> bool {
+        (self.margin(), other.num_moves) > (other.margin(), self.num_moves)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct DailyResource {
+    pub date_to_scores: BTreeMap<String, BTreeMap<String, DailyScore>>,
+    file: ConfigFile,
+    shown: bool,
+}
+
+/// The leaderboard is written one section per date, each line holding the
+/// territory of both seats and the moves of the best game, then the number of
+/// games played that day, e.g.
+///
+/// ```text
+/// [2026-10-19]
+/// alice = 58 40 27 3
+/// P1 = 49 49 31 1
+/// ```
+fn format_leaderboard(date_to_scores: &BTreeMap<String, BTreeMap<String, DailyScore>>) -> String {
+    let mut content = String::new();
+    for (date, name_to_scores) in date_to_scores.iter() {
+        content += &format!("[{date}]\n");
+        for (name, score) in name_to_scores.iter() {
+            content += &format!(
+                "{} = {} {} {} {}\n",
+                name,
+                score.territory,
+                score.opponent_territory,
+                score.num_moves,
+                score.num_attempts
+            );
+        }
+        content += "\n";
+    }
+    content
+}
+
+fn parse_leaderboard(
+    content: &str,
+) -> (BTreeMap<String, BTreeMap<String, DailyScore>>, Vec<String>) {
+    let mut date_to_scores: BTreeMap<String, BTreeMap<String, DailyScore>> = BTreeMap::new();
+    let mut errors = vec![];
+    let mut current_date: Option<String> = None;
+
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(date) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            current_date = Some(date.to_string());
+            date_to_scores.entry(date.to_string()).or_default();
+            continue;
+        }
+        let Some(name_to_scores) = current_date
+            .as_ref()
+            .and_then(|date| date_to_scores.get_mut(date))
+        else {
+            errors.push(format!("{line:?} is outside of a date"));
+            continue;
+        };
+        let Some((name, value)) = line.split_once('=') else {
+            errors.push(format!("expected name = score, got {line:?}"));
+            continue;
+        };
+        let counts = value
+            .split_whitespace()
+            .map(|count| count.parse::<usize>())
+            .collect::<Result<Vec<usize>, _>>();
+        match counts.as_deref() {
+            Ok([territory, opponent_territory, num_moves, num_attempts]) => {
+                name_to_scores.insert(
+                    name.trim().to_string(),
+                    DailyScore {
+                        territory: *territory,
+                        opponent_territory: *opponent_territory,
+                        num_moves: *num_moves,
+                        num_attempts: *num_attempts,
+                    },
+                );
+            }
+            _ => errors.push(format!("invalid score {:?}", value.trim())),
+        }
+    }
+
+    (date_to_scores, errors)
+}
+
+fn load_leaderboard(setup: Res<GameSetup>, mut daily: ResMut<DailyResource>) {
+    if setup.daily.is_none() {
+        return;
+    }
+    (daily.file, daily.date_to_scores) =
+        ConfigFile::load("daily leaderboard", &setup.daily_path, parse_leaderboard);
+}
+
+fn update_leaderboard(
+    setup: Res<GameSetup>,
+    game_results: Res<GameResultResource>,
+    mut daily: ResMut<DailyResource>,
+) {
+    let Some(date) = &setup.daily else {
+        return;
+    };
+    let Some(result) = game_results.results.last() else {
+        return;
+    };
+
+    let name = setup.player_name(&Player::One);
+    let mut score = DailyScore {
+        territory: *result.player_to_counts.get(&Player::One).unwrap_or(&0),
+        opponent_territory: *result.player_to_counts.get(&Player::Two).unwrap_or(&0),
+        num_moves: result.num_resolved_moves,
+        num_attempts: 1,
+    };
+    let name_to_scores = daily.date_to_scores.entry(date.clone()).or_default();
+    if let Some(best_score) = name_to_scores.get(&name) {
+        score.num_attempts += best_score.num_attempts;
+        if !score.beats(best_score) {
+            score = DailyScore {
+                num_attempts: score.num_attempts,
+                ..best_score.clone()
+            };
+        }
+    }
+    info!("daily {} {} {:?}", date, name, score);
+    name_to_scores.insert(name, score);
+    daily.shown = true;
+
+    daily.file.store(&format_leaderboard(&daily.date_to_scores));
+}
+
+#[derive(Component)]
+struct LeaderboardDiv;
+
+#[derive(Component)]
+struct LeaderboardText;
+
+const LEADERBOARD_BG_COLOR: Srgba = GRAY_100;
+const LEADERBOARD_FG_COLOR: Srgba = GRAY_900;
+
+fn populate(mut commands: Commands) {
+    let mut frame = commands.spawn((
+        LeaderboardDiv,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        ZIndex(3),
+    ));
+    frame.with_children(|parent| {
+        parent
+            .spawn((
+                Node {
+                    padding: UiRect::all(Val::Px(16.0)),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(LEADERBOARD_BG_COLOR.into()),
+                BorderColor(LEADERBOARD_FG_COLOR.into()),
+                BorderRadius::all(Val::Px(8.0)),
+            ))
+            .with_child((
+                LeaderboardText,
+                TextColor(LEADERBOARD_FG_COLOR.into()),
+                Text::new("leaderboard"),
+            ));
+    });
+}
+
+/// `D` shows or hides the leaderboard of the day, it opens once the game is over.
+fn toggle_leaderboard(
+    mut daily: ResMut<DailyResource>,
+    setup: Res<GameSetup>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    if setup.daily.is_some() && keyboard.just_pressed(KeyCode::KeyD) {
+        daily.shown = !daily.shown;
+    }
+}
+
+fn animate_leaderboard(
+    mut leaderboard_div: Single<&mut Visibility, With<LeaderboardDiv>>,
+    mut leaderboard_text: Single<&mut Text, With<LeaderboardText>>,
+    daily: Res<DailyResource>,
+    setup: Res<GameSetup>,
+) {
+    let Some(date) = &setup.daily else {
+        return;
+    };
+    if !daily.shown {
+        **leaderboard_div = Visibility::Hidden;
+        return;
+    }
+    **leaderboard_div = Visibility::Visible;
+    if !daily.is_changed() {
+        return;
+    }
+
+    let mut scores: Vec<(&String, &DailyScore)> = daily
+        .date_to_scores
+        .get(date)
+        .map(|name_to_scores| name_to_scores.iter().collect())
+        .unwrap_or_default();
+    scores.sort_by_key(|(_, score)| (std::cmp::Reverse(score.margin()), score.num_moves));
+
+    let mut lines = vec![
+        format!("Daily challenge {date}\n"),
+        format!(
+            "{:>4} {:<w$} {:>7} {:>5} {:>5}",
+            "Rank",
+            "Player",
+            "Score",
+            "Moves",
+            "Games",
+            w = MAX_PROFILE_NAME_LEN
+        ),
+    ];
+    for (rank, (name, score)) in scores.iter().take(MAX_SHOWN_SCORES).enumerate() {
+        lines.push(format!(
+            "{:>4} {:<w$} {:>7} {:>5} {:>5}",
+            rank + 1,
+            name,
+            format!("{}-{}", score.territory, score.opponent_territory),
+            score.num_moves,
+            score.num_attempts,
+            w = MAX_PROFILE_NAME_LEN
+        ));
+    }
+    if scores.is_empty() {
+        lines.push("no game finished today yet".into());
+    }
+    lines.push("\n[d] close".into());
+    **leaderboard_text = lines.join("\n").into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeds_stay_the_same() {
+        // everyone must get the same board, whatever the build
+        assert_eq!(daily_seed("2026-10-19"), 0x517f4e98);
+        assert_eq!(daily_seed("2026-10-20"), 0xd877d458);
+    }
+
+    #[test]
+    fn wider_margins_then_shorter_games_win() {
+        let score = |territory, opponent_territory, num_moves| DailyScore {
+            territory,
+            opponent_territory,
+            num_moves,
+            num_attempts: 1,
+        };
+        assert!(score(58, 40, 30).beats(&score(50, 40, 20)));
+        assert!(score(50, 40, 20).beats(&score(50, 40, 30)));
+        assert!(!score(50, 40, 30).beats(&score(50, 40, 30)));
+        assert!(!score(40, 50, 10).beats(&score(45, 45, 40)));
+    }
+
+    #[test]
+    fn leaderboard_round_trips() {
+        let content = "[2026-10-19]\nP1 = 49 49 31 1\nalice = 58 40 27 3\n\n[2026-10-20]\nbob the builder = 30 68 40 2\n\n";
+        let (date_to_scores, errors) = parse_leaderboard(content);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(date_to_scores["2026-10-19"]["alice"].num_attempts, 3);
+        assert_eq!(format_leaderboard(&date_to_scores), content);
+    }
+
+    #[test]
+    fn broken_scores_are_reported() {
+        let content = "alice = 1 2 3 4\n[2026-10-19]\nbob 1 2 3 4\ncarol = 1 2 3\ndave = 1 2 3 4\n";
+        let (date_to_scores, errors) = parse_leaderboard(content);
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert_eq!(date_to_scores["2026-10-19"].len(), 1);
+    }
+}
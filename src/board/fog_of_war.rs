@@ -0,0 +1,203 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::card_and_back::UiBack;
+use super::setup::Controller;
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+pub struct FogOfWarPlugin;
+
+impl Plugin for FogOfWarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FogOfWarResource>();
+        app.add_systems(Startup, populate);
+        app.add_systems(
+            OnEnter(BoardState::WaitingForMove(Player::One)),
+            request_handover,
+        );
+        app.add_systems(
+            OnEnter(BoardState::WaitingForMove(Player::Two)),
+            request_handover,
+        );
+        app.add_systems(PreUpdate, reset_handover.run_if(on_event::<StartBoard>));
+        app.add_systems(Update, (click_handover, animate_handover).chain());
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct FogOfWarResource {
+    pub player_to_visible_cards: BTreeMap<Player, HashSet<Entity>>,
+    pub hidden_cards: HashSet<Entity>,
+    pub handover_pending: bool,
+    last_waiting_player: Option<Player>,
+}
+
+impl FogOfWarResource {
+    pub fn is_visible_to(&self, player: &Player, card: &Entity) -> bool {
+        match self.player_to_visible_cards.get(player) {
+            Some(visible_cards) => visible_cards.contains(card),
+            None => true,
+        }
+    }
+}
+
+#[derive(Component)]
+struct HandoverDiv;
+
+#[derive(Component)]
+struct HandoverText;
+
+const HANDOVER_BG_COLOR: Srgba = GRAY_900;
+const HANDOVER_FG_COLOR: Srgba = GRAY_100;
+
+fn populate(mut commands: Commands) {
+    let mut frame = commands.spawn((
+        HandoverDiv,
+        Button,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(HANDOVER_BG_COLOR.into()),
+        Visibility::Hidden,
+        ZIndex(2),
+    ));
+    frame.with_child((
+        HandoverText,
+        TextColor(HANDOVER_FG_COLOR.into()),
+        Text::new("handover"),
+    ));
+}
+
+pub fn update_visibility(
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut fog: ResMut<FogOfWarResource>,
+) {
+    fog.player_to_visible_cards.clear();
+    fog.hidden_cards.clear();
+
+    let Some(radius) = setup.fog_radius else {
+        return;
+    };
+
+    // breadth first search from each player territory
+    for player in [Player::One, Player::Two] {
+        let mut visible_cards = HashSet::new();
+        let mut queue = VecDeque::new();
+        for (card, back) in board.card_to_backs.iter() {
+            if ui_backs.get(*back).unwrap().player == player {
+                visible_cards.insert(*card);
+                queue.push_back((*card, 0));
+            }
+        }
+        while let Some((current_card, distance)) = queue.pop_front() {
+            if distance >= radius {
+                continue;
+            }
+            for next_card in board.card_to_neighbors.get(&current_card).unwrap().values() {
+                if visible_cards.insert(*next_card) {
+                    queue.push_back((*next_card, distance + 1));
+                }
+            }
+        }
+        fog.player_to_visible_cards.insert(player, visible_cards);
+    }
+
+    // pick whose point of view is displayed
+    let viewer = match (setup.player_one, setup.player_two) {
+        (Controller::Human, Controller::Human) => match state.get() {
+            BoardState::Init => Some(Player::Undef),
+            BoardState::WaitingForMove(player) => Some(player.clone()),
+            BoardState::PlayingMove(player, _) => Some(player.clone()),
+            BoardState::ResolvingMove(player) => Some(player.clone()),
+            BoardState::Victory(_) => None,
+        },
+        (Controller::Human, _) => Some(Player::One),
+        (_, Controller::Human) => Some(Player::Two),
+        _ => None,
+    };
+    let viewer = if fog.handover_pending {
+        Some(Player::Undef)
+    } else {
+        viewer
+    };
+    let viewer = match state.get() {
+        BoardState::Victory(_) => None,
+        _ => viewer,
+    };
+
+    if let Some(viewer) = viewer {
+        let hidden_cards: HashSet<Entity> = board
+            .card_to_backs
+            .keys()
+            .filter(|card| viewer == Player::Undef || !fog.is_visible_to(&viewer, card))
+            .cloned()
+            .collect();
+        fog.hidden_cards = hidden_cards;
+    }
+}
+
+fn reset_handover(mut fog: ResMut<FogOfWarResource>) {
+    fog.last_waiting_player = None;
+}
+
+fn request_handover(
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut fog: ResMut<FogOfWarResource>,
+) {
+    let BoardState::WaitingForMove(player) = state.get() else {
+        unreachable!();
+    };
+    // extra moves are played in a row without leaving the seat
+    let is_same_player = fog.last_waiting_player.as_ref() == Some(player);
+    fog.last_waiting_player = Some(player.clone());
+    fog.handover_pending = setup.fog_radius.is_some() && setup.is_hot_seat() && !is_same_player;
+}
+
+fn click_handover(
+    interactions: Query<&Interaction, (Changed<Interaction>, With<HandoverDiv>)>,
+    mut fog: ResMut<FogOfWarResource>,
+) {
+    for interaction in interactions {
+        if matches!(interaction, Interaction::Pressed) {
+            fog.handover_pending = false;
+        }
+    }
+}
+
+fn animate_handover(
+    mut handover_div: Single<&mut Visibility, With<HandoverDiv>>,
+    mut handover_text: Single<&mut Text, With<HandoverText>>,
+    fog: Res<FogOfWarResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+) {
+    **handover_div = if fog.handover_pending {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    let label = match state.get() {
+        BoardState::WaitingForMove(player) => {
+            format!("Pass to {}\nclick when ready", setup.player_name(player))
+        }
+        _ => "".into(),
+    };
+    **handover_text = label.into();
+}
@@ -0,0 +1,458 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::Tile;
+use super::game_result;
+use super::game_result::GameResultResource;
+use super::move_check;
+use super::move_check::MoveSource;
+use super::move_check::RejectedMove;
+use super::move_history::MoveHistoryResource;
+use super::rules::RuleSet;
+use super::setup;
+use super::setup::Controller;
+use super::setup::Handicap;
+
+use std::collections::VecDeque;
+
+pub struct GameRecordPlugin;
+
+impl Plugin for GameRecordPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameRecordResource>();
+        app.add_systems(Startup, queue_replay_moves);
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::One)),
+            export_record.after(game_result::record_result),
+        );
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::Two)),
+            export_record.after(game_result::record_result),
+        );
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::Undef)),
+            export_record.after(game_result::record_result),
+        );
+    }
+}
+
+/// A full game in a compact text form, e.g.
+///
+/// ```text
+/// [Board "14x7"]
+/// [Colors "4"]
+/// [Seed "0xab28f3af"]
+/// [Rules "standard"]
+/// [PlayerOne "human"]
+/// [PlayerTwo "computer"]
+/// [FirstPlayer "P1"]
+/// [Result "P1 wins 52-46 after 31 moves"]
+///
+/// 1. R B 2. G Y 3. R -
+/// ```
+///
+/// Moves alternate between players, except for handicap extra moves, and `-`
+/// is a pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub seed: u64,
+    pub board_width: usize,
+    pub board_height: usize,
+    pub num_colors: usize,
+    pub rules: RuleSet,
+    pub player_one: Controller,
+    pub player_two: Controller,
+    pub player_one_handicap: Handicap,
+    pub player_two_handicap: Handicap,
+    pub first_player: Player,
+    pub moves: Vec<Tile>,
+    pub result: Option<String>,
+}
+
+#[derive(Resource, Default)]
+pub struct GameRecordResource {
+    pub replay_moves: VecDeque<Tile>,
+    pub open_viewer_when_done: bool,
+}
+
+fn controller_name(controller: &Controller) -> &'static str {
+    match controller {
+        // the remote seat is someone playing elsewhere
+        Controller::Human | Controller::Remote | Controller::Mail => "human",
+        // so is a bot, but the record is replayed without the arena
+        Controller::Computer | Controller::Bot => "computer",
+    }
+}
+
+pub fn player_name(player: &Player) -> &'static str {
+    match player {
+        Player::Undef => "??",
+        Player::One => "P1",
+        Player::Two => "P2",
+    }
+}
+
+pub fn parse_player_name(name: &str) -> Result<Player, String> {
+    match name {
+        "P1" => Ok(Player::One),
+        "P2" => Ok(Player::Two),
+        _ => Err(format!("unknown player {name:?}")),
+    }
+}
+
+impl std::fmt::Display for GameRecord {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(ff, "[Board \"{}x{}\"]", self.board_width, self.board_height)?;
+        writeln!(ff, "[Colors \"{}\"]", self.num_colors)?;
+        writeln!(ff, "[Seed \"{:#x}\"]", self.seed)?;
+        writeln!(ff, "[Rules \"{}\"]", self.rules)?;
+        writeln!(ff, "[PlayerOne \"{}\"]", controller_name(&self.player_one))?;
+        writeln!(ff, "[PlayerTwo \"{}\"]", controller_name(&self.player_two))?;
+        if !self.player_one_handicap.is_empty() {
+            writeln!(ff, "[HandicapOne \"{}\"]", self.player_one_handicap)?;
+        }
+        if !self.player_two_handicap.is_empty() {
+            writeln!(ff, "[HandicapTwo \"{}\"]", self.player_two_handicap)?;
+        }
+        writeln!(ff, "[FirstPlayer \"{}\"]", player_name(&self.first_player))?;
+        if let Some(result) = &self.result {
+            writeln!(ff, "[Result \"{}\"]", result)?;
+        }
+        writeln!(ff)?;
+        let moves: Vec<String> = self
+            .moves
+            .chunks(2)
+            .enumerate()
+            .map(|(index, pair)| {
+                let letters: Vec<String> =
+                    pair.iter().map(|tile| tile.to_letter().into()).collect();
+                format!("{}. {}", index + 1, letters.join(" "))
+            })
+            .collect();
+        writeln!(ff, "{}", moves.join(" "))
+    }
+}
+
+impl std::str::FromStr for GameRecord {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (record, other_tags) = Self::parse_with_other_tags(value)?;
+        for (key, _) in other_tags {
+            warn!("ignoring record tag {key:?}");
+        }
+        Ok(record)
+    }
+}
+
+impl GameRecord {
+    /// Parse a record, handing back the tags it doesn't know about so that
+    /// other formats can build on top of it.
+    pub fn parse_with_other_tags(value: &str) -> Result<(Self, Vec<(String, String)>), String> {
+        let mut other_tags = vec![];
+        let default_setup = GameSetup::default();
+        let mut record = GameRecord {
+            seed: 0,
+            board_width: default_setup.board_width,
+            board_height: default_setup.board_height,
+            num_colors: default_setup.num_colors,
+            rules: RuleSet::default(),
+            player_one: Controller::Human,
+            player_two: Controller::Human,
+            player_one_handicap: Handicap::default(),
+            player_two_handicap: Handicap::default(),
+            first_player: Player::One,
+            moves: vec![],
+            result: None,
+        };
+        let mut has_seed = false;
+
+        for line in value.lines().map(|line| line.trim()) {
+            if let Some(tag) = line.strip_prefix('[') {
+                let tag = tag
+                    .strip_suffix(']')
+                    .ok_or_else(|| format!("unterminated tag {line:?}"))?;
+                let (key, tag_value) = tag
+                    .split_once(' ')
+                    .ok_or_else(|| format!("missing tag value {line:?}"))?;
+                let tag_value = tag_value.trim().trim_matches('"');
+                let parse_controller = |name: &str| -> Result<Controller, String> {
+                    clap::ValueEnum::from_str(name, true)
+                };
+                match key {
+                    "Board" => {
+                        let (width, height) = tag_value
+                            .split_once('x')
+                            .ok_or_else(|| format!("expected WxH board, got {tag_value:?}"))?;
+                        record.board_width = width
+                            .parse()
+                            .map_err(|err| format!("invalid board width: {err}"))?;
+                        record.board_height = height
+                            .parse()
+                            .map_err(|err| format!("invalid board height: {err}"))?;
+                    }
+                    "Colors" => {
+                        record.num_colors = tag_value
+                            .parse()
+                            .map_err(|err| format!("invalid number of colors: {err}"))?;
+                    }
+                    "Seed" => {
+                        record.seed = setup::parse_seed(tag_value)?;
+                        has_seed = true;
+                    }
+                    "Rules" => record.rules = tag_value.parse()?,
+                    "PlayerOne" => record.player_one = parse_controller(tag_value)?,
+                    "PlayerTwo" => record.player_two = parse_controller(tag_value)?,
+                    "HandicapOne" => record.player_one_handicap = tag_value.parse()?,
+                    "HandicapTwo" => record.player_two_handicap = tag_value.parse()?,
+                    "FirstPlayer" => record.first_player = parse_player_name(tag_value)?,
+                    "Result" => record.result = Some(tag_value.into()),
+                    _ => other_tags.push((key.to_string(), tag_value.to_string())),
+                }
+                continue;
+            }
+
+            for token in line.split_whitespace() {
+                if token.ends_with('.') {
+                    continue;
+                }
+                let mut letters = token.chars();
+                let tile = match (letters.next(), letters.next()) {
+                    (Some(letter), None) => Tile::from_letter(letter),
+                    _ => None,
+                };
+                let tile = tile.ok_or_else(|| format!("invalid move {token:?}"))?;
+                record.moves.push(tile);
+            }
+        }
+
+        if !has_seed {
+            return Err("missing seed".into());
+        }
+        setup::check_board(record.board_width, record.board_height, record.num_colors)?;
+        Ok((record, other_tags))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        content.parse()
+    }
+
+    /// The game as set up, with the moves it starts with.
+    pub fn from_setup(setup: &GameSetup) -> Self {
+        Self {
+            seed: setup.seed,
+            board_width: setup.board_width,
+            board_height: setup.board_height,
+            num_colors: setup.num_colors,
+            rules: setup.rules.clone(),
+            player_one: setup.player_one,
+            player_two: setup.player_two,
+            player_one_handicap: setup.player_one_handicap.clone(),
+            player_two_handicap: setup.player_two_handicap.clone(),
+            first_player: setup.first_player.clone(),
+            moves: setup.replay_moves.clone(),
+            result: None,
+        }
+    }
+
+    /// Set up the game so that the first moves of the record are replayed.
+    pub fn apply_to(&self, setup: &mut GameSetup, num_moves: Option<usize>) {
+        let num_moves = num_moves.unwrap_or(self.moves.len()).min(self.moves.len());
+        setup.seed = self.seed;
+        setup.board_width = self.board_width;
+        setup.board_height = self.board_height;
+        setup.num_colors = self.num_colors;
+        setup.rules = self.rules.clone();
+        setup.player_one_handicap = self.player_one_handicap.clone();
+        setup.player_two_handicap = self.player_two_handicap.clone();
+        setup.first_player = self.first_player.clone();
+        setup.replay_moves = self.moves[..num_moves].to_vec();
+    }
+}
+
+fn queue_replay_moves(setup: Res<GameSetup>, mut records: ResMut<GameRecordResource>) {
+    records.replay_moves = setup.replay_moves.iter().cloned().collect();
+    records.open_viewer_when_done = setup.open_replay_viewer;
+}
+
+/// Feed the queued moves of a loaded record through the regular move path.
+pub fn play_replay_moves(
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    mut records: ResMut<GameRecordResource>,
+) {
+    let BoardState::WaitingForMove(player) = state.get() else {
+        return;
+    };
+    let Some(tile) = records.replay_moves.pop_front() else {
+        return;
+    };
+
+    if let Err(reason) = move_check::check_move(&board, &setup, state.get(), player, &tile) {
+        let source = setup
+            .replay_source
+            .clone()
+            .unwrap_or(MoveSource::File("the loaded game".into()));
+        let rejected = RejectedMove::new(source, &board, player, &tile, &reason);
+        move_check::record_rejected_move(&setup, &rejected);
+        error!("stopping the replay at move {}", rejected.move_number);
+        records.replay_moves.clear();
+        return;
+    }
+
+    next_state.set(BoardState::PlayingMove(player.clone(), tile));
+}
+
+fn export_record(
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    history: Res<MoveHistoryResource>,
+    game_results: Res<GameResultResource>,
+) {
+    let Some(path) = &setup.record_path else {
+        return;
+    };
+    // one file per board in a match
+    let path = if setup.num_games > 1 {
+        let board_index = game_results.results.len();
+        match path.rsplit_once('.') {
+            Some((stem, extension)) => format!("{stem}-{board_index}.{extension}"),
+            None => format!("{path}-{board_index}"),
+        }
+    } else {
+        path.clone()
+    };
+    let record = GameRecord {
+        seed: board.seed,
+        board_width: setup.board_width,
+        board_height: setup.board_height,
+        num_colors: setup.num_colors,
+        rules: setup.rules.clone(),
+        player_one: setup.player_one,
+        player_two: setup.player_two,
+        player_one_handicap: setup.player_one_handicap.clone(),
+        player_two_handicap: setup.player_two_handicap.clone(),
+        first_player: board.first_player.clone(),
+        moves: history
+            .moves
+            .iter()
+            .map(|record| record.tile.clone())
+            .collect(),
+        result: game_results.results.last().map(|result| result.to_string()),
+    };
+    match std::fs::write(&path, record.to_string()) {
+        Ok(()) => info!("game record written to {}", path),
+        Err(err) => error!("can't write game record to {}: {}", path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> GameRecord {
+        GameRecord {
+            seed: 0x5f3a9c2e81d4b706,
+            board_width: 10,
+            board_height: 5,
+            num_colors: 3,
+            rules: "allow-pass,forbid-previous-color".parse().unwrap(),
+            player_one: Controller::Human,
+            player_two: Controller::Computer,
+            player_one_handicap: Handicap::default(),
+            player_two_handicap: "territory=2,moves=1,waivers=3".parse().unwrap(),
+            first_player: Player::Two,
+            moves: vec![Tile::Red, Tile::Blue, Tile::Undef, Tile::Green, Tile::Red],
+            result: Some("P2 wins 28-22".into()),
+        }
+    }
+
+    #[test]
+    fn record_round_trips() {
+        let record = record();
+        assert_eq!(record.to_string().parse::<GameRecord>(), Ok(record));
+    }
+
+    #[test]
+    fn record_without_moves_round_trips() {
+        let record = GameRecord {
+            moves: vec![],
+            result: None,
+            ..record()
+        };
+        assert_eq!(record.to_string().parse::<GameRecord>(), Ok(record));
+    }
+
+    #[test]
+    fn record_is_written_as_numbered_pairs() {
+        let text = record().to_string();
+        assert!(text.contains("[Seed \"0x5f3a9c2e81d4b706\"]\n"));
+        assert!(text.contains("[PlayerTwo \"computer\"]\n"));
+        assert!(!text.contains("HandicapOne"));
+        assert!(text.ends_with("\n1. R B 2. - G 3. R\n"));
+    }
+
+    #[test]
+    fn remote_seats_are_written_as_humans() {
+        let record = GameRecord {
+            player_one: Controller::Remote,
+            player_two: Controller::Mail,
+            ..record()
+        };
+        let parsed: GameRecord = record.to_string().parse().unwrap();
+        assert_eq!(parsed.player_one, Controller::Human);
+        assert_eq!(parsed.player_two, Controller::Human);
+    }
+
+    #[test]
+    fn unknown_tags_are_handed_back() {
+        let text = format!("[Event \"club night\"]\n{}", record());
+        let (parsed, other_tags) = GameRecord::parse_with_other_tags(&text).unwrap();
+        assert_eq!(parsed, record());
+        assert_eq!(other_tags, vec![("Event".into(), "club night".into())]);
+    }
+
+    #[test]
+    fn decimal_seeds_are_read() {
+        let text = "[Seed \"42\"]\n\n1. R\n";
+        assert_eq!(text.parse::<GameRecord>().unwrap().seed, 42);
+    }
+
+    #[test]
+    fn broken_records_are_refused() {
+        assert!("1. R B".parse::<GameRecord>().is_err());
+        assert!("[Seed \"0x1\"\n".parse::<GameRecord>().is_err());
+        assert!("[Seed \"0x1\"]\n1. R X".parse::<GameRecord>().is_err());
+        assert!("[Seed \"0x1\"]\n1. RB".parse::<GameRecord>().is_err());
+        assert!(
+            "[Seed \"0x1\"]\n[Board \"2x2\"]"
+                .parse::<GameRecord>()
+                .is_err()
+        );
+        assert!(
+            "[Seed \"0x1\"]\n[FirstPlayer \"P3\"]"
+                .parse::<GameRecord>()
+                .is_err()
+        );
+        assert!(
+            "[Seed \"0x1\"]\n[Rules \"no-rules\"]"
+                .parse::<GameRecord>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn player_names_round_trip() {
+        for player in [Player::One, Player::Two] {
+            assert_eq!(parse_player_name(player_name(&player)), Ok(player));
+        }
+        assert!(parse_player_name("??").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::setup::Handicap;
+
+use std::collections::BTreeMap;
+
+pub struct GameResultPlugin;
+
+impl Plugin for GameResultPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameResultResource>();
+        app.add_event::<ResultTakenBack>();
+        app.add_systems(OnEnter(BoardState::Victory(Player::One)), record_result);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Two)), record_result);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Undef)), record_result);
+        app.add_systems(Update, take_back_result.run_if(on_event::<ResultTakenBack>));
+    }
+}
+
+/// The move that ended the last board was taken back, its result no longer
+/// stands.
+#[derive(Event)]
+pub struct ResultTakenBack;
+
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    pub winner: Player,
+    pub player_to_counts: BTreeMap<Player, usize>,
+    pub num_resolved_moves: usize,
+    pub player_one_handicap: Handicap,
+    pub player_two_handicap: Handicap,
+}
+
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let winner = match self.winner {
+            Player::Undef => "draw",
+            Player::One => "P1 wins",
+            Player::Two => "P2 wins",
+        };
+        write!(
+            ff,
+            "{} {}-{} after {} moves",
+            winner,
+            self.player_to_counts.get(&Player::One).unwrap_or(&0),
+            self.player_to_counts.get(&Player::Two).unwrap_or(&0),
+            self.num_resolved_moves,
+        )?;
+        if !self.player_one_handicap.is_empty() {
+            write!(ff, ", P1 handicap {}", self.player_one_handicap)?;
+        }
+        if !self.player_two_handicap.is_empty() {
+            write!(ff, ", P2 handicap {}", self.player_two_handicap)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct GameResultResource {
+    pub results: Vec<GameResult>,
+}
+
+pub fn record_result(
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut game_results: ResMut<GameResultResource>,
+) {
+    let BoardState::Victory(winner) = state.get() else {
+        unreachable!();
+    };
+    let result = GameResult {
+        winner: winner.clone(),
+        player_to_counts: board.player_to_counts.clone(),
+        num_resolved_moves: board.num_resolved_moves,
+        player_one_handicap: setup.player_one_handicap.clone(),
+        player_two_handicap: setup.player_two_handicap.clone(),
+    };
+    info!("game result: {}", result);
+    game_results.results.push(result);
+}
+
+fn take_back_result(mut game_results: ResMut<GameResultResource>) {
+    if let Some(result) = game_results.results.pop() {
+        info!("game result taken back: {}", result);
+    }
+}
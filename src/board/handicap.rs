@@ -0,0 +1,85 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::GameSetup;
+use super::Player;
+use super::card_and_back::UiCard;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+/// Give each player the handicaps chosen in the game setup.
+pub fn apply_handicaps(
+    ui_cards: &mut Query<&mut UiCard>,
+    board: &mut BoardResource,
+    setup: &GameSetup,
+) {
+    assert!(!board.card_to_neighbors.is_empty());
+
+    for player in [Player::One, Player::Two] {
+        let handicap = setup.handicap(&player);
+        if !handicap.is_empty() {
+            info!("handicap {:?} {}", player, handicap);
+        }
+        board
+            .player_to_extra_moves
+            .insert(player.clone(), handicap.extra_moves);
+        board
+            .player_to_color_ban_waivers
+            .insert(player.clone(), handicap.color_ban_waivers);
+
+        // paint the cards around the starting corner with the corner color,
+        // those at least as close to the opponent corner are left alone so
+        // that the two areas never meet on small boards
+        let (player_card, opponent_card) = match player {
+            Player::One => (
+                board.player_one_card.unwrap(),
+                board.player_two_card.unwrap(),
+            ),
+            Player::Two => (
+                board.player_two_card.unwrap(),
+                board.player_one_card.unwrap(),
+            ),
+            Player::Undef => unreachable!(),
+        };
+        let opponent_distances = distances_from(board, opponent_card);
+        let player_tile = ui_cards.get(player_card).unwrap().tile.clone();
+        let mut done = HashSet::new();
+        let mut queue = VecDeque::new();
+        done.insert(player_card);
+        queue.push_back((player_card, 0));
+        while let Some((current_card, distance)) = queue.pop_front() {
+            if opponent_distances[&current_card] <= distance {
+                continue;
+            }
+            ui_cards.get_mut(current_card).unwrap().tile = player_tile.clone();
+            if distance >= handicap.extra_territory {
+                continue;
+            }
+            for next_card in board.card_to_neighbors.get(&current_card).unwrap().values() {
+                if done.insert(*next_card) {
+                    queue.push_back((*next_card, distance + 1));
+                }
+            }
+        }
+    }
+}
+
+/// Number of steps from `start_card` to every card of the board.
+fn distances_from(board: &BoardResource, start_card: Entity) -> HashMap<Entity, usize> {
+    let mut distances = HashMap::new();
+    let mut queue = VecDeque::new();
+    distances.insert(start_card, 0);
+    queue.push_back(start_card);
+    while let Some(current_card) = queue.pop_front() {
+        let distance = distances[&current_card];
+        for next_card in board.card_to_neighbors.get(&current_card).unwrap().values() {
+            if !distances.contains_key(next_card) {
+                distances.insert(*next_card, distance + 1);
+                queue.push_back(*next_card);
+            }
+        }
+    }
+    distances
+}
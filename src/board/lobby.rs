@@ -0,0 +1,450 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::GameSetup;
+use super::network;
+use super::network::Connection;
+use super::network::Message;
+use super::network::handshake;
+use super::rules::RuleSet;
+use super::setup::ClockSetup;
+use super::setup::Controller;
+use super::setup::check_board;
+use super::utils;
+
+use std::collections::BTreeMap;
+use std::net::TcpStream;
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LobbyResource>();
+        app.add_systems(Startup, populate);
+        app.add_systems(Update, (receive_games, pick_game, animate_lobby).chain());
+    }
+}
+
+/// Longest match the lobby offers, the server referees it to the end.
+const MAX_NUM_GAMES: usize = 99;
+
+/// Settings of a game opened in the lobby, written as the board size, the
+/// number of colors, the match length, the rules and the clock, e.g.
+/// `14x7 4 3 allow-pass total=300,increment=5`, `-` for no clock.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameOffer {
+    pub board_width: usize,
+    pub board_height: usize,
+    pub num_colors: usize,
+    pub num_games: usize,
+    pub rules: RuleSet,
+    pub clock: Option<ClockSetup>,
+}
+
+impl Default for GameOffer {
+    fn default() -> Self {
+        Self::from_setup(&GameSetup::default())
+    }
+}
+
+impl GameOffer {
+    pub fn from_setup(setup: &GameSetup) -> Self {
+        Self {
+            board_width: setup.board_width,
+            board_height: setup.board_height,
+            num_colors: setup.num_colors,
+            num_games: setup.num_games,
+            rules: setup.rules.clone(),
+            clock: setup.clock,
+        }
+    }
+
+    /// The game to deal, the seats are left to the caller.
+    pub fn to_setup(&self, seed: u64) -> GameSetup {
+        GameSetup {
+            seed,
+            board_width: self.board_width,
+            board_height: self.board_height,
+            num_colors: self.num_colors,
+            num_games: self.num_games,
+            rules: self.rules.clone(),
+            clock: self.clock,
+            ranked: true,
+            ..GameSetup::default()
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        check_board(self.board_width, self.board_height, self.num_colors)?;
+        if self.num_games == 0 {
+            return Err("a match needs at least one game".into());
+        }
+        if self.num_games > MAX_NUM_GAMES {
+            return Err(format!("a match has at most {MAX_NUM_GAMES} games"));
+        }
+        if let Some(clock) = &self.clock {
+            clock.check()?;
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        let clock = match &self.clock {
+            Some(ClockSetup::Fischer { total, increment }) => format!("{total}s+{increment}s"),
+            Some(ClockSetup::PerMove { seconds }) => format!("{seconds}s/move"),
+            None => "no clock".into(),
+        };
+        format!(
+            "{:>5} {} colors  best of {}  {:<12} {}",
+            format!("{}x{}", self.board_width, self.board_height),
+            self.num_colors,
+            self.num_games,
+            clock,
+            self.rules
+        )
+    }
+}
+
+impl std::fmt::Display for GameOffer {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let clock = match &self.clock {
+            Some(clock) => clock.to_string(),
+            None => "-".into(),
+        };
+        write!(
+            ff,
+            "{}x{} {} {} {} {}",
+            self.board_width, self.board_height, self.num_colors, self.num_games, self.rules, clock
+        )
+    }
+}
+
+impl std::str::FromStr for GameOffer {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = value.split_whitespace().collect();
+        let [size, num_colors, num_games, rules, clock] = parts[..] else {
+            return Err(format!(
+                "expected size, colors, games, rules and clock, got {value:?}"
+            ));
+        };
+        let (width, height) = size
+            .split_once('x')
+            .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {size:?}"))?;
+        let count = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|err| format!("invalid count {value:?}: {err}"))
+        };
+        Ok(Self {
+            board_width: count(width)?,
+            board_height: count(height)?,
+            num_colors: count(num_colors)?,
+            num_games: count(num_games)?,
+            rules: rules.parse()?,
+            clock: match clock {
+                "-" => None,
+                clock => Some(clock.parse()?),
+            },
+        })
+    }
+}
+
+/// A game waiting for a second player, as listed by the lobby.
+#[derive(Debug, Clone)]
+struct OpenGame {
+    host: String,
+    offer: GameOffer,
+}
+
+/// Connection to a lobby, kept until a game starts on it.
+#[derive(Resource, Default)]
+pub struct LobbyResource {
+    connection: Option<Connection>,
+    /// Settings of the game opened from here, those of the command line.
+    offer: GameOffer,
+    id_to_games: BTreeMap<usize, OpenGame>,
+    selected: usize,
+    is_waiting: bool,
+    /// Game opened from here, as numbered by the lobby.
+    own_game: Option<usize>,
+    status: String,
+}
+
+impl LobbyResource {
+    fn is_open(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    fn send(&mut self, message: &Message) {
+        let Some(connection) = &self.connection else {
+            return;
+        };
+        if let Err(err) = connection.send(message) {
+            self.leave(&err);
+        }
+    }
+
+    fn leave(&mut self, reason: &str) {
+        error!("lobby: {}", reason);
+        self.connection = None;
+        self.id_to_games.clear();
+        self.status = format!("left the lobby: {reason}");
+    }
+}
+
+/// Connect to a lobby and ask for its open games. The board stays idle until
+/// a game starts, the games opened from here use the given setup.
+pub fn enter_lobby(setup: &mut GameSetup, address: &str) -> Result<LobbyResource, String> {
+    let stream =
+        TcpStream::connect(address).map_err(|err| format!("can't connect to {address}: {err}"))?;
+    let connection = Connection::lines(stream)?;
+    handshake(&connection, setup.player_one_profile.clone())?;
+    connection.send(&Message::List)?;
+    eprintln!("in the lobby of {}", connection.peer);
+
+    let offer = GameOffer::from_setup(setup);
+    offer.check()?;
+    setup.player_one = Controller::Remote;
+    setup.player_two = Controller::Remote;
+    setup.clock = None;
+    setup.save_path = None;
+    Ok(LobbyResource {
+        connection: Some(connection),
+        offer,
+        status: format!("lobby of {address}"),
+        ..default()
+    })
+}
+
+/// Follow the list of open games, and sit down once a game starts.
+fn receive_games(
+    mut commands: Commands,
+    mut setup: ResMut<GameSetup>,
+    mut lobby: ResMut<LobbyResource>,
+) {
+    let messages = match &lobby.connection {
+        Some(connection) => connection.poll(),
+        None => return,
+    };
+    let mut messages = messages.into_iter();
+    while let Some(message) = messages.next() {
+        match message {
+            Ok(Message::Open { id, host, offer }) => {
+                lobby.id_to_games.insert(id, OpenGame { host, offer });
+            }
+            Ok(Message::Closed(id)) => {
+                lobby.id_to_games.remove(&id);
+                if lobby.own_game == Some(id) {
+                    lobby.own_game = None;
+                    lobby.is_waiting = false;
+                }
+            }
+            Ok(Message::Opened(id)) => {
+                lobby.own_game = Some(id);
+            }
+            Ok(start @ Message::Start { .. }) => {
+                let connection = lobby.connection.take().unwrap();
+                match network::take_seat(&mut setup, connection, None, start) {
+                    Ok(mut network) => {
+                        // the first moves may come along with the start
+                        for message in messages.flatten() {
+                            network.queue_move(message);
+                        }
+                        commands.insert_resource(network);
+                        commands.run_system_cached(utils::rebuild_board);
+                        lobby.id_to_games.clear();
+                        lobby.status.clear();
+                    }
+                    Err(err) => lobby.leave(&err),
+                }
+                return;
+            }
+            Ok(Message::Bye(reason)) => {
+                lobby.leave(reason.as_deref().unwrap_or("the lobby closed"));
+                return;
+            }
+            Ok(message) => {
+                lobby.leave(&format!("unexpected message {message}"));
+                return;
+            }
+            Err(err) => {
+                lobby.leave(&err);
+                return;
+            }
+        }
+    }
+    let num_games = lobby.id_to_games.len();
+    if lobby.selected >= num_games {
+        lobby.selected = num_games.saturating_sub(1);
+    }
+}
+
+/// `Up`/`Down` pick an open game and `Enter` joins it, `C` opens a game with
+/// the settings of the command line and `Q` plays whoever is around. `X` gives
+/// up the game opened from here.
+fn pick_game(mut lobby: ResMut<LobbyResource>, keyboard: Res<ButtonInput<KeyCode>>) {
+    if !lobby.is_open() {
+        return;
+    }
+    let num_games = lobby.id_to_games.len();
+    if keyboard.just_pressed(KeyCode::ArrowDown) && lobby.selected + 1 < num_games {
+        lobby.selected += 1;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) && lobby.selected > 0 {
+        lobby.selected -= 1;
+    }
+    if lobby.is_waiting {
+        // waiting stops once the lobby says the game is closed
+        if keyboard.just_pressed(KeyCode::KeyX)
+            && let Some(id) = lobby.own_game
+        {
+            lobby.send(&Message::Closed(id));
+        }
+        return;
+    }
+    if keyboard.just_pressed(KeyCode::Enter)
+        && let Some(id) = lobby.id_to_games.keys().nth(lobby.selected).copied()
+    {
+        lobby.send(&Message::Join(id));
+    }
+    if keyboard.just_pressed(KeyCode::KeyC) {
+        let offer = lobby.offer.clone();
+        lobby.send(&Message::Create(offer));
+        lobby.is_waiting = true;
+    }
+    if keyboard.just_pressed(KeyCode::KeyQ) {
+        lobby.send(&Message::QuickMatch);
+        lobby.is_waiting = true;
+    }
+}
+
+#[derive(Component)]
+struct LobbyDiv;
+
+#[derive(Component)]
+struct LobbyText;
+
+const LOBBY_BG_COLOR: Srgba = GRAY_100;
+const LOBBY_FG_COLOR: Srgba = GRAY_900;
+
+fn populate(mut commands: Commands) {
+    let mut frame = commands.spawn((
+        LobbyDiv,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        BackgroundColor(LOBBY_BG_COLOR.into()),
+        Visibility::Hidden,
+        ZIndex(4),
+    ));
+    frame.with_child((
+        LobbyText,
+        TextColor(LOBBY_FG_COLOR.into()),
+        Text::new("lobby"),
+    ));
+}
+
+fn animate_lobby(
+    mut lobby_div: Single<&mut Visibility, With<LobbyDiv>>,
+    mut lobby_text: Single<&mut Text, With<LobbyText>>,
+    lobby: Res<LobbyResource>,
+) {
+    // a lobby that closed before any game started stays up with the reason
+    if !lobby.is_open() && lobby.status.is_empty() {
+        **lobby_div = Visibility::Hidden;
+        return;
+    }
+    **lobby_div = Visibility::Visible;
+    if !lobby.is_changed() {
+        return;
+    }
+
+    let mut lines = vec![format!("{}\n", lobby.status)];
+    for (index, (id, game)) in lobby.id_to_games.iter().enumerate() {
+        let current = if index == lobby.selected { ">" } else { " " };
+        lines.push(format!(
+            "{}{:>4} {:<16} {}",
+            current,
+            id,
+            game.host,
+            game.offer.describe()
+        ));
+    }
+    if lobby.id_to_games.is_empty() {
+        lines.push("no open game".into());
+    }
+    lines.push(String::new());
+    if !lobby.is_open() {
+        lines.push("no game to play".into());
+    } else if lobby.is_waiting {
+        lines.push("waiting for an opponent  [x] give up".into());
+    } else {
+        lines.push(format!(
+            "[enter] join  [c] open {}  [q] quick match",
+            lobby.offer.describe()
+        ));
+    }
+    **lobby_text = lines.join("\n").into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_round_trip() {
+        let offer = GameOffer {
+            board_width: 20,
+            board_height: 9,
+            num_colors: 3,
+            num_games: 5,
+            rules: "allow-pass".parse().unwrap(),
+            clock: Some(ClockSetup::PerMove { seconds: 10.0 }),
+        };
+        assert_eq!(offer.to_string().parse(), Ok(offer));
+        let offer = GameOffer::default();
+        assert_eq!(offer.to_string(), "14x7 4 1 standard -");
+        assert_eq!(offer.to_string().parse(), Ok(offer));
+    }
+
+    #[test]
+    fn broken_offers_are_refused() {
+        assert!("14x7 4 1 standard".parse::<GameOffer>().is_err());
+        assert!("14 4 1 standard -".parse::<GameOffer>().is_err());
+        assert!("14x7 four 1 standard -".parse::<GameOffer>().is_err());
+        assert!("14x7 4 1 standard soon".parse::<GameOffer>().is_err());
+        assert!(
+            "14x7 4 0 standard -"
+                .parse::<GameOffer>()
+                .unwrap()
+                .check()
+                .is_err()
+        );
+        let offer = GameOffer {
+            num_games: usize::MAX,
+            ..GameOffer::default()
+        };
+        assert!(offer.check().is_err());
+        for clock in [
+            ClockSetup::Fischer {
+                total: f32::INFINITY,
+                increment: 0.0,
+            },
+            ClockSetup::PerMove { seconds: f32::NAN },
+            ClockSetup::PerMove { seconds: -10.0 },
+        ] {
+            let offer = GameOffer {
+                clock: Some(clock),
+                ..GameOffer::default()
+            };
+            assert!(offer.check().is_err(), "{clock:?}");
+        }
+    }
+}
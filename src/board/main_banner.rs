@@ -4,7 +4,9 @@ use bevy::prelude::*;
 use super::BOARD_BLOCK;
 use super::BoardResource;
 use super::BoardState;
+use super::GameSetup;
 use super::Player;
+use super::match_play::MatchResource;
 use super::player::PLAYER_COLOR_DATA;
 
 pub struct MainBannerPlugin;
@@ -12,7 +14,7 @@ pub struct MainBannerPlugin;
 impl Plugin for MainBannerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, populate);
-        app.add_systems(Update, (animate_main, animate_score));
+        app.add_systems(Update, (animate_main, animate_score, animate_match));
     }
 }
 
@@ -25,6 +27,9 @@ struct MainBannerText;
 #[derive(Component)]
 struct ScoreBannerText;
 
+#[derive(Component)]
+struct MatchBannerText;
+
 const BANNER_BG_COLOR: Srgba = GRAY_100;
 const BANNER_FG_COLOR: Srgba = GRAY_900;
 
@@ -69,6 +74,53 @@ fn populate(mut commands: Commands) {
         TextColor(BANNER_BG_COLOR.into()),
         Text::new("P1 ??\nP2 ??"),
     ));
+
+    let mut frame = commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(3.0 * 5.0 + 3.0 * BOARD_BLOCK / 4.0 + 3.0 * 24.0),
+            right: Val::Px(10.0),
+            align_items: AlignItems::FlexEnd,
+            justify_content: JustifyContent::FlexStart,
+            ..default()
+        },
+        ZIndex(1),
+    ));
+    frame.with_child((
+        MatchBannerText,
+        TextColor(BANNER_BG_COLOR.into()),
+        Text::new(""),
+    ));
+}
+
+fn animate_match(
+    mut match_text: Single<&mut Text, With<MatchBannerText>>,
+    standing: Res<MatchResource>,
+    setup: Res<GameSetup>,
+) {
+    if setup.num_games <= 1 {
+        return;
+    }
+    let wins = &standing.player_to_wins;
+    let counts = &standing.player_to_total_counts;
+    // the board being played, or the last one once the match is over
+    let board_number = (standing.num_played_games + 1).min(setup.num_games);
+    let lines = [
+        format!("Board {}/{}", board_number, setup.num_games),
+        format!(
+            "{} {:>2} {:>4}",
+            setup.player_name(&Player::One),
+            wins.get(&Player::One).unwrap_or(&0),
+            counts.get(&Player::One).unwrap_or(&0)
+        ),
+        format!(
+            "{} {:>2} {:>4}",
+            setup.player_name(&Player::Two),
+            wins.get(&Player::Two).unwrap_or(&0),
+            counts.get(&Player::Two).unwrap_or(&0)
+        ),
+    ];
+    **match_text = lines.join("\n").into();
 }
 
 fn animate_score(
@@ -93,24 +145,19 @@ fn animate_main(
     mut main_banner: Single<(&mut BackgroundColor, &mut BorderColor), With<MainBannerDiv>>,
     mut main_text: Single<(&mut Text, &mut TextColor), With<MainBannerText>>,
     state: Res<State<BoardState>>,
+    standing: Res<MatchResource>,
+    setup: Res<GameSetup>,
     time: Res<Time>,
 ) {
     let state = state.get();
     let make_label = |player: &Player, suffix: &str| -> String {
-        let player = match player {
-            Player::Undef => "??",
-            Player::One => "P1",
-            Player::Two => "P2",
-        };
-        format!("{} {}", player, suffix)
+        format!("{} {}", setup.player_name(player), suffix)
     };
     let make_win_label = |player: &Player| -> String {
         match player {
-            Player::Undef => "Draw",
-            Player::One => "P1 wins",
-            Player::Two => "P2 wins",
+            Player::Undef => "Draw".into(),
+            _ => format!("{} wins", setup.player_name(player)),
         }
-        .into()
     };
     let make_colors = |player: &Player| -> (Color, Color) {
         let index: usize = player.clone().into();
@@ -122,7 +169,10 @@ fn animate_main(
         BoardState::WaitingForMove(player) => make_label(player, "turn"),
         BoardState::PlayingMove(player, _) => make_label(player, "turn"),
         BoardState::ResolvingMove(player) => make_label(player, "turn"),
-        BoardState::Victory(player) => make_win_label(player),
+        BoardState::Victory(player) => match &standing.winner {
+            Some(winner) if setup.num_games > 1 => format!("{} match", make_win_label(winner)),
+            _ => make_win_label(player),
+        },
     };
     let (bg_color, fg_color) = match state {
         BoardState::Init => (BANNER_BG_COLOR.into(), BANNER_FG_COLOR.into()),
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::game_result::ResultTakenBack;
+use super::replay_viewer::ReplayResource;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+pub struct MatchPlayPlugin;
+
+impl Plugin for MatchPlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchResource>();
+        app.add_systems(OnEnter(BoardState::Victory(Player::One)), score_board);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Two)), score_board);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Undef)), score_board);
+        app.add_systems(Update, start_next_board);
+        app.add_systems(Update, take_back_board.run_if(on_event::<ResultTakenBack>));
+    }
+}
+
+const NEXT_BOARD_DELAY: f32 = 4.0;
+
+/// Standing of a series of boards, the first player alternates between boards.
+#[derive(Resource, Default)]
+pub struct MatchResource {
+    pub num_played_games: usize,
+    pub player_to_wins: BTreeMap<Player, usize>,
+    pub player_to_total_counts: BTreeMap<Player, usize>,
+    pub winner: Option<Player>,
+    board_over_since: Option<f32>,
+    /// Winner and counts of the last scored board, until the next one starts.
+    last_board: Option<(Player, BTreeMap<Player, usize>)>,
+}
+
+/// Seed of the board following `num_played_games` boards of the match.
+pub fn board_seed(setup: &GameSetup, num_played_games: usize) -> u64 {
+    setup
+        .seed
+        .wrapping_add((num_played_games as u64).wrapping_mul(0x9e3779b9))
+}
+
+impl MatchResource {
+    pub fn first_player(&self) -> Player {
+        if self.num_played_games.is_multiple_of(2) {
+            Player::One
+        } else {
+            Player::Two
+        }
+    }
+
+    pub fn is_over(&self, num_games: usize) -> bool {
+        let most_wins = self.player_to_wins.values().max().unwrap_or(&0);
+        self.num_played_games >= num_games || *most_wins > num_games / 2
+    }
+
+    fn compute_winner(&self) -> Player {
+        let wins_one = self.player_to_wins.get(&Player::One).unwrap_or(&0);
+        let wins_two = self.player_to_wins.get(&Player::Two).unwrap_or(&0);
+        let counts_one = self.player_to_total_counts.get(&Player::One).unwrap_or(&0);
+        let counts_two = self.player_to_total_counts.get(&Player::Two).unwrap_or(&0);
+        match wins_one.cmp(wins_two).then(counts_one.cmp(counts_two)) {
+            Ordering::Less => Player::Two,
+            Ordering::Equal => Player::Undef,
+            Ordering::Greater => Player::One,
+        }
+    }
+}
+
+pub fn score_board(
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut standing: ResMut<MatchResource>,
+    time: Res<Time>,
+) {
+    let BoardState::Victory(winner) = state.get() else {
+        unreachable!();
+    };
+
+    standing.num_played_games += 1;
+    standing.last_board = Some((winner.clone(), board.player_to_counts.clone()));
+    if *winner != Player::Undef {
+        *standing.player_to_wins.entry(winner.clone()).or_default() += 1;
+    }
+    for player in [Player::One, Player::Two] {
+        let count = board.player_to_counts.get(&player).unwrap_or(&0);
+        *standing.player_to_total_counts.entry(player).or_default() += count;
+    }
+
+    if standing.is_over(setup.num_games) {
+        let winner = standing.compute_winner();
+        info!("match over {:?}", winner);
+        standing.winner = Some(winner);
+    } else {
+        standing.board_over_since = Some(time.elapsed().as_secs_f32());
+    }
+}
+
+fn start_next_board(
+    mut writer: EventWriter<StartBoard>,
+    setup: Res<GameSetup>,
+    mut standing: ResMut<MatchResource>,
+    replay: Res<ReplayResource>,
+    time: Res<Time>,
+) {
+    let Some(since) = standing.board_over_since else {
+        return;
+    };
+    if replay.is_active() {
+        return;
+    }
+    if time.elapsed().as_secs_f32() - since < NEXT_BOARD_DELAY {
+        return;
+    }
+    standing.board_over_since = None;
+    standing.last_board = None;
+
+    writer.write(StartBoard {
+        seed: board_seed(&setup, standing.num_played_games),
+        first_player: standing.first_player(),
+    });
+}
+
+/// Put the standing back as it was before the board that was taken back.
+fn take_back_board(mut standing: ResMut<MatchResource>) {
+    let Some((winner, player_to_counts)) = standing.last_board.take() else {
+        return;
+    };
+    standing.num_played_games -= 1;
+    if winner != Player::Undef {
+        *standing.player_to_wins.get_mut(&winner).unwrap() -= 1;
+    }
+    for player in [Player::One, Player::Two] {
+        let count = player_to_counts.get(&player).unwrap_or(&0);
+        *standing.player_to_total_counts.get_mut(&player).unwrap() -= count;
+    }
+    standing.winner = None;
+    standing.board_over_since = None;
+}
@@ -1,11 +1,36 @@
 mod player;
+mod rules;
+mod setup;
 mod tile;
 
+mod arena;
 mod card_and_back;
+mod chat;
+mod chess_clock;
+mod computer_player;
+mod correspondence;
+mod daily;
+mod fog_of_war;
+mod game_record;
+mod game_result;
+mod handicap;
+mod lobby;
+mod match_play;
+mod move_check;
+mod move_history;
+mod network;
 mod player_block;
+mod profiles;
+mod puzzles;
+mod replay_viewer;
+mod save_game;
 mod select_move;
+mod server;
 mod sound_effect;
+mod spectators;
+mod svg_export;
 mod utils;
+mod websocket;
 
 mod debug_label;
 mod main_banner;
@@ -13,6 +38,49 @@ mod main_banner;
 use player::Player;
 use tile::Tile;
 
+pub use arena::ArenaResource;
+pub use arena::open_arena;
+pub use correspondence::CorrespondenceResource;
+pub use correspondence::TurnFile;
+pub use correspondence::open_turn_file;
+pub use daily::setup_daily;
+pub use daily::today;
+pub use game_record::GameRecord;
+pub use lobby::GameOffer;
+pub use lobby::LobbyResource;
+pub use lobby::enter_lobby;
+pub use move_check::MoveSource;
+pub use network::NetworkResource;
+pub use network::host;
+pub use network::join;
+#[cfg(target_family = "wasm")]
+pub use network::join_from_browser;
+pub use network::watch;
+pub use profiles::parse_profile_name;
+pub use puzzles::PuzzlePack;
+pub use puzzles::start_pack;
+pub use rules::RuleSet;
+pub use save_game::SavedGame;
+pub use server::Lobby;
+pub use server::Seat;
+pub use server::game_seed;
+pub use server::greet;
+pub use server::referee;
+pub use setup::ClockSetup;
+pub use setup::Controller;
+pub use setup::GameSetup;
+pub use setup::Handicap;
+pub use setup::Preferences;
+pub use setup::check_board;
+pub use setup::parse_clock_increment;
+pub use setup::parse_clock_seconds;
+pub use setup::parse_seed;
+pub use spectators::SpectatorsResource;
+pub use spectators::publish;
+pub use tile::Palette;
+#[cfg(target_family = "wasm")]
+pub use websocket::browser::join_address;
+
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
@@ -25,16 +93,37 @@ impl Plugin for BoardPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Startup,
-            (utils::populate_board, card_and_back::compute_neighborhoods).chain(),
+            (
+                utils::populate_board,
+                card_and_back::compute_neighborhoods,
+                utils::start_first_board,
+            )
+                .chain(),
         );
         app.add_systems(
             PreUpdate,
             (
+                utils::start_board,
+                move_history::undo_redo.run_if(replay_viewer::is_not_replaying),
                 card_and_back::update_counts_and_playable_tiles,
                 select_move::update,
-                select_move::click_move,
+                select_move::click_move.run_if(
+                    replay_viewer::is_not_replaying
+                        .and(network::is_not_halted)
+                        .and(correspondence::is_not_halted),
+                ),
+                computer_player::play_move.run_if(replay_viewer::is_not_replaying),
+                chess_clock::tick.run_if(replay_viewer::is_not_replaying),
+                game_record::play_replay_moves,
+                network::receive_moves.run_if(replay_viewer::is_not_replaying),
+                arena::receive_requests,
+                network::send_move,
+                network::check_move,
+                spectators::broadcast_move,
+                move_history::record_move,
                 card_and_back::play_and_resolve_move,
                 card_and_back::update_backs,
+                fog_of_war::update_visibility,
             )
                 .chain(),
         );
@@ -50,19 +139,41 @@ impl Plugin for BoardPlugin {
                 .chain(),
         );
 
+        app.add_event::<StartBoard>();
         app.init_resource::<BoardResource>();
+        app.init_resource::<GameSetup>();
+        app.init_resource::<Preferences>();
         app.init_state::<BoardState>();
 
         app.add_plugins(sound_effect::SoundEffectPlugin);
-        app.add_plugins(main_banner::MainBannerPlugin);        
-        // app.add_plugins(debug_label::DebugLabelPlugin);     
+        app.add_plugins(main_banner::MainBannerPlugin);
+        app.add_plugins(fog_of_war::FogOfWarPlugin);
+        app.add_plugins(chess_clock::ChessClockPlugin);
+        app.add_plugins(game_result::GameResultPlugin);
+        app.add_plugins(match_play::MatchPlayPlugin);
+        app.add_plugins(move_history::MoveHistoryPlugin);
+        app.add_plugins(game_record::GameRecordPlugin);
+        app.add_plugins(replay_viewer::ReplayViewerPlugin);
+        app.add_plugins(save_game::SaveGamePlugin);
+        app.add_plugins(profiles::ProfilesPlugin);
+        app.add_plugins(svg_export::SvgExportPlugin);
+        app.add_plugins(daily::DailyPlugin);
+        app.add_plugins(puzzles::PuzzlesPlugin);
+        app.add_plugins(network::NetworkPlugin);
+        app.add_plugins(spectators::SpectatorsPlugin);
+        app.add_plugins(lobby::LobbyPlugin);
+        app.add_plugins(chat::ChatPlugin);
+        app.add_plugins(arena::ArenaPlugin);
+        app.add_plugins(correspondence::CorrespondencePlugin);
+        // app.add_plugins(debug_label::DebugLabelPlugin);
     }
 }
 
+/// Default board size, the game setup can change it.
 const BOARD_WIDTH: usize = 14;
 const BOARD_HEIGHT: usize = 7;
 const BOARD_BLOCK: f32 = 74.0;
-const BOARD_SEED: usize = 0xab28f3af;
+const BOARD_SEED: u64 = 0xab28f3af;
 
 #[derive(PartialEq, Eq, Hash)]
 enum Direction {
@@ -70,10 +181,19 @@ enum Direction {
     South,
     West,
     East,
-} 
+}
+
+/// Reset every card from the seed and wait for the first player move.
+#[derive(Event)]
+struct StartBoard {
+    seed: u64,
+    first_player: Player,
+}
 
 #[derive(Resource, Default)]
 struct BoardResource {
+    seed: u64,
+    first_player: Player,
     player_one_card: Option<Entity>,
     player_two_card: Option<Entity>,
     select_cards: Vec<Entity>,
@@ -81,6 +201,11 @@ struct BoardResource {
     card_to_backs: HashMap<Entity, Entity>,
     player_to_counts: BTreeMap<Player, usize>,
     player_to_playable_tiles: BTreeMap<Player, BTreeSet<Tile>>,
+    player_to_extra_moves: BTreeMap<Player, usize>,
+    player_to_color_ban_waivers: BTreeMap<Player, usize>,
+    player_to_previous_tiles: BTreeMap<Player, Tile>,
+    player_to_last_picks: BTreeMap<Player, Tile>,
+    num_consecutive_passes: usize,
     num_resolved_moves: usize,
 }
 
@@ -89,6 +214,7 @@ enum BoardState {
     #[default]
     Init,
     WaitingForMove(Player),
+    /// Playing `Tile::Undef` is a pass.
     PlayingMove(Player, Tile),
     ResolvingMove(Player),
     Victory(Player),
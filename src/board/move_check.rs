@@ -0,0 +1,199 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::Tile;
+use super::game_record::player_name;
+
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+
+/// Where a move played outside of this window comes from.
+#[derive(Debug, Clone)]
+pub enum MoveSource {
+    /// The other side of a connection, by address.
+    Peer(String),
+    /// A game record, saved game or turn file.
+    File(String),
+    /// A bot of the arena.
+    Engine(String),
+}
+
+impl fmt::Display for MoveSource {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveSource::Peer(peer) => write!(formatter, "peer {peer}"),
+            MoveSource::File(path) => write!(formatter, "file {path}"),
+            MoveSource::Engine(name) => write!(formatter, "engine {name}"),
+        }
+    }
+}
+
+/// A move refused before reaching the board, one line of the rejected log.
+#[derive(Debug, Clone)]
+pub struct RejectedMove {
+    pub source: MoveSource,
+    pub seed: u64,
+    /// Number the move would have had on the board, from 1.
+    pub move_number: usize,
+    pub player: Player,
+    pub tile: Tile,
+    pub reason: String,
+}
+
+impl RejectedMove {
+    pub fn new(
+        source: MoveSource,
+        board: &BoardResource,
+        player: &Player,
+        tile: &Tile,
+        reason: &str,
+    ) -> Self {
+        Self {
+            source,
+            seed: board.seed,
+            move_number: board.num_resolved_moves + 1,
+            player: player.clone(),
+            tile: tile.clone(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for RejectedMove {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} board {:#x} move {}: {} {} rejected, {}",
+            self.source,
+            self.seed,
+            self.move_number,
+            player_name(&self.player),
+            self.tile.to_letter(),
+            self.reason
+        )
+    }
+}
+
+/// Whether `player` may pick `tile` now, the same check a local player goes
+/// through. The pick is then resolved here, nothing of the sender's board is
+/// taken for granted.
+pub fn check_move(
+    board: &BoardResource,
+    setup: &GameSetup,
+    state: &BoardState,
+    player: &Player,
+    tile: &Tile,
+) -> Result<(), String> {
+    if *state != BoardState::WaitingForMove(player.clone()) {
+        return Err(format!("it isn't the turn of {}", player_name(player)));
+    }
+    let is_playable = if *tile == Tile::Undef {
+        setup.rules.allow_pass
+    } else {
+        board
+            .player_to_playable_tiles
+            .get(player)
+            .is_some_and(|playable_tiles| playable_tiles.contains(tile))
+    };
+    if !is_playable {
+        return Err(format!("{} is not playable", tile.to_letter()));
+    }
+    Ok(())
+}
+
+/// Log a refused move and append it to the rejected log of the setup, if any.
+pub fn record_rejected_move(setup: &GameSetup, rejected: &RejectedMove) {
+    warn!("{}", rejected);
+    let Some(path) = &setup.rejected_path else {
+        return;
+    };
+    if let Err(err) = append_line(path, &rejected.to_string()) {
+        warn!("can't record the rejected move: {}", err);
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("can't create {}: {}", parent.display(), err))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("can't open {}: {}", path.display(), err))?;
+    writeln!(file, "{line}").map_err(|err| format!("can't write {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_with(player: Player, tiles: &[Tile]) -> BoardResource {
+        let mut board = BoardResource::default();
+        board
+            .player_to_playable_tiles
+            .insert(player, tiles.iter().cloned().collect());
+        board
+    }
+
+    #[test]
+    fn moves_out_of_turn_are_refused() {
+        let board = board_with(Player::Two, &[Tile::Red]);
+        let state = BoardState::WaitingForMove(Player::One);
+        let result = check_move(
+            &board,
+            &GameSetup::default(),
+            &state,
+            &Player::Two,
+            &Tile::Red,
+        );
+        assert!(result.unwrap_err().contains("turn"));
+    }
+
+    #[test]
+    fn colors_not_playable_are_refused() {
+        let board = board_with(Player::One, &[Tile::Red, Tile::Green]);
+        let state = BoardState::WaitingForMove(Player::One);
+        let result = check_move(
+            &board,
+            &GameSetup::default(),
+            &state,
+            &Player::One,
+            &Tile::Blue,
+        );
+        assert!(result.unwrap_err().contains("not playable"));
+    }
+
+    #[test]
+    fn passes_need_the_rule() {
+        let board = board_with(Player::One, &[]);
+        let state = BoardState::WaitingForMove(Player::One);
+        let mut setup = GameSetup::default();
+        setup.rules.allow_pass = false;
+        assert!(check_move(&board, &setup, &state, &Player::One, &Tile::Undef).is_err());
+        setup.rules.allow_pass = true;
+        assert_eq!(
+            check_move(&board, &setup, &state, &Player::One, &Tile::Undef),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn playable_colors_are_accepted() {
+        let board = board_with(Player::One, &[Tile::Red, Tile::Green]);
+        let state = BoardState::WaitingForMove(Player::One);
+        let result = check_move(
+            &board,
+            &GameSetup::default(),
+            &state,
+            &Player::One,
+            &Tile::Green,
+        );
+        assert_eq!(result, Ok(()));
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::chess_clock::ChessClockResource;
+use super::game_result::ResultTakenBack;
+use super::setup::Controller;
+use super::spectators::SpectatorsResource;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+pub struct MoveHistoryPlugin;
+
+impl Plugin for MoveHistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveHistoryResource>();
+        app.add_systems(PreUpdate, clear_history.run_if(on_event::<StartBoard>));
+    }
+}
+
+/// Everything needed to put the board back as it was before a move.
+#[derive(Clone)]
+pub struct BoardSnapshot {
+    card_to_tiles: HashMap<Entity, Tile>,
+    card_to_players: HashMap<Entity, Player>,
+    player_to_extra_moves: BTreeMap<Player, usize>,
+    player_to_color_ban_waivers: BTreeMap<Player, usize>,
+    player_to_previous_tiles: BTreeMap<Player, Tile>,
+    player_to_last_picks: BTreeMap<Player, Tile>,
+    num_consecutive_passes: usize,
+    num_resolved_moves: usize,
+    /// Time left on the clocks, only put back by an undo.
+    player_to_remaining: BTreeMap<Player, f32>,
+}
+
+impl BoardSnapshot {
+    pub fn card(&self, card: &Entity) -> (Tile, Player) {
+        (
+            self.card_to_tiles.get(card).unwrap().clone(),
+            self.card_to_players.get(card).unwrap().clone(),
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct MoveRecord {
+    pub player: Player,
+    pub tile: Tile,
+    pub prior: BoardSnapshot,
+}
+
+#[derive(Resource, Default)]
+pub struct MoveHistoryResource {
+    pub moves: Vec<MoveRecord>,
+    undone_moves: Vec<MoveRecord>,
+}
+
+fn clear_history(mut history: ResMut<MoveHistoryResource>) {
+    history.moves.clear();
+    history.undone_moves.clear();
+}
+
+pub fn take_snapshot(
+    ui_cards: &Query<(&UiCard, Entity)>,
+    ui_backs: &Query<&UiBack>,
+    board: &BoardResource,
+) -> BoardSnapshot {
+    let mut card_to_tiles = HashMap::new();
+    let mut card_to_players = HashMap::new();
+    for (ui_card, card) in ui_cards.iter() {
+        let back = board.card_to_backs.get(&card).unwrap();
+        card_to_tiles.insert(card, ui_card.tile.clone());
+        card_to_players.insert(card, ui_backs.get(*back).unwrap().player.clone());
+    }
+    BoardSnapshot {
+        card_to_tiles,
+        card_to_players,
+        player_to_extra_moves: board.player_to_extra_moves.clone(),
+        player_to_color_ban_waivers: board.player_to_color_ban_waivers.clone(),
+        player_to_previous_tiles: board.player_to_previous_tiles.clone(),
+        player_to_last_picks: board.player_to_last_picks.clone(),
+        num_consecutive_passes: board.num_consecutive_passes,
+        num_resolved_moves: board.num_resolved_moves,
+        player_to_remaining: BTreeMap::new(),
+    }
+}
+
+pub fn restore_snapshot(
+    ui_cards: &mut Query<(&mut UiCard, Entity)>,
+    ui_backs: &mut Query<&mut UiBack>,
+    board: &mut BoardResource,
+    snapshot: &BoardSnapshot,
+) {
+    for (mut ui_card, card) in ui_cards.iter_mut() {
+        let back = board.card_to_backs.get(&card).unwrap();
+        ui_card.tile = snapshot.card_to_tiles.get(&card).unwrap().clone();
+        ui_backs.get_mut(*back).unwrap().player =
+            snapshot.card_to_players.get(&card).unwrap().clone();
+    }
+    board.player_to_extra_moves = snapshot.player_to_extra_moves.clone();
+    board.player_to_color_ban_waivers = snapshot.player_to_color_ban_waivers.clone();
+    board.player_to_previous_tiles = snapshot.player_to_previous_tiles.clone();
+    board.player_to_last_picks = snapshot.player_to_last_picks.clone();
+    board.num_consecutive_passes = snapshot.num_consecutive_passes;
+    board.num_resolved_moves = snapshot.num_resolved_moves;
+}
+
+/// Push the move about to be played, along with the board before it.
+pub fn record_move(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    clocks: Res<ChessClockResource>,
+    state: Res<State<BoardState>>,
+    mut history: ResMut<MoveHistoryResource>,
+) {
+    let BoardState::PlayingMove(player, tile) = state.get() else {
+        return;
+    };
+
+    // replaying the next undone move keeps the rest of the redo stack
+    let is_redo = history
+        .undone_moves
+        .last()
+        .is_some_and(|record| record.player == *player && record.tile == *tile);
+    if is_redo {
+        history.undone_moves.pop();
+    } else {
+        history.undone_moves.clear();
+    }
+
+    let mut prior = take_snapshot(&ui_cards, &ui_backs, &board);
+    prior.player_to_remaining = clocks.player_to_remaining.clone();
+    history.moves.push(MoveRecord {
+        player: player.clone(),
+        tile: tile.clone(),
+        prior,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn undo_redo(
+    mut ui_cards: Query<(&mut UiCard, Entity)>,
+    mut ui_backs: Query<&mut UiBack>,
+    mut board: ResMut<BoardResource>,
+    mut history: ResMut<MoveHistoryResource>,
+    mut clocks: ResMut<ChessClockResource>,
+    mut taken_back: EventWriter<ResultTakenBack>,
+    setup: Res<GameSetup>,
+    spectators: Res<SpectatorsResource>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    // the other side of an online game can't take its moves back, nor can
+    // spectators or a correspondent
+    if setup.ranked || setup.is_online() || setup.is_correspondence() || spectators.is_publishing()
+    {
+        return;
+    }
+    let is_human = |player: &Player| setup.controller(player) == Controller::Human;
+    // the move that ended the game can be taken back too
+    let is_over = match state.get() {
+        BoardState::WaitingForMove(player) if is_human(player) => false,
+        BoardState::Victory(_) if is_human(&Player::One) || is_human(&Player::Two) => true,
+        _ => return,
+    };
+
+    if keyboard.just_pressed(KeyCode::KeyZ) {
+        // also take back the computer replies
+        let mut restored = None;
+        while let Some(record) = history.moves.pop() {
+            let is_human_move = is_human(&record.player);
+            restored = Some(record.clone());
+            history.undone_moves.push(record);
+            if is_human_move {
+                break;
+            }
+        }
+        if let Some(record) = restored {
+            info!("undo {:?} {:?}", record.player, record.tile);
+            restore_snapshot(&mut ui_cards, &mut ui_backs, &mut board, &record.prior);
+            if !record.prior.player_to_remaining.is_empty() {
+                clocks.player_to_remaining = record.prior.player_to_remaining.clone();
+                clocks.flagged_player = None;
+            }
+            if is_over {
+                taken_back.write(ResultTakenBack);
+            }
+            next_state.set(BoardState::WaitingForMove(record.player));
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::KeyY)
+        && let BoardState::WaitingForMove(player) = state.get()
+        && let Some(record) = history.undone_moves.last()
+        && record.player == *player
+    {
+        info!("redo {:?} {:?}", record.player, record.tile);
+        next_state.set(BoardState::PlayingMove(
+            record.player.clone(),
+            record.tile.clone(),
+        ));
+    }
+}
@@ -0,0 +1,1330 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::chess_clock::ChessClockResource;
+use super::game_record::GameRecord;
+use super::game_record::GameRecordResource;
+use super::game_record::parse_player_name;
+use super::game_record::player_name;
+use super::lobby::GameOffer;
+use super::move_check;
+use super::move_check::MoveSource;
+use super::move_check::RejectedMove;
+use super::profiles::parse_profile_name;
+use super::save_game::SavedGame;
+use super::setup::ClockSetup;
+use super::setup::Controller;
+use super::websocket;
+
+use std::collections::VecDeque;
+use std::hash::BuildHasher;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::time::Instant;
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkResource>();
+        app.add_systems(Startup, populate);
+        app.add_systems(OnEnter(BoardState::Victory(Player::One)), send_flag);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Two)), send_flag);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Undef)), send_flag);
+        app.add_systems(Update, animate_status);
+        app.add_systems(Last, say_goodbye.run_if(on_event::<AppExit>));
+    }
+}
+
+/// Bumped whenever a message changes, both sides must agree on it.
+pub const PROTOCOL_VERSION: u32 = 7;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest line read from another instance, a watched match fits well within.
+const MAX_LINE_LEN: u64 = 1 << 20;
+const MATCHMAKING_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long a dropped opponent has to come back before the game is given up.
+const RECONNECT_GRACE: Duration = Duration::from_secs(60);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What two game instances tell each other, one message per line:
+///
+/// ```text
+/// hello 1 alice
+/// start P2 3 - total=300,increment=5 0x9d2c5680e41b3f77 [Board "14x7"]\t[Colors "4"]\t...
+/// move R 0x3b9f6a2c81d4e057
+/// resume 12
+/// rejoin 0x9d2c5680e41b3f77
+/// flag
+/// bye boards differ after move 12
+/// watch [Version "1"]\t[MatchSeed "0xab28f3af"]\t...\t1. R B 2. G
+/// chat nice opening
+/// emote gg
+/// list
+/// open 3 14x7 4 1 standard - alice
+/// closed 3
+/// opened 3
+/// create 14x7 4 1 standard -
+/// join 3
+/// quick
+/// ```
+///
+/// The game record of `start` and the saved game of `watch` have their line
+/// breaks turned into tabs, `-` is an unset fog radius or clock. Each move
+/// carries the hash of the board it leads to. The token of `start` is what a
+/// dropped player gives back with `rejoin` to take its seat again. The last ones are spoken with a
+/// lobby before a game starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Hello {
+        version: u32,
+        /// Profile of the sender, if any.
+        name: Option<String>,
+    },
+    /// Sent by the host: the seat of the receiver and the game to play.
+    Start {
+        seat: Player,
+        num_games: usize,
+        fog_radius: Option<usize>,
+        clock: Option<ClockSetup>,
+        /// Secret of the match, only known to both players.
+        token: u64,
+        record: GameRecord,
+    },
+    /// A pick of the sender, `Tile::Undef` is a pass, and the hash of the
+    /// resulting board.
+    Move { tile: Tile, hash: u64 },
+    /// Sent again after a drop: the number of moves of the match the sender
+    /// knows of, the other side sends back the ones it missed.
+    Resume(usize),
+    /// Sent after the hello by a player dialing back after a drop, with the
+    /// token of the match.
+    Rejoin(u64),
+    /// The player to move ran out of time, sent by that player.
+    Flag,
+    /// Leaving, with the reason when something went wrong.
+    Bye(Option<String>),
+    /// Sent to a spectator: the match as it stands, moves follow.
+    Watch(Box<SavedGame>),
+    /// A line for the chat panel of the other player.
+    Chat(String),
+    /// One of the quick emotes, by its text.
+    Emote(String),
+    /// Ask the lobby for its open games, and to hear of every change.
+    List,
+    /// Sent by the lobby: a game waiting for a second player.
+    Open {
+        id: usize,
+        host: String,
+        offer: GameOffer,
+    },
+    /// Sent by the lobby: the game was taken or given up. Sent by the host of
+    /// an open game to give it up.
+    Closed(usize),
+    /// Sent by the lobby to the client whose game it opened.
+    Opened(usize),
+    /// Open a game in the lobby and wait for someone to join it.
+    Create(GameOffer),
+    /// Take an open game of the lobby.
+    Join(usize),
+    /// Take the oldest open game of the lobby, or open one.
+    QuickMatch,
+}
+
+impl std::fmt::Display for Message {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Hello { version, name } => match name {
+                Some(name) => write!(ff, "hello {version} {name}"),
+                None => write!(ff, "hello {version}"),
+            },
+            Self::Start {
+                seat,
+                num_games,
+                fog_radius,
+                clock,
+                token,
+                record,
+            } => {
+                let fog_radius = match fog_radius {
+                    Some(radius) => radius.to_string(),
+                    None => "-".into(),
+                };
+                let clock = match clock {
+                    Some(clock) => clock.to_string(),
+                    None => "-".into(),
+                };
+                let record = record.to_string().trim_end().replace('\n', "\t");
+                write!(
+                    ff,
+                    "start {} {} {} {} {:#018x} {}",
+                    player_name(seat),
+                    num_games,
+                    fog_radius,
+                    clock,
+                    token,
+                    record
+                )
+            }
+            Self::Move { tile, hash } => write!(ff, "move {} {:#018x}", tile.to_letter(), hash),
+            Self::Resume(num_moves) => write!(ff, "resume {num_moves}"),
+            Self::Rejoin(token) => write!(ff, "rejoin {token:#018x}"),
+            Self::Flag => write!(ff, "flag"),
+            Self::Bye(None) => write!(ff, "bye"),
+            Self::Bye(Some(reason)) => write!(ff, "bye {reason}"),
+            Self::Watch(saved) => {
+                write!(
+                    ff,
+                    "watch {}",
+                    saved.to_string().trim_end().replace('\n', "\t")
+                )
+            }
+            // a line break would end the message early
+            Self::Chat(text) => write!(ff, "chat {}", text.replace(['\n', '\r'], " ")),
+            Self::Emote(text) => write!(ff, "emote {}", text.replace(['\n', '\r'], " ")),
+            Self::List => write!(ff, "list"),
+            Self::Open { id, host, offer } => write!(ff, "open {id} {offer} {host}"),
+            Self::Closed(id) => write!(ff, "closed {id}"),
+            Self::Opened(id) => write!(ff, "opened {id}"),
+            Self::Create(offer) => write!(ff, "create {offer}"),
+            Self::Join(id) => write!(ff, "join {id}"),
+            Self::QuickMatch => write!(ff, "quick"),
+        }
+    }
+}
+
+impl std::str::FromStr for Message {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = line
+            .trim_end()
+            .split_once(' ')
+            .unwrap_or((line.trim_end(), ""));
+        match kind {
+            "hello" => {
+                let (version, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                let version = version
+                    .parse()
+                    .map_err(|err| format!("invalid protocol version {version:?}: {err}"))?;
+                let name = Some(name.trim().to_string()).filter(|name| !name.is_empty());
+                Ok(Self::Hello { version, name })
+            }
+            "start" => {
+                let parts: Vec<&str> = rest.splitn(6, ' ').collect();
+                let [seat, num_games, fog_radius, clock, token, record] = parts[..] else {
+                    return Err(format!("incomplete start {rest:?}"));
+                };
+                let num_games = num_games
+                    .parse()
+                    .map_err(|err| format!("invalid number of games {num_games:?}: {err}"))?;
+                let fog_radius = match fog_radius {
+                    "-" => None,
+                    radius => Some(
+                        radius
+                            .parse()
+                            .map_err(|err| format!("invalid fog radius {radius:?}: {err}"))?,
+                    ),
+                };
+                Ok(Self::Start {
+                    seat: parse_player_name(seat)?,
+                    num_games,
+                    fog_radius,
+                    clock: match clock {
+                        "-" => None,
+                        clock => Some(clock.parse()?),
+                    },
+                    token: parse_token(token)?,
+                    record: record.replace('\t', "\n").parse()?,
+                })
+            }
+            "move" => {
+                let (letter, hash) = rest.split_once(' ').unwrap_or((rest, ""));
+                Ok(Self::Move {
+                    tile: parse_tile(letter)?,
+                    hash: parse_hash(hash)?,
+                })
+            }
+            "resume" => {
+                Ok(Self::Resume(rest.parse().map_err(|err| {
+                    format!("invalid number of moves {rest:?}: {err}")
+                })?))
+            }
+            "rejoin" => Ok(Self::Rejoin(parse_token(rest)?)),
+            "flag" => Ok(Self::Flag),
+            "bye" => Ok(Self::Bye(
+                Some(rest.trim().to_string()).filter(|reason| !reason.is_empty()),
+            )),
+            "watch" => Ok(Self::Watch(Box::new(rest.replace('\t', "\n").parse()?))),
+            "chat" => Ok(Self::Chat(rest.to_string())),
+            "emote" => Ok(Self::Emote(rest.to_string())),
+            "list" => Ok(Self::List),
+            "open" => {
+                let parts: Vec<&str> = rest.splitn(7, ' ').collect();
+                let [id, _, _, _, _, _, host] = parts[..] else {
+                    return Err(format!("incomplete open game {rest:?}"));
+                };
+                Ok(Self::Open {
+                    id: parse_game_id(id)?,
+                    host: host.trim().to_string(),
+                    offer: parts[1..6].join(" ").parse()?,
+                })
+            }
+            "closed" => Ok(Self::Closed(parse_game_id(rest)?)),
+            "opened" => Ok(Self::Opened(parse_game_id(rest)?)),
+            "create" => Ok(Self::Create(rest.parse()?)),
+            "join" => Ok(Self::Join(parse_game_id(rest)?)),
+            "quick" => Ok(Self::QuickMatch),
+            _ => Err(format!("unknown message {kind:?}")),
+        }
+    }
+}
+
+type Outgoing = Box<dyn Fn(&Message) -> Result<(), String> + Send + Sync>;
+
+pub fn parse_tile(letter: &str) -> Result<Tile, String> {
+    let mut letters = letter.chars();
+    match (letters.next(), letters.next()) {
+        (Some(letter), None) => Tile::from_letter(letter),
+        _ => None,
+    }
+    .ok_or_else(|| format!("invalid move {letter:?}"))
+}
+
+pub fn parse_game_id(id: &str) -> Result<usize, String> {
+    id.trim()
+        .parse()
+        .map_err(|err| format!("invalid game id {id:?}: {err}"))
+}
+
+pub fn parse_hash(hash: &str) -> Result<u64, String> {
+    let digits = hash.strip_prefix("0x").unwrap_or(hash);
+    u64::from_str_radix(digits, 16).map_err(|err| format!("invalid board hash {hash:?}: {err}"))
+}
+
+pub fn parse_token(token: &str) -> Result<u64, String> {
+    let digits = token.strip_prefix("0x").unwrap_or(token);
+    u64::from_str_radix(digits, 16).map_err(|err| format!("invalid match token {token:?}: {err}"))
+}
+
+/// A token for a new match. The hashers of std are keyed at random, so that
+/// hashing the time gives a number the other side can't guess.
+pub fn match_token() -> u64 {
+    std::collections::hash_map::RandomState::new().hash_one(std::time::SystemTime::now())
+}
+
+/// FNV-1a hash of every tile and owner, row by row, for both sides to check
+/// they still have the same board.
+pub fn board_hash(
+    ui_cards: &Query<(&UiCard, Entity)>,
+    ui_backs: &Query<&UiBack>,
+    board: &BoardResource,
+) -> u64 {
+    let mut cells: Vec<(usize, usize, char, usize)> = ui_cards
+        .iter()
+        .map(|(ui_card, card)| {
+            let back = board.card_to_backs.get(&card).unwrap();
+            let owner: usize = ui_backs.get(*back).unwrap().player.clone().into();
+            (ui_card.row, ui_card.column, ui_card.tile.to_letter(), owner)
+        })
+        .collect();
+    cells.sort();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (_, _, letter, owner) in cells {
+        for byte in [letter as u8, owner as u8] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// A socket to the other instance, read on its own thread so that the game
+/// never waits on the network. Native instances talk in lines, browsers in
+/// WebSocket frames.
+pub struct Connection {
+    outgoing: Outgoing,
+    incoming: Mutex<mpsc::Receiver<Result<Message, String>>>,
+    pub peer: String,
+}
+
+fn peer_name(stream: &TcpStream) -> String {
+    stream
+        .peer_addr()
+        .map(|address| address.to_string())
+        .unwrap_or("??".into())
+}
+
+/// Read one line without its end of line, `None` once the peer is gone. A
+/// line that doesn't end within `MAX_LINE_LEN` is refused, not buffered.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    let num_bytes = reader
+        .take(MAX_LINE_LEN)
+        .read_line(&mut line)
+        .map_err(|err| err.to_string())?;
+    if num_bytes == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && num_bytes as u64 == MAX_LINE_LEN {
+        return Err(format!("line longer than {MAX_LINE_LEN} bytes"));
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+impl Connection {
+    /// Tell apart a browser, whose first bytes are an HTTP upgrade request,
+    /// from another instance.
+    pub fn accept(stream: TcpStream) -> Result<Self, String> {
+        let peer = peer_name(&stream);
+        let timeout_err = |err: std::io::Error| format!("can't read from {peer}: {err}");
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(timeout_err)?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut first_bytes = [0u8; 4];
+        let mut num_bytes = 0;
+        while num_bytes < first_bytes.len() {
+            let num_peeked = stream.peek(&mut first_bytes).map_err(timeout_err)?;
+            if num_peeked == num_bytes {
+                // nothing new yet, or the peer is gone
+                if num_peeked == 0 {
+                    return Err(format!("{peer} closed the connection"));
+                }
+                if Instant::now() > deadline {
+                    return Err(format!("{peer} stalled before saying anything"));
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            num_bytes = num_peeked;
+        }
+        stream.set_read_timeout(None).map_err(timeout_err)?;
+        if &first_bytes == b"GET " {
+            Self::websocket(stream)
+        } else {
+            Self::lines(stream)
+        }
+    }
+
+    pub fn lines(stream: TcpStream) -> Result<Self, String> {
+        let peer = peer_name(&stream);
+        let reader = stream
+            .try_clone()
+            .map_err(|err| format!("can't read from {peer}: {err}"))?;
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut reader = std::io::BufReader::new(reader);
+            loop {
+                let message = match read_line(&mut reader) {
+                    Ok(Some(line)) => line.parse(),
+                    Ok(None) => Err("connection closed".into()),
+                    Err(err) => Err(err),
+                };
+                let is_err = message.is_err();
+                if sender.send(message).is_err() || is_err {
+                    return;
+                }
+            }
+        });
+        let writer_peer = peer.clone();
+        Ok(Self {
+            outgoing: Box::new(move |message| {
+                (&stream)
+                    .write_all(format!("{message}\n").as_bytes())
+                    .map_err(|err| format!("can't send to {writer_peer}: {err}"))
+            }),
+            incoming: Mutex::new(receiver),
+            peer,
+        })
+    }
+
+    /// Answer the upgrade request of a browser, then exchange JSON messages.
+    pub fn websocket(stream: TcpStream) -> Result<Self, String> {
+        let peer = peer_name(&stream);
+        let mut reader = stream
+            .try_clone()
+            .map_err(|err| format!("can't read from {peer}: {err}"))?;
+        websocket::accept(&mut reader, &mut &stream)?;
+        let writer = Arc::new(Mutex::new(stream));
+
+        let (sender, receiver) = mpsc::channel();
+        let reply_writer = writer.clone();
+        std::thread::spawn(move || {
+            websocket::read_messages(
+                &mut reader,
+                |opcode, payload| {
+                    let _ =
+                        websocket::write_frame(&mut *reply_writer.lock().unwrap(), opcode, payload);
+                },
+                |message| sender.send(message).is_ok(),
+            );
+        });
+        let writer_peer = peer.clone();
+        Ok(Self {
+            outgoing: Box::new(move |message| {
+                websocket::write_frame(
+                    &mut *writer.lock().unwrap(),
+                    websocket::OPCODE_TEXT,
+                    message.to_json().as_bytes(),
+                )
+                .map_err(|err| format!("can't send to {writer_peer}: {err}"))
+            }),
+            incoming: Mutex::new(receiver),
+            peer,
+        })
+    }
+
+    /// Socket of the browser, opened by `websocket::browser::connect`.
+    #[cfg(target_family = "wasm")]
+    pub fn browser(incoming: mpsc::Receiver<Result<Message, String>>, peer: String) -> Self {
+        Self {
+            outgoing: Box::new(websocket::browser::send),
+            incoming: Mutex::new(incoming),
+            peer,
+        }
+    }
+
+    pub fn send(&self, message: &Message) -> Result<(), String> {
+        (self.outgoing)(message)
+    }
+
+    /// Wait for the next message, only while setting up the game.
+    pub fn receive(&self, timeout: Duration) -> Result<Message, String> {
+        match self.incoming.lock().unwrap().recv_timeout(timeout) {
+            Ok(message) => message,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(format!("{} isn't answering", self.peer)),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err("connection closed".into()),
+        }
+    }
+
+    /// Messages received since the last call, without waiting.
+    pub fn poll(&self) -> Vec<Result<Message, String>> {
+        self.incoming.lock().unwrap().try_iter().collect()
+    }
+}
+
+/// Exchange `hello` and check both sides speak the same protocol, giving back
+/// the name of the other player.
+pub fn handshake(connection: &Connection, name: Option<String>) -> Result<Option<String>, String> {
+    connection.send(&Message::Hello {
+        version: PROTOCOL_VERSION,
+        name,
+    })?;
+    check_hello(connection, connection.receive(HANDSHAKE_TIMEOUT)?)
+}
+
+fn check_hello(connection: &Connection, message: Message) -> Result<Option<String>, String> {
+    match message {
+        // the name ends up in profiles and game records, it gets the same check
+        // as one given on the command line
+        Message::Hello { version, name } if version == PROTOCOL_VERSION => name
+            .map(|name| parse_profile_name(&name))
+            .transpose()
+            .map_err(|err| format!("{} sent a bad name: {}", connection.peer, err)),
+        Message::Hello { version, .. } => Err(format!(
+            "{} speaks protocol {}, expected {}",
+            connection.peer, version, PROTOCOL_VERSION
+        )),
+        message => Err(format!("expected hello, got {message}")),
+    }
+}
+
+pub fn opponent(player: &Player) -> Player {
+    match player {
+        Player::One => Player::Two,
+        Player::Two => Player::One,
+        Player::Undef => unreachable!(),
+    }
+}
+
+/// Seat the local player, whose profile is the first one given, against the
+/// remote one, leaving out the options that only make sense on one machine.
+fn make_online(setup: &mut GameSetup, local_player: &Player, remote_name: Option<String>) {
+    let local_name = setup.player_one_profile.take();
+    (
+        setup.player_one,
+        setup.player_two,
+        setup.player_one_profile,
+        setup.player_two_profile,
+    ) = match local_player {
+        Player::One => (
+            Controller::Human,
+            Controller::Remote,
+            local_name,
+            remote_name,
+        ),
+        _ => (
+            Controller::Remote,
+            Controller::Human,
+            remote_name,
+            local_name,
+        ),
+    };
+    setup.clock = None;
+    setup.save_path = None;
+}
+
+/// Wait for an opponent on the given port, the host plays the first seat and
+/// deals the board. Both other instances and browsers may connect.
+pub fn host(setup: &mut GameSetup, port: u16) -> Result<NetworkResource, String> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|err| format!("can't listen on {port}: {err}"))?;
+    eprintln!("waiting for an opponent on port {port}");
+    let (stream, _) = listener
+        .accept()
+        .map_err(|err| format!("can't accept an opponent: {err}"))?;
+    let connection = Connection::accept(stream)?;
+    let remote_name = handshake(&connection, setup.player_one_profile.clone())?;
+
+    make_online(setup, &Player::One, remote_name);
+    let token = match_token();
+    connection.send(&Message::Start {
+        seat: Player::Two,
+        num_games: setup.num_games,
+        fog_radius: setup.fog_radius,
+        clock: setup.clock,
+        token,
+        record: GameRecord::from_setup(setup),
+    })?;
+    eprintln!("playing against {}", connection.peer);
+    let local_name = setup.player_one_profile.clone();
+    Ok(NetworkResource::new(connection).with_rejoin(Rejoin::Accept(listener), local_name, token))
+}
+
+/// Connect to a host and take the seat and board it hands out.
+pub fn join(setup: &mut GameSetup, address: &str) -> Result<NetworkResource, String> {
+    let stream =
+        TcpStream::connect(address).map_err(|err| format!("can't connect to {address}: {err}"))?;
+    let connection = Connection::lines(stream)?;
+    let local_name = setup.player_one_profile.clone();
+    let remote_name = handshake(&connection, local_name.clone())?;
+    // a dedicated server only starts the game once it found an opponent
+    eprintln!("waiting for the game to start");
+    let start = connection.receive(MATCHMAKING_TIMEOUT)?;
+    let network = take_seat(setup, connection, remote_name, start)?;
+    let token = network.token;
+    Ok(network.with_rejoin(Rejoin::Dial(address.into()), local_name, token))
+}
+
+/// Join from the web build, the host address being given in the page address
+/// as `?join=ws://host:port`.
+#[cfg(target_family = "wasm")]
+pub async fn join_from_browser(
+    setup: &mut GameSetup,
+    address: &str,
+) -> Result<NetworkResource, String> {
+    let incoming = websocket::browser::connect(address).await?;
+    let connection = Connection::browser(incoming, address.to_string());
+    connection.send(&Message::Hello {
+        version: PROTOCOL_VERSION,
+        name: setup.player_one_profile.clone(),
+    })?;
+
+    // the browser can't block, look for the answers of the host now and then
+    let mut messages = VecDeque::new();
+    let mut waited = Duration::ZERO;
+    while messages.len() < 2
+        && !messages
+            .iter()
+            .any(|message: &Result<Message, String>| message.is_err())
+    {
+        // a dedicated server only starts the game once it found an opponent
+        let timeout = if messages.is_empty() {
+            HANDSHAKE_TIMEOUT
+        } else {
+            MATCHMAKING_TIMEOUT
+        };
+        if waited > timeout {
+            return Err(format!("{address} isn't answering"));
+        }
+        websocket::browser::sleep(50).await;
+        waited += Duration::from_millis(50);
+        messages.extend(connection.poll());
+    }
+    let hello = messages.pop_front().ok_or("connection closed")??;
+    let start = messages.pop_front().ok_or("connection closed")??;
+    let remote_name = check_hello(&connection, hello)?;
+    let mut network = take_seat(setup, connection, remote_name, start)?;
+    for message in messages.into_iter().flatten() {
+        network.queue_move(message);
+    }
+    Ok(network)
+}
+
+/// Follow the match published at this address, from where it stands.
+pub fn watch(setup: &mut GameSetup, address: &str) -> Result<NetworkResource, String> {
+    let stream =
+        TcpStream::connect(address).map_err(|err| format!("can't connect to {address}: {err}"))?;
+    let connection = Connection::lines(stream)?;
+    handshake(&connection, setup.player_one_profile.clone())?;
+    let mut saved = match connection.receive(HANDSHAKE_TIMEOUT)? {
+        Message::Watch(saved) => saved,
+        Message::Bye(_) => return Err("the match is over".into()),
+        message => return Err(format!("expected the match, got {message}")),
+    };
+
+    // flag falls come from the publisher, the clocks aren't run here
+    saved.clock = None;
+    saved.player_to_remaining.clear();
+    saved.apply_to(setup);
+    setup.replay_source = Some(MoveSource::Peer(connection.peer.clone()));
+    setup.player_one = Controller::Remote;
+    setup.player_two = Controller::Remote;
+    setup.clock = None;
+    setup.save_path = None;
+    eprintln!(
+        "watching board {} of {} from {}",
+        saved.num_played_games + 1,
+        saved.num_games,
+        connection.peer
+    );
+    let mut network = NetworkResource::new(connection);
+    network.status = format!("watching {}", address);
+    Ok(network)
+}
+
+/// Set up the game the host sent.
+pub fn take_seat(
+    setup: &mut GameSetup,
+    connection: Connection,
+    remote_name: Option<String>,
+    start: Message,
+) -> Result<NetworkResource, String> {
+    let Message::Start {
+        seat,
+        num_games,
+        fog_radius,
+        clock,
+        token,
+        record,
+    } = start
+    else {
+        return Err("expected the game to start".into());
+    };
+    if seat == Player::Undef {
+        return Err("no seat given".into());
+    }
+    record.apply_to(setup, None);
+    setup.replay_source = Some(MoveSource::Peer(connection.peer.clone()));
+    setup.num_games = num_games;
+    setup.fog_radius = fog_radius;
+    make_online(setup, &seat, remote_name);
+    setup.clock = clock;
+    eprintln!("playing {} against {}", player_name(&seat), connection.peer);
+    Ok(NetworkResource {
+        token,
+        ..NetworkResource::new(connection)
+    })
+}
+
+/// How a dropped connection comes back.
+enum Rejoin {
+    /// The host listens again on its port.
+    Accept(TcpListener),
+    /// The other side dials the host again.
+    Dial(String),
+}
+
+type Reconnection = Mutex<mpsc::Receiver<Result<(Connection, Rejoin), String>>>;
+
+/// Try to get the other side back on a thread of its own until the grace
+/// period is over, only the player who knows the token of the match is let in
+/// again.
+fn reconnect(rejoin: Rejoin, local_name: Option<String>, token: u64) -> Reconnection {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let deadline = Instant::now() + RECONNECT_GRACE;
+        if let Rejoin::Accept(listener) = &rejoin
+            && let Err(err) = listener.set_nonblocking(true)
+        {
+            let _ = sender.send(Err(format!("can't listen again: {err}")));
+            return;
+        }
+        while Instant::now() < deadline {
+            let connection = match &rejoin {
+                Rejoin::Accept(listener) => match listener.accept() {
+                    Ok((stream, _)) => stream
+                        .set_nonblocking(false)
+                        .map_err(|err| err.to_string())
+                        .and_then(|()| Connection::accept(stream)),
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(100));
+                        continue;
+                    }
+                    Err(err) => Err(err.to_string()),
+                },
+                Rejoin::Dial(address) => TcpStream::connect(address)
+                    .map_err(|err| err.to_string())
+                    .and_then(Connection::lines),
+            };
+            let connection = connection.and_then(|connection| {
+                handshake(&connection, local_name.clone())?;
+                match &rejoin {
+                    Rejoin::Dial(_) => connection.send(&Message::Rejoin(token))?,
+                    Rejoin::Accept(_) => {
+                        if connection.receive(HANDSHAKE_TIMEOUT)? != Message::Rejoin(token) {
+                            let _ = connection.send(&Message::Bye(Some("not in this game".into())));
+                            return Err(format!("{} is not in this game", connection.peer));
+                        }
+                    }
+                }
+                Ok(connection)
+            });
+            match connection {
+                Ok(connection) => {
+                    let _ = sender.send(Ok((connection, rejoin)));
+                    return;
+                }
+                Err(err) => {
+                    warn!("can't reconnect: {}", err);
+                    std::thread::sleep(RECONNECT_INTERVAL);
+                }
+            }
+        }
+        let _ = sender.send(Err(format!(
+            "gone for more than {} seconds",
+            RECONNECT_GRACE.as_secs()
+        )));
+    });
+    Mutex::new(receiver)
+}
+
+#[derive(Resource, Default)]
+pub struct NetworkResource {
+    connection: Option<Connection>,
+    /// Set for players, spectators and browsers don't come back after a drop.
+    rejoin: Option<Rejoin>,
+    reconnection: Option<Reconnection>,
+    local_name: Option<String>,
+    /// Token of the match, from the `start` message.
+    token: u64,
+    /// Every move of the match in the order played, to send again what the
+    /// other side missed while it was away.
+    log: Vec<Message>,
+    /// Moves and flag falls received before the turn of the remote seat came
+    /// up locally.
+    pending_moves: VecDeque<Message>,
+    /// Hash the board must have once the remote move being played resolves.
+    expected_hash: Option<u64>,
+    /// The boards differ, nothing more can be played.
+    halted: bool,
+    status: String,
+    /// Chat lines and emotes received, for the chat panel.
+    chat: VecDeque<Message>,
+}
+
+impl NetworkResource {
+    pub fn new(connection: Connection) -> Self {
+        let status = format!("online with {}", connection.peer);
+        Self {
+            connection: Some(connection),
+            status,
+            ..default()
+        }
+    }
+
+    fn with_rejoin(mut self, rejoin: Rejoin, local_name: Option<String>, token: u64) -> Self {
+        self.rejoin = Some(rejoin);
+        self.local_name = local_name;
+        self.token = token;
+        self
+    }
+
+    fn disconnect(&mut self, reason: &str) {
+        self.rejoin = None;
+        self.reconnection = None;
+        if let Some(connection) = self.connection.take() {
+            error!("{}: {}", connection.peer, reason);
+            self.status = format!("{} left: {}", connection.peer, reason);
+        } else {
+            error!("{}", reason);
+            self.status = format!("offline: {}", reason);
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Chat lines and emotes are only sent while connected, they aren't
+    /// sent again after a drop.
+    pub fn send_chat(&mut self, message: Message) {
+        let sent = match &self.connection {
+            Some(connection) => connection.send(&message),
+            None => return,
+        };
+        if let Err(err) = sent {
+            self.drop_connection(&err);
+        }
+    }
+
+    pub fn take_chat(&mut self) -> Vec<Message> {
+        self.chat.drain(..).collect()
+    }
+
+    /// Take a move that came along with the start of the game.
+    pub fn queue_move(&mut self, message: Message) {
+        if let Message::Move { .. } = message {
+            self.log.push(message.clone());
+            self.pending_moves.push_back(message);
+        }
+    }
+
+    /// Lost the connection, wait for the other side to come back if it may.
+    fn drop_connection(&mut self, reason: &str) {
+        let Some(rejoin) = self.rejoin.take() else {
+            self.disconnect(reason);
+            return;
+        };
+        let peer = self
+            .connection
+            .take()
+            .map(|connection| connection.peer)
+            .unwrap_or("??".into());
+        warn!("{} dropped: {}", peer, reason);
+        self.status = format!(
+            "{} dropped, waiting {} seconds for them",
+            peer,
+            RECONNECT_GRACE.as_secs()
+        );
+        self.reconnection = Some(reconnect(rejoin, self.local_name.clone(), self.token));
+    }
+
+    /// Stop the game for good, telling the other side why.
+    fn halt(&mut self, reason: String) {
+        if let Some(connection) = &self.connection {
+            let _ = connection.send(&Message::Bye(Some(reason.clone())));
+        }
+        self.halted = true;
+        self.disconnect(&reason);
+    }
+
+    /// Refuse a move of the other side and stop there, it is recorded with
+    /// the address it came from.
+    fn reject(
+        &mut self,
+        setup: &GameSetup,
+        board: &BoardResource,
+        player: &Player,
+        tile: &Tile,
+        reason: String,
+    ) {
+        let peer = match &self.connection {
+            Some(connection) => connection.peer.clone(),
+            None => "unknown".into(),
+        };
+        let rejected = RejectedMove::new(MoveSource::Peer(peer), board, player, tile, &reason);
+        move_check::record_rejected_move(setup, &rejected);
+        self.halt(reason);
+    }
+
+    fn send_move(&mut self, message: Message) {
+        self.log.push(message.clone());
+        let sent = match &self.connection {
+            Some(connection) => connection.send(&message),
+            // sent again once the other side is back
+            None => return,
+        };
+        if let Err(err) = sent {
+            self.drop_connection(&err);
+        }
+    }
+
+    /// Pick up the connection once it is back and catch up on both sides.
+    fn poll_reconnection(&mut self) {
+        let received = match &self.reconnection {
+            Some(reconnection) => match reconnection.lock().unwrap().try_recv() {
+                Ok(received) => received,
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => Err("reconnection failed".into()),
+            },
+            None => return,
+        };
+        self.reconnection = None;
+        match received {
+            Ok((connection, rejoin)) => {
+                info!("{} is back", connection.peer);
+                self.status = format!("online with {}", connection.peer);
+                self.rejoin = Some(rejoin);
+                let sent = connection.send(&Message::Resume(self.log.len()));
+                self.connection = Some(connection);
+                if let Err(err) = sent {
+                    self.drop_connection(&err);
+                }
+            }
+            Err(err) => self.disconnect(&err),
+        }
+    }
+
+    /// Send the moves the other side doesn't know of, only ever local ones.
+    fn resume(&mut self, num_moves: usize) {
+        // the other side is ahead, it sends what was missed here
+        if num_moves >= self.log.len() {
+            return;
+        }
+        info!("resending {} moves", self.log.len() - num_moves);
+        let sent = match &self.connection {
+            Some(connection) => self.log[num_moves..]
+                .iter()
+                .try_for_each(|message| connection.send(message)),
+            None => return,
+        };
+        if let Err(err) = sent {
+            self.drop_connection(&err);
+        }
+    }
+}
+
+/// Nothing can be played once the boards of both sides differ.
+pub fn is_not_halted(network: Res<NetworkResource>) -> bool {
+    !network.halted
+}
+
+/// Send the pick of the local player once it is resolved, along with the
+/// resulting board.
+pub fn send_move(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut network: ResMut<NetworkResource>,
+) {
+    let BoardState::ResolvingMove(player) = state.get() else {
+        return;
+    };
+    if setup.controller(player) == Controller::Remote || network.halted {
+        return;
+    }
+    if network.connection.is_none() && network.reconnection.is_none() {
+        return;
+    }
+    let tile = board.player_to_last_picks.get(player).unwrap().clone();
+    let hash = board_hash(&ui_cards, &ui_backs, &board);
+    network.send_move(Message::Move { tile, hash });
+}
+
+/// Once a remote move is resolved, check both boards are the same.
+pub fn check_move(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut network: ResMut<NetworkResource>,
+) {
+    let BoardState::ResolvingMove(player) = state.get() else {
+        return;
+    };
+    if setup.controller(player) != Controller::Remote {
+        return;
+    }
+    let Some(expected_hash) = network.expected_hash.take() else {
+        return;
+    };
+    let hash = board_hash(&ui_cards, &ui_backs, &board);
+    if hash != expected_hash {
+        let reason = format!(
+            "boards differ after move {}, {:#018x} here and {:#018x} there",
+            board.num_resolved_moves + 1,
+            hash,
+            expected_hash
+        );
+        let tile = board.player_to_last_picks.get(player).unwrap().clone();
+        network.reject(&setup, &board, player, &tile, reason);
+    }
+}
+
+/// Take the picks of the other instance and play them through the regular
+/// move path once it is the turn of a remote seat, spectators have both. A
+/// pick out of turn or not playable stops the game and is recorded.
+#[allow(clippy::too_many_arguments)]
+pub fn receive_moves(
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    records: Res<GameRecordResource>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    mut clocks: ResMut<ChessClockResource>,
+    mut network: ResMut<NetworkResource>,
+) {
+    network.poll_reconnection();
+    let messages = match &network.connection {
+        Some(connection) => connection.poll(),
+        None => vec![],
+    };
+    for message in messages {
+        match message {
+            Ok(message @ Message::Move { .. }) => {
+                network.log.push(message.clone());
+                network.pending_moves.push_back(message);
+            }
+            Ok(Message::Flag) => {
+                network.log.push(Message::Flag);
+                network.pending_moves.push_back(Message::Flag);
+            }
+            Ok(Message::Resume(num_moves)) => network.resume(num_moves),
+            Ok(message @ (Message::Chat(_) | Message::Emote(_))) => network.chat.push_back(message),
+            Ok(Message::Bye(Some(reason))) => {
+                network.halted = true;
+                network.disconnect(&reason);
+            }
+            Ok(Message::Bye(None)) if setup.is_spectating() => {
+                network.disconnect("the match is closed")
+            }
+            Ok(Message::Bye(None)) => network.disconnect("the opponent quit"),
+            Ok(message) => network.disconnect(&format!("unexpected message {message}")),
+            Err(err) => network.drop_connection(&err),
+        }
+        if network.connection.is_none() {
+            break;
+        }
+    }
+
+    // the moves played before joining come first
+    if !records.replay_moves.is_empty() || network.halted {
+        return;
+    }
+    let BoardState::WaitingForMove(player) = state.get() else {
+        return;
+    };
+    if setup.controller(player) != Controller::Remote {
+        // the other side only moves once told about the local one
+        if let Some(Message::Move { tile, .. }) = network.pending_moves.front().cloned()
+            && let Err(reason) =
+                move_check::check_move(&board, &setup, state.get(), &opponent(player), &tile)
+        {
+            network.reject(&setup, &board, &opponent(player), &tile, reason);
+        }
+        return;
+    }
+    let (tile, hash) = match network.pending_moves.pop_front() {
+        Some(Message::Move { tile, hash }) => (tile, hash),
+        Some(Message::Flag) => {
+            info!("flag fall {:?}", player);
+            clocks.flagged_player = Some(player.clone());
+            next_state.set(BoardState::Victory(opponent(player)));
+            return;
+        }
+        _ => return,
+    };
+    if let Err(reason) = move_check::check_move(&board, &setup, state.get(), player, &tile) {
+        network.reject(&setup, &board, player, &tile, reason);
+        return;
+    }
+    network.expected_hash = Some(hash);
+    next_state.set(BoardState::PlayingMove(player.clone(), tile));
+}
+
+/// A local flag fall ends the board in place of a move.
+fn send_flag(
+    setup: Res<GameSetup>,
+    clocks: Res<ChessClockResource>,
+    mut network: ResMut<NetworkResource>,
+) {
+    let Some(player) = &clocks.flagged_player else {
+        return;
+    };
+    if setup.is_online() && setup.controller(player) != Controller::Remote {
+        network.send_move(Message::Flag);
+    }
+}
+
+fn say_goodbye(network: Res<NetworkResource>) {
+    if let Some(connection) = &network.connection {
+        let _ = connection.send(&Message::Bye(None));
+    }
+}
+
+#[derive(Component)]
+struct NetworkText;
+
+const NETWORK_FG_COLOR: Srgba = GRAY_100;
+const NETWORK_ERROR_COLOR: Srgba = RED_400;
+
+fn populate(mut commands: Commands, network: Res<NetworkResource>, setup: Res<GameSetup>) {
+    // a game of the lobby only gets its connection later
+    if network.connection.is_none() && !setup.is_online() {
+        return;
+    }
+    let mut frame = commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        },
+        ZIndex(1),
+    ));
+    frame.with_child((
+        NetworkText,
+        TextColor(NETWORK_FG_COLOR.into()),
+        Text::new(""),
+    ));
+}
+
+fn animate_status(
+    network_text: Option<Single<(&mut Text, &mut TextColor), With<NetworkText>>>,
+    network: Res<NetworkResource>,
+) {
+    let Some(mut network_text) = network_text else {
+        return;
+    };
+    let (network_text, network_text_color) = &mut *network_text;
+    if network.is_changed() {
+        **network_text = network.status.clone().into();
+    }
+    if network.halted {
+        network_text_color.0 = NETWORK_ERROR_COLOR.into();
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// One of every message, with the fields that need escaping.
+    pub fn every_message() -> Vec<Message> {
+        let setup = GameSetup {
+            seed: 0x5f3a9c2e81d4b706,
+            rules: "allow-pass".parse().unwrap(),
+            replay_moves: vec![Tile::Red, Tile::Undef, Tile::Blue],
+            ..GameSetup::default()
+        };
+        let record = GameRecord::from_setup(&setup);
+        let saved: SavedGame = format!(
+            "[Version \"1\"]\n[NumGames \"3\"]\n[MatchWins \"1-0\"]\n[MatchCounts \"40-38\"]\n{record}"
+        )
+            .parse()
+            .unwrap();
+        let offer = GameOffer {
+            num_games: 3,
+            clock: Some(ClockSetup::PerMove { seconds: 10.0 }),
+            ..GameOffer::default()
+        };
+        vec![
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                name: Some("alice".into()),
+            },
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                name: None,
+            },
+            Message::Start {
+                seat: Player::Two,
+                num_games: 3,
+                fog_radius: Some(2),
+                clock: Some(ClockSetup::Fischer {
+                    total: 300.0,
+                    increment: 5.0,
+                }),
+                token: 0x9d2c5680e41b3f77,
+                record: record.clone(),
+            },
+            Message::Start {
+                seat: Player::One,
+                num_games: 1,
+                fog_radius: None,
+                clock: None,
+                token: 0,
+                record,
+            },
+            Message::Move {
+                tile: Tile::Green,
+                hash: 0x0123456789abcdef,
+            },
+            Message::Move {
+                tile: Tile::Undef,
+                hash: 0,
+            },
+            Message::Resume(7),
+            Message::Rejoin(0x9d2c5680e41b3f77),
+            Message::Flag,
+            Message::Bye(None),
+            Message::Bye(Some("boards differ after move 3".into())),
+            Message::Watch(Box::new(saved)),
+            Message::Chat("good game, \"well\" played".into()),
+            Message::Emote("gg".into()),
+            Message::List,
+            Message::Open {
+                id: 4,
+                host: "bob the builder".into(),
+                offer: offer.clone(),
+            },
+            Message::Closed(4),
+            Message::Opened(4),
+            Message::Create(offer),
+            Message::Join(4),
+            Message::QuickMatch,
+        ]
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for message in every_message() {
+            let line = message.to_string();
+            assert!(!line.contains('\n'), "{line:?}");
+            assert_eq!(line.parse::<Message>(), Ok(message));
+        }
+    }
+
+    #[test]
+    fn chat_stays_on_one_line() {
+        let line = Message::Chat("one\ntwo".into()).to_string();
+        assert_eq!(line.parse(), Ok(Message::Chat("one two".into())));
+    }
+
+    #[test]
+    fn lines_are_bounded() {
+        let mut reader = std::io::Cursor::new(b"flag\r\nresume 7\nlist".to_vec());
+        assert_eq!(read_line(&mut reader), Ok(Some("flag".into())));
+        assert_eq!(read_line(&mut reader), Ok(Some("resume 7".into())));
+        assert_eq!(read_line(&mut reader), Ok(Some("list".into())));
+        assert_eq!(read_line(&mut reader), Ok(None));
+        let endless = vec![b'a'; MAX_LINE_LEN as usize + 1];
+        assert!(read_line(&mut std::io::Cursor::new(endless)).is_err());
+    }
+
+    #[test]
+    fn broken_messages_are_refused() {
+        for line in [
+            "",
+            "hi 5",
+            "hello five",
+            "start P1 1 -",
+            "move X 0x0",
+            "move R",
+            "move R 0xnothex",
+            "resume -1",
+            "rejoin",
+            "rejoin 0xnothex",
+            "open 1 14x7 4 1 standard",
+            "closed one",
+            "create 14x7",
+        ] {
+            assert!(line.parse::<Message>().is_err(), "{line:?}");
+        }
+    }
+}
@@ -7,8 +7,9 @@ pub const PLAYER_COLOR_DATA: &[(Srgba, Srgba)] = &[
     (CYAN_200, CYAN_600),
 ];
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Player {
+    #[default]
     Undef,
     One,
     Two,
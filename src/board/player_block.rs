@@ -1,12 +1,17 @@
 use bevy::prelude::*;
 
 use super::BoardState;
+use super::GameSetup;
 use super::Player;
+use super::match_play::MatchResource;
 
-use super::BOARD_WIDTH;
 use super::BOARD_BLOCK;
+use super::chat::UiChatBubble;
+use super::chess_clock::UiClockBlock;
 use super::player::PLAYER_COLOR_DATA;
 
+const MAX_LABEL_LEN: usize = 7;
+
 #[derive(Component)]
 pub struct UiPlayerBlock {
     player: Player,
@@ -23,6 +28,7 @@ pub fn make_pair(
     parent: &mut ChildSpawnerCommands,
     left_player: Player,
     right_player: Player,
+    setup: &GameSetup,
     round_top: bool,
 ) {
     let border_radius = 8.0;
@@ -97,10 +103,16 @@ pub fn make_pair(
         let (color_bg, color_fg) = PLAYER_COLOR_DATA[index];
         let color_bg: Color = color_bg.into();
         let color_fg: Color = color_fg.into();
-        let label = match ui_player_label.player.clone() {
-            Player::One => "P1",
-            Player::Two => "P2",
-            Player::Undef => "??",
+        // long profile names are cut to fit the block
+        let label: String = setup
+            .player_name(&ui_player_label.player)
+            .chars()
+            .take(MAX_LABEL_LEN)
+            .collect();
+        let font_size = if label.chars().count() > 2 {
+            14.0
+        } else {
+            20.0
         };
         container.with_children(|parent| {
             let flex_direction = if round_top {
@@ -108,37 +120,67 @@ pub fn make_pair(
             } else {
                 FlexDirection::ColumnReverse
             };
-            parent.spawn((
-                Node {
+            parent
+                .spawn((Node {
                     width: Val::Px(BOARD_BLOCK),
                     height: Val::Px(BOARD_BLOCK),
                     flex_direction,
                     align_items: AlignItems::FlexEnd,
                     justify_content: JustifyContent::FlexEnd,
                     ..default()
-                },
-            )).with_children(|parent| {
-                parent.spawn((
-                    block_node.clone(),
-                    border_radius.clone(),
-                    BackgroundColor(color_bg),
-                    BorderColor(color_fg),
-                )).with_child((
-                    ui_player_label,
-                    TextColor(color_fg),
-                    Text::new(label),
-                ));
-            });
+                },))
+                .with_children(|parent| {
+                    parent
+                        .spawn((
+                            block_node.clone(),
+                            border_radius.clone(),
+                            BackgroundColor(color_bg),
+                            BorderColor(color_fg),
+                        ))
+                        .with_child((
+                            ui_player_label,
+                            TextColor(color_fg),
+                            TextFont::from_font_size(font_size),
+                            Text::new(label),
+                        ));
+                });
         });
     };
 
+    let (clock_player, justify_content) = if left_player != Player::Undef {
+        (left_player.clone(), JustifyContent::FlexStart)
+    } else {
+        (right_player.clone(), JustifyContent::FlexEnd)
+    };
     let make_spacer = |container: &mut EntityCommands| {
-        container.with_child(Node {
-            width: Val::Px(BOARD_BLOCK * (BOARD_WIDTH - 4) as f32),
-            height: Val::Px(BOARD_BLOCK),
-            align_items: AlignItems::Center,
-            justify_content: JustifyContent::Center,
-            ..default()
+        let ui_chat_bubble = UiChatBubble {
+            player: clock_player.clone(),
+        };
+        let ui_clock_block = UiClockBlock {
+            player: clock_player,
+        };
+        container.with_children(|parent| {
+            parent
+                .spawn(Node {
+                    width: Val::Px(BOARD_BLOCK * (setup.board_width - 4) as f32),
+                    height: Val::Px(BOARD_BLOCK),
+                    align_items: AlignItems::Center,
+                    justify_content,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((ui_clock_block, TextColor::default(), Text::new("")));
+                    parent.spawn((
+                        ui_chat_bubble,
+                        Node {
+                            margin: UiRect::horizontal(Val::Px(12.0)),
+                            ..default()
+                        },
+                        TextColor::default(),
+                        TextFont::from_font_size(16.0),
+                        Text::new(""),
+                    ));
+                });
         });
     };
 
@@ -182,14 +224,10 @@ pub fn animate_labels(
 
 pub fn animate_crowns(
     mut ui_crowns: Query<(&UiCrownBlock, &mut ImageNode)>,
-    state: Res<State<BoardState>>,
+    standing: Res<MatchResource>,
 ) {
-    let state = state.get();
     for (ui_crown, mut image_node) in ui_crowns.iter_mut() {
-        let is_winning = match state {
-            BoardState::Victory(player) => *player == ui_crown.player,
-            _ => false,
-        };
+        let is_winning = standing.winner.as_ref() == Some(&ui_crown.player);
         let alpha = if is_winning { 1.0 } else { 0.0 };
         let color = Srgba::new(1.0, 1.0, 1.0, alpha);
         image_node.color = color.into();
@@ -0,0 +1,589 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::game_result;
+use super::game_result::GameResultResource;
+use super::game_result::ResultTakenBack;
+use super::setup::Controller;
+use super::utils::ConfigFile;
+
+use std::collections::BTreeMap;
+
+pub struct ProfilesPlugin;
+
+impl Plugin for ProfilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProfilesResource>();
+        app.add_systems(Startup, (load_profiles, populate).chain());
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::One)),
+            update_profiles.after(game_result::record_result),
+        );
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::Two)),
+            update_profiles.after(game_result::record_result),
+        );
+        app.add_systems(
+            OnEnter(BoardState::Victory(Player::Undef)),
+            update_profiles.after(game_result::record_result),
+        );
+        app.add_systems(
+            Update,
+            take_back_profiles.run_if(on_event::<ResultTakenBack>),
+        );
+        app.add_systems(Update, (toggle_stats, animate_stats).chain());
+    }
+}
+
+pub const MAX_PROFILE_NAME_LEN: usize = 16;
+
+/// Profile of computer seats that weren't given one, so that bots get a rating.
+const BOT_PROFILE_NAME: &str = "computer";
+
+const INITIAL_RATING: f32 = 1500.0;
+const RATING_K_FACTOR: f32 = 32.0;
+const MAX_RATING_HISTORY: usize = 20;
+
+/// Check a profile name given on the command line.
+pub fn parse_profile_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_PROFILE_NAME_LEN {
+        return Err(format!(
+            "profile name must have 1 to {MAX_PROFILE_NAME_LEN} characters"
+        ));
+    }
+    if name.contains(['[', ']', '=', '"']) || name.chars().any(|cc| cc.is_control()) {
+        return Err(format!("profile name {name:?} has reserved characters"));
+    }
+    // the profile of computer seats can't be taken by a player
+    if name.eq_ignore_ascii_case(BOT_PROFILE_NAME) {
+        return Err(format!("profile name {name:?} is reserved"));
+    }
+    Ok(name.into())
+}
+
+/// Lifetime statistics of a named local player.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub num_games: usize,
+    pub num_wins: usize,
+    pub num_draws: usize,
+    pub num_losses: usize,
+    /// Average part of the board owned at the end of a game, from 0 to 1.
+    pub average_territory_share: f32,
+    /// Most moves in a finished game.
+    pub longest_game: usize,
+    /// Elo rating, only ranked games count.
+    pub rating: f32,
+    pub num_rated_games: usize,
+    /// Ratings after the last rated games, oldest first.
+    pub rating_history: Vec<f32>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            num_games: 0,
+            num_wins: 0,
+            num_draws: 0,
+            num_losses: 0,
+            average_territory_share: 0.0,
+            longest_game: 0,
+            rating: INITIAL_RATING,
+            num_rated_games: 0,
+            rating_history: vec![],
+        }
+    }
+}
+
+impl Profile {
+    fn add_game(
+        &mut self,
+        winner: &Player,
+        player: &Player,
+        territory_share: f32,
+        num_moves: usize,
+    ) {
+        if *winner == Player::Undef {
+            self.num_draws += 1;
+        } else if winner == player {
+            self.num_wins += 1;
+        } else {
+            self.num_losses += 1;
+        }
+        let total_share = self.average_territory_share * self.num_games as f32 + territory_share;
+        self.num_games += 1;
+        self.average_territory_share = total_share / self.num_games as f32;
+        self.longest_game = self.longest_game.max(num_moves);
+    }
+
+    fn set_rating(&mut self, rating: f32) {
+        self.rating = rating;
+        self.num_rated_games += 1;
+        self.rating_history.push(rating);
+        if self.rating_history.len() > MAX_RATING_HISTORY {
+            self.rating_history.remove(0);
+        }
+    }
+}
+
+/// New Elo ratings after a game, `score` is 1 when the first player wins, 0.5
+/// for a draw and 0 when the second player wins.
+fn compute_ratings(rating_one: f32, rating_two: f32, score: f32) -> (f32, f32) {
+    let expected = 1.0 / (1.0 + 10f32.powf((rating_two - rating_one) / 400.0));
+    let delta = RATING_K_FACTOR * (score - expected);
+    (rating_one + delta, rating_two - delta)
+}
+
+/// Profile of the seat, computer seats fall back to the bot profile. A seat
+/// played from another machine or by turn file has none, its name is only
+/// what the other side says and must not move a local profile.
+fn seat_profile(setup: &GameSetup, player: &Player) -> Option<String> {
+    if matches!(
+        setup.controller(player),
+        Controller::Remote | Controller::Mail
+    ) {
+        return None;
+    }
+    match setup.profile(player) {
+        Some(name) => Some(name.clone()),
+        None if setup.controller(player) == Controller::Computer => Some(BOT_PROFILE_NAME.into()),
+        None => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum StatsScreen {
+    #[default]
+    Hidden,
+    Statistics,
+    Ladder,
+}
+
+#[derive(Resource, Default)]
+pub struct ProfilesResource {
+    pub name_to_profiles: BTreeMap<String, Profile>,
+    file: ConfigFile,
+    shown_screen: StatsScreen,
+    /// Profiles before the last board was credited, until it is taken back.
+    previous_profiles: Option<BTreeMap<String, Profile>>,
+}
+
+/// Profiles are written one section per name, e.g.
+///
+/// ```text
+/// [alice]
+/// games = 12
+/// wins = 7
+/// draws = 1
+/// losses = 4
+/// territory_share = 0.5342
+/// longest_game = 48
+/// rating = 1532.4
+/// rated_games = 3
+/// rating_history = 1516.0 1501.2 1532.4
+/// ```
+fn format_profiles(name_to_profiles: &BTreeMap<String, Profile>) -> String {
+    let mut content = String::new();
+    for (name, profile) in name_to_profiles.iter() {
+        let rating_history: Vec<String> = profile
+            .rating_history
+            .iter()
+            .map(|rating| format!("{rating:.1}"))
+            .collect();
+        content += &format!(
+            "[{}]\ngames = {}\nwins = {}\ndraws = {}\nlosses = {}\nterritory_share = {:.4}\nlongest_game = {}\nrating = {:.1}\nrated_games = {}\nrating_history = {}\n\n",
+            name,
+            profile.num_games,
+            profile.num_wins,
+            profile.num_draws,
+            profile.num_losses,
+            profile.average_territory_share,
+            profile.longest_game,
+            profile.rating,
+            profile.num_rated_games,
+            rating_history.join(" "),
+        );
+    }
+    content
+}
+
+fn parse_profiles(content: &str) -> (BTreeMap<String, Profile>, Vec<String>) {
+    let mut name_to_profiles: BTreeMap<String, Profile> = BTreeMap::new();
+    let mut errors = vec![];
+    let mut current_name: Option<String> = None;
+
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            current_name = Some(name.to_string());
+            name_to_profiles.entry(name.to_string()).or_default();
+            continue;
+        }
+        let Some(profile) = current_name
+            .as_ref()
+            .and_then(|name| name_to_profiles.get_mut(name))
+        else {
+            errors.push(format!("{line:?} is outside of a profile"));
+            continue;
+        };
+        let Some((key, value)) = line.split_once('=') else {
+            errors.push(format!("expected key = value, got {line:?}"));
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let parse_count = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|err| format!("invalid {key} {value:?}: {err}"))
+        };
+        let parsed = match key {
+            "games" => parse_count(value).map(|count| profile.num_games = count),
+            "wins" => parse_count(value).map(|count| profile.num_wins = count),
+            "draws" => parse_count(value).map(|count| profile.num_draws = count),
+            "losses" => parse_count(value).map(|count| profile.num_losses = count),
+            "territory_share" => value
+                .parse::<f32>()
+                .map(|share| profile.average_territory_share = share.clamp(0.0, 1.0))
+                .map_err(|err| format!("invalid {key} {value:?}: {err}")),
+            "longest_game" => parse_count(value).map(|count| profile.longest_game = count),
+            "rating" => value
+                .parse::<f32>()
+                .map(|rating| profile.rating = rating)
+                .map_err(|err| format!("invalid {key} {value:?}: {err}")),
+            "rated_games" => parse_count(value).map(|count| profile.num_rated_games = count),
+            "rating_history" => value
+                .split_whitespace()
+                .map(|rating| rating.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()
+                .map(|ratings| profile.rating_history = ratings)
+                .map_err(|err| format!("invalid {key} {value:?}: {err}")),
+            _ => Err(format!("unknown profile field {key:?}")),
+        };
+        if let Err(err) = parsed {
+            errors.push(err);
+        }
+    }
+
+    (name_to_profiles, errors)
+}
+
+fn load_profiles(setup: Res<GameSetup>, mut profiles: ResMut<ProfilesResource>) {
+    (profiles.file, profiles.name_to_profiles) =
+        ConfigFile::load("profiles", &setup.profiles_path, parse_profiles);
+
+    for player in [Player::One, Player::Two] {
+        if let Some(name) = seat_profile(&setup, &player) {
+            profiles.name_to_profiles.entry(name).or_default();
+        }
+    }
+}
+
+fn update_profiles(
+    setup: Res<GameSetup>,
+    game_results: Res<GameResultResource>,
+    mut profiles: ResMut<ProfilesResource>,
+) {
+    profiles.previous_profiles = None;
+    // the first board of a loaded record was played before
+    let is_loaded_record = setup.saved_game.is_none() && !setup.replay_moves.is_empty();
+    if is_loaded_record && game_results.results.len() == 1 {
+        return;
+    }
+    // puzzles start from a curated position
+    if setup.puzzle_pack.is_some() {
+        return;
+    }
+    // spectators only watch, the players record their own games
+    if setup.is_spectating() {
+        return;
+    }
+    let name_one = seat_profile(&setup, &Player::One);
+    let name_two = seat_profile(&setup, &Player::Two);
+    // a game against oneself says nothing
+    if name_one.is_some() && name_one == name_two {
+        return;
+    }
+    let Some(result) = game_results.results.last() else {
+        return;
+    };
+    profiles.previous_profiles = Some(profiles.name_to_profiles.clone());
+
+    let num_cards = (setup.board_width * setup.board_height) as f32;
+    for (player, name) in [(Player::One, &name_one), (Player::Two, &name_two)] {
+        let Some(name) = name else {
+            continue;
+        };
+        let count = *result.player_to_counts.get(&player).unwrap_or(&0);
+        let profile = profiles.name_to_profiles.entry(name.clone()).or_default();
+        profile.add_game(
+            &result.winner,
+            &player,
+            count as f32 / num_cards,
+            result.num_resolved_moves,
+        );
+        info!("profile {} {:?}", name, profile);
+    }
+
+    // ratings move when both seats are known and the game is ranked
+    if setup.ranked
+        && let (Some(name_one), Some(name_two)) = (&name_one, &name_two)
+    {
+        let score = match result.winner {
+            Player::One => 1.0,
+            Player::Undef => 0.5,
+            Player::Two => 0.0,
+        };
+        let rating_one = profiles.name_to_profiles.get(name_one).unwrap().rating;
+        let rating_two = profiles.name_to_profiles.get(name_two).unwrap().rating;
+        let (rating_one, rating_two) = compute_ratings(rating_one, rating_two, score);
+        info!(
+            "ratings {} {:.1} {} {:.1}",
+            name_one, rating_one, name_two, rating_two
+        );
+        profiles
+            .name_to_profiles
+            .get_mut(name_one)
+            .unwrap()
+            .set_rating(rating_one);
+        profiles
+            .name_to_profiles
+            .get_mut(name_two)
+            .unwrap()
+            .set_rating(rating_two);
+    }
+
+    write_profiles(&profiles);
+}
+
+/// Forget the board that was taken back, it is credited again once replayed.
+fn take_back_profiles(mut profiles: ResMut<ProfilesResource>) {
+    let Some(name_to_profiles) = profiles.previous_profiles.take() else {
+        return;
+    };
+    profiles.name_to_profiles = name_to_profiles;
+    write_profiles(&profiles);
+}
+
+fn write_profiles(profiles: &ProfilesResource) {
+    profiles
+        .file
+        .store(&format_profiles(&profiles.name_to_profiles));
+}
+
+#[derive(Component)]
+struct StatsDiv;
+
+#[derive(Component)]
+struct StatsText;
+
+const STATS_BG_COLOR: Srgba = GRAY_100;
+const STATS_FG_COLOR: Srgba = GRAY_900;
+
+fn populate(mut commands: Commands) {
+    let mut frame = commands.spawn((
+        StatsDiv,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        ZIndex(3),
+    ));
+    frame.with_children(|parent| {
+        parent
+            .spawn((
+                Node {
+                    padding: UiRect::all(Val::Px(16.0)),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(STATS_BG_COLOR.into()),
+                BorderColor(STATS_FG_COLOR.into()),
+                BorderRadius::all(Val::Px(8.0)),
+            ))
+            .with_child((
+                StatsText,
+                TextColor(STATS_FG_COLOR.into()),
+                Text::new("statistics"),
+            ));
+    });
+}
+
+/// `Tab` goes through the statistics screen, the rating ladder and back to the board.
+fn toggle_stats(mut profiles: ResMut<ProfilesResource>, keyboard: Res<ButtonInput<KeyCode>>) {
+    if keyboard.just_pressed(KeyCode::Tab) {
+        profiles.shown_screen = match profiles.shown_screen {
+            StatsScreen::Hidden => StatsScreen::Statistics,
+            StatsScreen::Statistics => StatsScreen::Ladder,
+            StatsScreen::Ladder => StatsScreen::Hidden,
+        };
+    }
+}
+
+fn animate_stats(
+    mut stats_div: Single<&mut Visibility, With<StatsDiv>>,
+    mut stats_text: Single<&mut Text, With<StatsText>>,
+    profiles: Res<ProfilesResource>,
+    setup: Res<GameSetup>,
+) {
+    if profiles.shown_screen == StatsScreen::Hidden {
+        **stats_div = Visibility::Hidden;
+        return;
+    }
+    **stats_div = Visibility::Visible;
+    if !profiles.is_changed() {
+        return;
+    }
+    if profiles.shown_screen == StatsScreen::Ladder {
+        **stats_text = format_ladder(&profiles).into();
+        return;
+    }
+
+    let mut lines = vec![format!(
+        "{:<w$} {:>5} {:>4} {:>5} {:>6} {:>5} {:>7}",
+        "Profile",
+        "Games",
+        "Wins",
+        "Draws",
+        "Losses",
+        "Share",
+        "Longest",
+        w = MAX_PROFILE_NAME_LEN
+    )];
+    for (name, profile) in profiles.name_to_profiles.iter() {
+        let seat = if setup.player_one_profile.as_ref() == Some(name) {
+            " P1"
+        } else if setup.player_two_profile.as_ref() == Some(name) {
+            " P2"
+        } else {
+            ""
+        };
+        lines.push(format!(
+            "{:<w$} {:>5} {:>4} {:>5} {:>6} {:>4.0}% {:>7}{}",
+            name,
+            profile.num_games,
+            profile.num_wins,
+            profile.num_draws,
+            profile.num_losses,
+            100.0 * profile.average_territory_share,
+            profile.longest_game,
+            seat,
+            w = MAX_PROFILE_NAME_LEN
+        ));
+    }
+    if profiles.name_to_profiles.is_empty() {
+        lines.push("no profile yet, pick one with --profile-one".into());
+    }
+    lines.push("\n[tab] rating ladder".into());
+    **stats_text = lines.join("\n").into();
+}
+
+fn format_ladder(profiles: &ProfilesResource) -> String {
+    let mut ranked_profiles: Vec<(&String, &Profile)> = profiles
+        .name_to_profiles
+        .iter()
+        .filter(|(_, profile)| profile.num_rated_games > 0)
+        .collect();
+    ranked_profiles.sort_by(|aa, bb| bb.1.rating.total_cmp(&aa.1.rating));
+
+    let mut lines = vec![format!(
+        "{:>4} {:<w$} {:>6} {:>5}  History",
+        "Rank",
+        "Profile",
+        "Rating",
+        "Games",
+        w = MAX_PROFILE_NAME_LEN
+    )];
+    for (rank, (name, profile)) in ranked_profiles.iter().enumerate() {
+        let history: Vec<String> = profile
+            .rating_history
+            .iter()
+            .rev()
+            .take(8)
+            .rev()
+            .map(|rating| format!("{rating:.0}"))
+            .collect();
+        lines.push(format!(
+            "{:>4} {:<w$} {:>6.0} {:>5}  {}",
+            rank + 1,
+            name,
+            profile.rating,
+            profile.num_rated_games,
+            history.join(" "),
+            w = MAX_PROFILE_NAME_LEN
+        ));
+    }
+    if ranked_profiles.is_empty() {
+        lines.push("no rated game yet, play one with --ranked".into());
+    }
+    lines.push("\n[tab] close".into());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratings_move_by_expected_score() {
+        let (rating_one, rating_two) = compute_ratings(1500.0, 1500.0, 1.0);
+        assert_eq!((rating_one, rating_two), (1516.0, 1484.0));
+        let (rating_one, rating_two) = compute_ratings(1500.0, 1500.0, 0.0);
+        assert_eq!((rating_one, rating_two), (1484.0, 1516.0));
+        let (rating_one, rating_two) = compute_ratings(1500.0, 1500.0, 0.5);
+        assert_eq!((rating_one, rating_two), (1500.0, 1500.0));
+
+        // a draw against a stronger player is a good result
+        let (rating_one, rating_two) = compute_ratings(1400.0, 1600.0, 0.5);
+        assert!(rating_one > 1400.0 && rating_two < 1600.0);
+        assert!((rating_one - 1400.0 - (1600.0 - rating_two)).abs() < 1e-3);
+        // and the same seen from the other seat
+        let (rating_two_again, rating_one_again) = compute_ratings(1600.0, 1400.0, 0.5);
+        assert!((rating_one - rating_one_again).abs() < 1e-3);
+        assert!((rating_two - rating_two_again).abs() < 1e-3);
+    }
+
+    #[test]
+    fn profiles_round_trip() {
+        let mut name_to_profiles = BTreeMap::new();
+        name_to_profiles.insert(
+            "alice".to_string(),
+            Profile {
+                num_games: 12,
+                num_wins: 7,
+                num_draws: 1,
+                num_losses: 4,
+                average_territory_share: 0.5342,
+                longest_game: 48,
+                rating: 1532.5,
+                num_rated_games: 3,
+                rating_history: vec![1516.0, 1501.5, 1532.5],
+            },
+        );
+        name_to_profiles.insert("bob the builder".to_string(), Profile::default());
+        let content = format_profiles(&name_to_profiles);
+        assert_eq!(parse_profiles(&content), (name_to_profiles, vec![]));
+    }
+
+    #[test]
+    fn broken_profile_lines_are_reported() {
+        let content = "wins = 3\n[alice]\ngames = many\nwins 2\nluck = 1\nlosses = 2\n";
+        let (name_to_profiles, errors) = parse_profiles(content);
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        assert_eq!(name_to_profiles["alice"].num_games, 0);
+        assert_eq!(name_to_profiles["alice"].num_losses, 2);
+    }
+}
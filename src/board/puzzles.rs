@@ -0,0 +1,796 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::game_record::GameRecord;
+use super::game_record::GameRecordResource;
+use super::game_record::parse_player_name;
+use super::game_record::player_name;
+use super::match_play::MatchResource;
+use super::move_history::MoveHistoryResource;
+use super::replay_viewer::ReplayResource;
+use super::setup::Controller;
+use super::utils::ConfigFile;
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+pub struct PuzzlesPlugin;
+
+impl Plugin for PuzzlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PuzzlesResource>();
+        app.add_systems(Startup, (load_progress, populate).chain());
+        app.add_systems(
+            Update,
+            (check_goal, pick_puzzle, animate_puzzle, animate_pack).chain(),
+        );
+    }
+}
+
+/// What the player to move has to achieve from the puzzle position.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PuzzleGoal {
+    /// Win the game with at least this many more tiles than the opponent.
+    WinBy(usize),
+    /// Win the game within this many of one's own moves.
+    FloodIn(usize),
+}
+
+impl std::fmt::Display for PuzzleGoal {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::WinBy(margin) => write!(ff, "win-by {margin}"),
+            Self::FloodIn(num_moves) => write!(ff, "flood-in {num_moves}"),
+        }
+    }
+}
+
+impl std::str::FromStr for PuzzleGoal {
+    type Err = String;
+
+    /// Parse either `win-by 10` or `flood-in 6`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (kind, count) = value
+            .split_once(' ')
+            .ok_or_else(|| format!("expected goal and count, got {value:?}"))?;
+        let count: usize = count
+            .trim()
+            .parse()
+            .map_err(|err| format!("invalid goal count {count:?}: {err}"))?;
+        match kind {
+            "win-by" => Ok(Self::WinBy(count)),
+            "flood-in" if count > 0 => Ok(Self::FloodIn(count)),
+            "flood-in" => Err("flood-in needs at least one move".into()),
+            _ => Err(format!("unknown goal {kind:?}")),
+        }
+    }
+}
+
+impl PuzzleGoal {
+    fn describe(&self) -> String {
+        match self {
+            Self::WinBy(margin) => format!("win by at least {margin}"),
+            Self::FloodIn(num_moves) => format!("win within {num_moves} moves"),
+        }
+    }
+}
+
+/// A curated position: the moves leading to it, who is to move and the goal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub name: String,
+    pub record: GameRecord,
+    pub to_move: Player,
+    pub goal: PuzzleGoal,
+}
+
+/// Puzzles played in order, each one unlocked by solving the previous one, e.g.
+///
+/// ```text
+/// [Pack "Beginner"]
+/// [Board "14x7"]
+/// [Colors "4"]
+///
+/// [Puzzle "Corner rush"]
+/// [Seed "0x2a"]
+/// [ToMove "P1"]
+/// [Goal "win-by 10"]
+///
+/// 1. R B 2. G Y
+///
+/// [Puzzle "Six to go"]
+/// [Seed "0x2b"]
+/// [ToMove "P2"]
+/// [Goal "flood-in 6"]
+///
+/// 1. R B 2. G
+/// ```
+///
+/// Tags before the first puzzle are shared by every puzzle, which are game
+/// records on their own. The board size is the same for the whole pack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuzzlePack {
+    pub name: String,
+    pub puzzles: Vec<Puzzle>,
+}
+
+impl PuzzlePack {
+    pub fn parse(content: &str, default_name: &str) -> Result<Self, String> {
+        let mut header = String::new();
+        let mut chunks: Vec<String> = vec![];
+        for line in content.lines() {
+            if line.trim().starts_with("[Puzzle ") {
+                chunks.push(String::new());
+            }
+            match chunks.last_mut() {
+                Some(chunk) => *chunk += &format!("{line}\n"),
+                None => header += &format!("{line}\n"),
+            }
+        }
+
+        let mut name = default_name.to_string();
+        let mut shared_tags = String::new();
+        for line in header.lines().map(|line| line.trim()) {
+            match line.strip_prefix("[Pack ") {
+                Some(tag) => name = tag.trim_end_matches(']').trim().trim_matches('"').into(),
+                None => shared_tags += &format!("{line}\n"),
+            }
+        }
+
+        let mut puzzles: Vec<Puzzle> = vec![];
+        for (index, chunk) in chunks.iter().enumerate() {
+            let parse = || -> Result<Puzzle, String> {
+                let (record, other_tags) =
+                    GameRecord::parse_with_other_tags(&format!("{shared_tags}{chunk}"))?;
+                let mut puzzle_name = None;
+                let mut to_move = None;
+                let mut goal = None;
+                for (key, value) in other_tags {
+                    match key.as_str() {
+                        "Puzzle" => puzzle_name = Some(value),
+                        "ToMove" => to_move = Some(parse_player_name(&value)?),
+                        "Goal" => goal = Some(value.parse()?),
+                        _ => warn!("ignoring puzzle tag {key:?}"),
+                    }
+                }
+                Ok(Puzzle {
+                    name: puzzle_name.unwrap_or(format!("Puzzle {}", index + 1)),
+                    record,
+                    to_move: to_move.ok_or("missing ToMove")?,
+                    goal: goal.ok_or("missing goal")?,
+                })
+            };
+            let puzzle = parse().map_err(|err| format!("puzzle {}: {}", index + 1, err))?;
+            // progress is kept by name
+            if puzzles.iter().any(|other| other.name == puzzle.name) {
+                return Err(format!("puzzle name {:?} is used twice", puzzle.name));
+            }
+            if let Some(first) = puzzles.first()
+                && (first.record.board_width, first.record.board_height)
+                    != (puzzle.record.board_width, puzzle.record.board_height)
+            {
+                return Err(format!(
+                    "puzzle {} board differs from the rest of the pack",
+                    index + 1
+                ));
+            }
+            puzzles.push(puzzle);
+        }
+
+        if puzzles.is_empty() {
+            return Err("no puzzle in pack".into());
+        }
+        Ok(Self { name, puzzles })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        let default_name = std::path::Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::parse(&content, &default_name)
+    }
+
+    /// Set up the game so that the puzzle position is replayed, with the
+    /// player to move at the board and the computer in the other seat.
+    pub fn apply_to(&self, setup: &mut GameSetup, index: usize) {
+        let puzzle = &self.puzzles[index];
+        puzzle.record.apply_to(setup, None);
+        (setup.player_one, setup.player_two) = match puzzle.to_move {
+            Player::One => (Controller::Human, Controller::Computer),
+            _ => (Controller::Computer, Controller::Human),
+        };
+        setup.num_games = 1;
+        setup.ranked = true;
+        setup.fog_radius = None;
+        setup.clock = None;
+        setup.save_path = None;
+        setup.puzzle_pack = Some(self.clone());
+        setup.puzzle_index = index;
+    }
+}
+
+/// Set up the pack at the given puzzle, counted from 1, or at the first one
+/// not solved yet.
+pub fn start_pack(
+    setup: &mut GameSetup,
+    pack: PuzzlePack,
+    number: Option<usize>,
+) -> Result<(), String> {
+    let solved = setup
+        .puzzles_path
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| parse_progress(&content).0.remove(&pack.name))
+        .unwrap_or_default();
+    let index = match number {
+        Some(number) if (1..=pack.puzzles.len()).contains(&number) => number - 1,
+        Some(number) => {
+            return Err(format!(
+                "no puzzle {number}, the pack has {}",
+                pack.puzzles.len()
+            ));
+        }
+        None => first_unsolved(&pack, &solved),
+    };
+    if !is_unlocked(&pack, &solved, index) {
+        return Err(format!(
+            "puzzle {} is locked, solve puzzle {} first",
+            index + 1,
+            index
+        ));
+    }
+    pack.apply_to(setup, index);
+    Ok(())
+}
+
+/// Pick up at the first puzzle not solved yet, or the last one.
+fn first_unsolved(pack: &PuzzlePack, solved: &BTreeSet<String>) -> usize {
+    pack.puzzles
+        .iter()
+        .position(|puzzle| !solved.contains(&puzzle.name))
+        .unwrap_or(pack.puzzles.len() - 1)
+}
+
+/// The first puzzle is open, the others once the previous one is solved.
+fn is_unlocked(pack: &PuzzlePack, solved: &BTreeSet<String>, index: usize) -> bool {
+    index == 0
+        || solved.contains(&pack.puzzles[index].name)
+        || solved.contains(&pack.puzzles[index - 1].name)
+}
+
+/// Solved puzzles are written one section per pack, e.g.
+///
+/// ```text
+/// [Beginner]
+/// solved = Corner rush
+/// solved = Six to go
+/// ```
+fn format_progress(pack_to_solved: &BTreeMap<String, BTreeSet<String>>) -> String {
+    let mut content = String::new();
+    for (pack_name, solved) in pack_to_solved.iter() {
+        content += &format!("[{pack_name}]\n");
+        for name in solved.iter() {
+            content += &format!("solved = {name}\n");
+        }
+        content += "\n";
+    }
+    content
+}
+
+fn parse_progress(content: &str) -> (BTreeMap<String, BTreeSet<String>>, Vec<String>) {
+    let mut pack_to_solved: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut errors = vec![];
+    let mut current_pack: Option<String> = None;
+
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(pack_name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            current_pack = Some(pack_name.to_string());
+            pack_to_solved.entry(pack_name.to_string()).or_default();
+            continue;
+        }
+        let Some(solved) = current_pack
+            .as_ref()
+            .and_then(|pack_name| pack_to_solved.get_mut(pack_name))
+        else {
+            errors.push(format!("{line:?} is outside of a pack"));
+            continue;
+        };
+        match line.split_once('=') {
+            Some((key, name)) if key.trim() == "solved" => {
+                solved.insert(name.trim().to_string());
+            }
+            _ => errors.push(format!("expected solved = name, got {line:?}")),
+        }
+    }
+
+    (pack_to_solved, errors)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum PuzzleStatus {
+    /// The moves leading to the puzzle position are being played.
+    #[default]
+    Replaying,
+    Playing,
+    Solved,
+    Failed,
+    /// The moves don't lead to a position with the player to move at the board.
+    Broken,
+}
+
+#[derive(Resource, Default)]
+pub struct PuzzlesResource {
+    pub pack_to_solved: BTreeMap<String, BTreeSet<String>>,
+    file: ConfigFile,
+    status: PuzzleStatus,
+    show_pack: bool,
+}
+
+impl PuzzlesResource {
+    fn solved(&self, pack: &PuzzlePack) -> BTreeSet<String> {
+        self.pack_to_solved
+            .get(&pack.name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn write(&self) {
+        self.file.store(&format_progress(&self.pack_to_solved));
+    }
+}
+
+fn load_progress(setup: Res<GameSetup>, mut puzzles: ResMut<PuzzlesResource>) {
+    if setup.puzzle_pack.is_none() {
+        return;
+    }
+    (puzzles.file, puzzles.pack_to_solved) =
+        ConfigFile::load("puzzle progress", &setup.puzzles_path, parse_progress);
+}
+
+/// Judge the game once the puzzle position is on the board: the goal is met
+/// when the game ends in time with the right margin.
+#[allow(clippy::too_many_arguments)]
+fn check_goal(
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    history: Res<MoveHistoryResource>,
+    records: Res<GameRecordResource>,
+    replay: Res<ReplayResource>,
+    board: Res<BoardResource>,
+    mut puzzles: ResMut<PuzzlesResource>,
+) {
+    let Some(pack) = &setup.puzzle_pack else {
+        return;
+    };
+    if !records.replay_moves.is_empty() || replay.is_active() {
+        return;
+    }
+    let puzzle = &pack.puzzles[setup.puzzle_index];
+    let num_setup_moves = puzzle.record.moves.len();
+
+    // every move of the record must have been played, a refused one stops
+    // the replay early, and the player to move must be the one at the board
+    if puzzles.status == PuzzleStatus::Replaying {
+        let is_settled = matches!(
+            state.get(),
+            BoardState::WaitingForMove(_) | BoardState::Victory(_)
+        ) && matches!(*next_state, NextState::Unchanged);
+        if !is_settled {
+            return;
+        }
+        let is_ready = history.moves.len() == num_setup_moves
+            && *state.get() == BoardState::WaitingForMove(puzzle.to_move.clone());
+        puzzles.status = if is_ready {
+            PuzzleStatus::Playing
+        } else {
+            warn!(
+                "puzzle {:?} is broken, {} of its {} moves lead to {:?}",
+                puzzle.name,
+                history.moves.len(),
+                num_setup_moves,
+                state.get()
+            );
+            PuzzleStatus::Broken
+        };
+        return;
+    }
+    if puzzles.status != PuzzleStatus::Playing {
+        return;
+    }
+    let num_own_moves = history
+        .moves
+        .iter()
+        .skip(num_setup_moves)
+        .filter(|record| record.player == puzzle.to_move)
+        .count();
+
+    let status = match state.get() {
+        BoardState::Victory(winner) => {
+            let opponent = match puzzle.to_move {
+                Player::One => Player::Two,
+                _ => Player::One,
+            };
+            let count = *board.player_to_counts.get(&puzzle.to_move).unwrap_or(&0);
+            let opponent_count = *board.player_to_counts.get(&opponent).unwrap_or(&0);
+            let is_met = *winner == puzzle.to_move
+                && match puzzle.goal {
+                    PuzzleGoal::WinBy(margin) => count >= opponent_count + margin,
+                    PuzzleGoal::FloodIn(num_moves) => num_own_moves <= num_moves,
+                };
+            if is_met {
+                PuzzleStatus::Solved
+            } else {
+                PuzzleStatus::Failed
+            }
+        }
+        BoardState::WaitingForMove(player) if *player == puzzle.to_move => match puzzle.goal {
+            PuzzleGoal::FloodIn(num_moves) if num_own_moves >= num_moves => PuzzleStatus::Failed,
+            _ => return,
+        },
+        _ => return,
+    };
+
+    info!("puzzle {:?} {:?}", puzzle.name, status);
+    puzzles.status = status;
+    if status == PuzzleStatus::Solved {
+        puzzles
+            .pack_to_solved
+            .entry(pack.name.clone())
+            .or_default()
+            .insert(puzzle.name.clone());
+        puzzles.write();
+    }
+}
+
+/// `Backspace` retries the puzzle, `Delete` forgets it was solved,
+/// `PageUp` and `PageDown` move through the unlocked puzzles and `L` lists
+/// the pack.
+#[allow(clippy::too_many_arguments)]
+fn pick_puzzle(
+    mut writer: EventWriter<StartBoard>,
+    mut setup: ResMut<GameSetup>,
+    mut records: ResMut<GameRecordResource>,
+    mut standing: ResMut<MatchResource>,
+    mut puzzles: ResMut<PuzzlesResource>,
+    replay: Res<ReplayResource>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    let Some(pack) = setup.puzzle_pack.clone() else {
+        return;
+    };
+    if keyboard.just_pressed(KeyCode::KeyL) {
+        puzzles.show_pack = !puzzles.show_pack;
+    }
+    if replay.is_active() {
+        return;
+    }
+
+    let index = setup.puzzle_index;
+    if keyboard.just_pressed(KeyCode::Delete)
+        && let Some(solved) = puzzles.pack_to_solved.get_mut(&pack.name)
+        && solved.remove(&pack.puzzles[index].name)
+    {
+        info!("puzzle {:?} reset", pack.puzzles[index].name);
+        puzzles.write();
+    }
+
+    let solved = puzzles.solved(&pack);
+    let next_index =
+        if keyboard.just_pressed(KeyCode::Backspace) || keyboard.just_pressed(KeyCode::Delete) {
+            Some(index)
+        } else if keyboard.just_pressed(KeyCode::PageDown) {
+            Some(index + 1).filter(|index| *index < pack.puzzles.len())
+        } else if keyboard.just_pressed(KeyCode::PageUp) {
+            index.checked_sub(1)
+        } else {
+            None
+        };
+    let Some(next_index) = next_index else {
+        return;
+    };
+    if !is_unlocked(&pack, &solved, next_index) {
+        info!("puzzle {} is locked", next_index + 1);
+        return;
+    }
+
+    pack.apply_to(&mut setup, next_index);
+    records.replay_moves = setup.replay_moves.iter().cloned().collect();
+    records.open_viewer_when_done = false;
+    *standing = MatchResource::default();
+    puzzles.status = PuzzleStatus::Replaying;
+    writer.write(StartBoard {
+        seed: setup.seed,
+        first_player: setup.first_player.clone(),
+    });
+}
+
+#[derive(Component)]
+struct PuzzleText;
+
+#[derive(Component)]
+struct PackDiv;
+
+#[derive(Component)]
+struct PackText;
+
+const PUZZLE_BG_COLOR: Srgba = GRAY_100;
+const PUZZLE_FG_COLOR: Srgba = GRAY_900;
+
+fn populate(mut commands: Commands, setup: Res<GameSetup>) {
+    if setup.puzzle_pack.is_none() {
+        return;
+    }
+
+    let mut frame = commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            right: Val::Px(10.0),
+            align_items: AlignItems::FlexEnd,
+            justify_content: JustifyContent::FlexEnd,
+            ..default()
+        },
+        ZIndex(1),
+    ));
+    frame.with_child((
+        PuzzleText,
+        TextColor(PUZZLE_BG_COLOR.into()),
+        Text::new("puzzle"),
+    ));
+
+    let mut frame = commands.spawn((
+        PackDiv,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        ZIndex(3),
+    ));
+    frame.with_children(|parent| {
+        parent
+            .spawn((
+                Node {
+                    padding: UiRect::all(Val::Px(16.0)),
+                    border: UiRect::all(Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(PUZZLE_BG_COLOR.into()),
+                BorderColor(PUZZLE_FG_COLOR.into()),
+                BorderRadius::all(Val::Px(8.0)),
+            ))
+            .with_child((
+                PackText,
+                TextColor(PUZZLE_FG_COLOR.into()),
+                Text::new("pack"),
+            ));
+    });
+}
+
+fn animate_puzzle(
+    puzzle_text: Option<Single<&mut Text, With<PuzzleText>>>,
+    setup: Res<GameSetup>,
+    puzzles: Res<PuzzlesResource>,
+) {
+    let (Some(mut puzzle_text), Some(pack)) = (puzzle_text, &setup.puzzle_pack) else {
+        return;
+    };
+    if !puzzles.is_changed() && !setup.is_changed() {
+        return;
+    }
+    let puzzle = &pack.puzzles[setup.puzzle_index];
+    let status = match puzzles.status {
+        PuzzleStatus::Replaying | PuzzleStatus::Playing => "",
+        PuzzleStatus::Solved => "Solved!\n",
+        PuzzleStatus::Failed => "Failed\n",
+        PuzzleStatus::Broken => "Broken, check the pack\n",
+    };
+    let next_hint =
+        if puzzles.status == PuzzleStatus::Solved && setup.puzzle_index + 1 < pack.puzzles.len() {
+            "  [pgdn] next"
+        } else {
+            ""
+        };
+    **puzzle_text = format!(
+        "{} {}/{}: {}\n{} to {}\n{}[backspace] retry{}",
+        pack.name,
+        setup.puzzle_index + 1,
+        pack.puzzles.len(),
+        puzzle.name,
+        player_name(&puzzle.to_move),
+        puzzle.goal.describe(),
+        status,
+        next_hint,
+    )
+    .into();
+}
+
+fn animate_pack(
+    pack_div: Option<Single<&mut Visibility, With<PackDiv>>>,
+    pack_text: Option<Single<&mut Text, With<PackText>>>,
+    setup: Res<GameSetup>,
+    puzzles: Res<PuzzlesResource>,
+) {
+    let (Some(mut pack_div), Some(mut pack_text), Some(pack)) =
+        (pack_div, pack_text, &setup.puzzle_pack)
+    else {
+        return;
+    };
+    if !puzzles.show_pack {
+        **pack_div = Visibility::Hidden;
+        return;
+    }
+    **pack_div = Visibility::Visible;
+    if !puzzles.is_changed() && !setup.is_changed() {
+        return;
+    }
+
+    let solved = puzzles.solved(pack);
+    let num_solved = pack
+        .puzzles
+        .iter()
+        .filter(|puzzle| solved.contains(&puzzle.name))
+        .count();
+    let mut lines = vec![format!(
+        "{}, {}/{} solved\n",
+        pack.name,
+        num_solved,
+        pack.puzzles.len()
+    )];
+    for (index, puzzle) in pack.puzzles.iter().enumerate() {
+        let mark = if solved.contains(&puzzle.name) {
+            "solved"
+        } else if is_unlocked(pack, &solved, index) {
+            "open"
+        } else {
+            "locked"
+        };
+        let current = if index == setup.puzzle_index {
+            ">"
+        } else {
+            " "
+        };
+        lines.push(format!(
+            "{}{:>3} {:<24} {:<22} {}",
+            current,
+            index + 1,
+            puzzle.name,
+            puzzle.goal.describe(),
+            mark
+        ));
+    }
+    lines.push("\n[l] close".into());
+    **pack_text = lines.join("\n").into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Tile;
+    use super::*;
+
+    const PACK: &str = r#"[Pack "Beginner"]
+[Board "14x7"]
+[Colors "4"]
+
+[Puzzle "Corner rush"]
+[Seed "0x2a"]
+[ToMove "P1"]
+[Goal "win-by 10"]
+
+1. R B 2. G Y
+
+[Puzzle "Six to go"]
+[Seed "0x2b"]
+[ToMove "P2"]
+[Goal "flood-in 6"]
+
+1. R B 2. G
+"#;
+
+    #[test]
+    fn pack_parses() {
+        let pack = PuzzlePack::parse(PACK, "beginner").unwrap();
+        assert_eq!(pack.name, "Beginner");
+        assert_eq!(pack.puzzles.len(), 2);
+        let puzzle = &pack.puzzles[1];
+        assert_eq!(puzzle.name, "Six to go");
+        assert_eq!(puzzle.to_move, Player::Two);
+        assert_eq!(puzzle.goal, PuzzleGoal::FloodIn(6));
+        assert_eq!(puzzle.record.seed, 0x2b);
+        assert_eq!(puzzle.record.board_width, 14);
+        assert_eq!(
+            puzzle.record.moves,
+            vec![Tile::Red, Tile::Blue, Tile::Green]
+        );
+
+        let unnamed = PACK.replace("[Pack \"Beginner\"]\n", "");
+        assert_eq!(
+            PuzzlePack::parse(&unnamed, "beginner").unwrap().name,
+            "beginner"
+        );
+    }
+
+    #[test]
+    fn broken_packs_are_refused() {
+        for (pack, error) in [
+            (PACK.replace("Six to go", "Corner rush"), "used twice"),
+            (
+                PACK.replace("[Seed \"0x2b\"]", "[Seed \"0x2b\"]\n[Board \"10x5\"]"),
+                "board differs",
+            ),
+            (PACK.replace("[ToMove \"P2\"]\n", ""), "missing ToMove"),
+            (PACK.replace("[Goal \"win-by 10\"]\n", ""), "missing goal"),
+            (
+                PACK.replace("flood-in 6", "flood-in 0"),
+                "at least one move",
+            ),
+            ("[Pack \"Empty\"]\n[Board \"14x7\"]\n".into(), "no puzzle"),
+        ] {
+            let err = PuzzlePack::parse(&pack, "broken").unwrap_err();
+            assert!(err.contains(error), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn puzzles_unlock_in_order() {
+        let mut pack = PuzzlePack::parse(PACK, "beginner").unwrap();
+        let mut third = pack.puzzles[1].clone();
+        third.name = "Last one".into();
+        pack.puzzles.push(third);
+
+        let mut solved = BTreeSet::new();
+        assert!(is_unlocked(&pack, &solved, 0));
+        assert!(!is_unlocked(&pack, &solved, 1));
+        assert_eq!(first_unsolved(&pack, &solved), 0);
+
+        solved.insert("Corner rush".to_string());
+        assert!(is_unlocked(&pack, &solved, 1));
+        assert!(!is_unlocked(&pack, &solved, 2));
+        assert_eq!(first_unsolved(&pack, &solved), 1);
+
+        // a puzzle solved before stays open even if the previous one is reset
+        solved.insert("Last one".to_string());
+        solved.remove("Corner rush");
+        assert!(is_unlocked(&pack, &solved, 2));
+        assert!(!is_unlocked(&pack, &solved, 1));
+
+        solved.extend(["Corner rush".to_string(), "Six to go".to_string()]);
+        assert_eq!(first_unsolved(&pack, &solved), 2);
+    }
+
+    #[test]
+    fn progress_round_trips() {
+        let mut pack_to_solved = BTreeMap::new();
+        pack_to_solved.insert(
+            "Beginner".to_string(),
+            BTreeSet::from(["Corner rush".to_string(), "Six to go".to_string()]),
+        );
+        pack_to_solved.insert("Expert".to_string(), BTreeSet::new());
+        let content = format_progress(&pack_to_solved);
+        assert_eq!(parse_progress(&content), (pack_to_solved, vec![]));
+
+        let (pack_to_solved, errors) =
+            parse_progress("solved = Nope\n[Beginner]\nsolved = Corner rush\nfailed = Six to go\n");
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert_eq!(pack_to_solved["Beginner"].len(), 1);
+    }
+}
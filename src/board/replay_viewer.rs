@@ -0,0 +1,344 @@
+use bevy::color::palettes::tailwind::*;
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+
+use super::BOARD_BLOCK;
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::game_record::GameRecordResource;
+use super::move_history;
+use super::move_history::BoardSnapshot;
+use super::move_history::MoveHistoryResource;
+
+pub struct ReplayViewerPlugin;
+
+impl Plugin for ReplayViewerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayResource>();
+        app.add_systems(Startup, populate);
+        app.add_systems(PreUpdate, stop_replay.run_if(on_event::<StartBoard>));
+        app.add_systems(
+            Update,
+            (toggle_replay, scrub_timeline, step_replay, animate_timeline).chain(),
+        );
+    }
+}
+
+const REPLAY_SPEEDS: &[f32] = &[0.5, 1.0, 2.0, 4.0, 8.0];
+const TIMELINE_WIDTH: f32 = 6.0 * BOARD_BLOCK;
+
+/// Positions of the current game, shown one at a time while replaying.
+#[derive(Resource)]
+pub struct ReplayResource {
+    moves: Vec<(Player, Tile)>,
+    positions: Vec<BoardSnapshot>,
+    position: usize,
+    shown_position: Option<usize>,
+    is_playing: bool,
+    speed_index: usize,
+    last_step: f32,
+}
+
+impl Default for ReplayResource {
+    fn default() -> Self {
+        Self {
+            moves: vec![],
+            positions: vec![],
+            position: 0,
+            shown_position: None,
+            is_playing: false,
+            speed_index: 1,
+            last_step: 0.0,
+        }
+    }
+}
+
+impl ReplayResource {
+    pub fn is_active(&self) -> bool {
+        !self.positions.is_empty()
+    }
+
+    /// Position of the game being replayed, the board shows an earlier one.
+    pub fn live_position(&self) -> Option<&BoardSnapshot> {
+        self.positions.last()
+    }
+}
+
+/// Run condition keeping moves out of the board while replaying.
+pub fn is_not_replaying(replay: Res<ReplayResource>) -> bool {
+    !replay.is_active()
+}
+
+#[derive(Component)]
+struct TimelineDiv;
+
+#[derive(Component)]
+struct TimelineBar;
+
+#[derive(Component)]
+struct TimelineHandle;
+
+#[derive(Component)]
+struct TimelineText;
+
+const TIMELINE_BG_COLOR: Srgba = GRAY_100;
+const TIMELINE_FG_COLOR: Srgba = GRAY_900;
+
+fn populate(mut commands: Commands) {
+    let mut frame = commands.spawn((
+        TimelineDiv,
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            left: Val::Px(5.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::FlexStart,
+            padding: UiRect::all(Val::Px(6.0)),
+            border: UiRect::all(Val::Px(2.0)),
+            ..default()
+        },
+        BackgroundColor(TIMELINE_BG_COLOR.into()),
+        BorderColor(TIMELINE_FG_COLOR.into()),
+        BorderRadius::all(Val::Px(8.0)),
+        Visibility::Hidden,
+        ZIndex(1),
+    ));
+    frame.with_child((
+        TimelineText,
+        TextColor(TIMELINE_FG_COLOR.into()),
+        Text::new("replay"),
+    ));
+    frame.with_children(|parent| {
+        parent
+            .spawn((
+                TimelineBar,
+                Button,
+                RelativeCursorPosition::default(),
+                Node {
+                    width: Val::Px(TIMELINE_WIDTH),
+                    height: Val::Px(16.0),
+                    margin: UiRect::top(Val::Px(5.0)),
+                    border: UiRect::all(Val::Px(1.0)),
+                    ..default()
+                },
+                BackgroundColor(GRAY_300.into()),
+                BorderColor(TIMELINE_FG_COLOR.into()),
+            ))
+            .with_child((
+                TimelineHandle,
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(6.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                BackgroundColor(TIMELINE_FG_COLOR.into()),
+            ));
+    });
+}
+
+fn stop_replay(mut replay: ResMut<ReplayResource>) {
+    *replay = ReplayResource::default();
+}
+
+/// Enter replay mode with `R`, leaving it puts the board back at the last position.
+#[allow(clippy::too_many_arguments)]
+fn toggle_replay(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    history: Res<MoveHistoryResource>,
+    mut records: ResMut<GameRecordResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    mut replay: ResMut<ReplayResource>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    let is_settled = matches!(
+        state.get(),
+        BoardState::WaitingForMove(_) | BoardState::Victory(_)
+    ) && matches!(*next_state, NextState::Unchanged);
+    if !is_settled {
+        return;
+    }
+    // a loaded record opens in the viewer once all its moves are played
+    let is_loaded = records.open_viewer_when_done && records.replay_moves.is_empty();
+    if is_loaded {
+        records.open_viewer_when_done = false;
+    }
+    if !keyboard.just_pressed(KeyCode::KeyR) && !is_loaded {
+        return;
+    }
+
+    if replay.is_active() {
+        info!("leave replay");
+        replay.position = replay.positions.len() - 1;
+        replay.is_playing = false;
+        // the last position is shown before clearing the replay
+        replay.shown_position = None;
+        return;
+    }
+
+    // the opponent territory stays hidden until the game is over, and the
+    // clocks don't run in the viewer so it can't be used to think for free
+    let is_over = matches!(state.get(), BoardState::Victory(_));
+    if (setup.fog_radius.is_some() || setup.clock.is_some()) && !is_over {
+        return;
+    }
+    if history.moves.is_empty() {
+        return;
+    }
+
+    info!("enter replay {} moves", history.moves.len());
+    let mut positions: Vec<BoardSnapshot> = history
+        .moves
+        .iter()
+        .map(|record| record.prior.clone())
+        .collect();
+    positions.push(move_history::take_snapshot(&ui_cards, &ui_backs, &board));
+    replay.moves = history
+        .moves
+        .iter()
+        .map(|record| (record.player.clone(), record.tile.clone()))
+        .collect();
+    replay.position = positions.len() - 1;
+    replay.positions = positions;
+    replay.shown_position = Some(replay.position);
+    replay.is_playing = false;
+}
+
+fn scrub_timeline(
+    bars: Query<(&Interaction, &RelativeCursorPosition), With<TimelineBar>>,
+    mut replay: ResMut<ReplayResource>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+) {
+    if !replay.is_active() {
+        return;
+    }
+    let last_position = replay.positions.len() - 1;
+
+    for (interaction, relative_cursor) in bars.iter() {
+        if matches!(interaction, Interaction::Pressed)
+            && let Some(cursor) = relative_cursor.normalized
+        {
+            let alpha = cursor.x.clamp(0.0, 1.0);
+            replay.position = (alpha * last_position as f32).round() as usize;
+            replay.is_playing = false;
+        }
+    }
+
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        replay.position = replay.position.saturating_sub(1);
+        replay.is_playing = false;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        replay.position = (replay.position + 1).min(last_position);
+        replay.is_playing = false;
+    }
+    if keyboard.just_pressed(KeyCode::Home) {
+        replay.position = 0;
+        replay.is_playing = false;
+    }
+    if keyboard.just_pressed(KeyCode::End) {
+        replay.position = last_position;
+        replay.is_playing = false;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        replay.speed_index = (replay.speed_index + 1).min(REPLAY_SPEEDS.len() - 1);
+    }
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        replay.speed_index = replay.speed_index.saturating_sub(1);
+    }
+    if keyboard.just_pressed(KeyCode::KeyP) {
+        replay.is_playing = !replay.is_playing;
+        if replay.is_playing && replay.position == last_position {
+            replay.position = 0;
+        }
+        replay.last_step = time.elapsed().as_secs_f32();
+    }
+
+    if replay.is_playing {
+        let now = time.elapsed().as_secs_f32();
+        if now - replay.last_step >= 1.0 / REPLAY_SPEEDS[replay.speed_index] {
+            replay.last_step = now;
+            replay.position = (replay.position + 1).min(last_position);
+            replay.is_playing = replay.position < last_position;
+        }
+    }
+}
+
+/// Put the cards and backs in the selected position, the regular card and back
+/// animations take it from there.
+fn step_replay(
+    mut ui_cards: Query<(&mut UiCard, Entity)>,
+    mut ui_backs: Query<&mut UiBack>,
+    mut board: ResMut<BoardResource>,
+    mut replay: ResMut<ReplayResource>,
+) {
+    if !replay.is_active() {
+        return;
+    }
+
+    match replay.shown_position {
+        Some(shown_position) if shown_position == replay.position => {}
+        Some(_) => {
+            let snapshot = &replay.positions[replay.position];
+            move_history::restore_snapshot(&mut ui_cards, &mut ui_backs, &mut board, snapshot);
+            replay.shown_position = Some(replay.position);
+        }
+        None => {
+            // leaving the replay
+            let snapshot = replay.positions.last().unwrap();
+            move_history::restore_snapshot(&mut ui_cards, &mut ui_backs, &mut board, snapshot);
+            *replay = ReplayResource::default();
+        }
+    }
+}
+
+fn animate_timeline(
+    mut timeline_div: Single<&mut Visibility, With<TimelineDiv>>,
+    mut timeline_handle: Single<&mut Node, With<TimelineHandle>>,
+    mut timeline_text: Single<&mut Text, With<TimelineText>>,
+    replay: Res<ReplayResource>,
+) {
+    if !replay.is_active() {
+        **timeline_div = Visibility::Hidden;
+        return;
+    }
+    **timeline_div = Visibility::Visible;
+
+    let last_position = replay.positions.len() - 1;
+    let alpha = replay.position as f32 / last_position.max(1) as f32;
+    timeline_handle.left = Val::Px(alpha * (TIMELINE_WIDTH - 8.0));
+
+    let last_move = match replay.position {
+        0 => "start".into(),
+        position => {
+            let (player, tile) = &replay.moves[position - 1];
+            let player = match player {
+                Player::Undef => "??",
+                Player::One => "P1",
+                Player::Two => "P2",
+            };
+            format!("{} {}", player, tile.to_letter())
+        }
+    };
+    let play_label = if replay.is_playing {
+        "playing"
+    } else {
+        "paused"
+    };
+    **timeline_text = format!(
+        "Replay move {}/{} {}\n{} x{} [p] play [arrows] step/speed [r] leave",
+        replay.position, last_position, last_move, play_label, REPLAY_SPEEDS[replay.speed_index],
+    )
+    .into();
+}
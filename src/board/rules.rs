@@ -0,0 +1,220 @@
+use super::Tile;
+
+use std::collections::BTreeSet;
+
+/// Toggles for the restrictions on which color a player may pick.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    /// The opponent current color can be picked, touching territories stay apart.
+    pub allow_opponent_color: bool,
+    /// The color a player had before their last move can't be picked again.
+    pub forbid_previous_color: bool,
+    /// The color picked by the opponent on their last turn can't be picked.
+    pub forbid_opponent_last_pick: bool,
+    /// A player may pass instead of picking a color.
+    pub allow_pass: bool,
+}
+
+const RULE_NAMES: &[&str] = &[
+    "allow-opponent-color",
+    "forbid-previous-color",
+    "forbid-opponent-last-pick",
+    "allow-pass",
+];
+
+impl RuleSet {
+    fn toggles(&self) -> [bool; 4] {
+        [
+            self.allow_opponent_color,
+            self.forbid_previous_color,
+            self.forbid_opponent_last_pick,
+            self.allow_pass,
+        ]
+    }
+}
+
+/// Comma separated list of the enabled toggles, `standard` when none is.
+impl std::fmt::Display for RuleSet {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let names: Vec<&str> = RULE_NAMES
+            .iter()
+            .zip(self.toggles())
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        if names.is_empty() {
+            write!(ff, "standard")
+        } else {
+            write!(ff, "{}", names.join(","))
+        }
+    }
+}
+
+impl std::str::FromStr for RuleSet {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut rules = Self::default();
+        for name in value.split(',').filter(|name| !name.is_empty()) {
+            match name {
+                "standard" => {}
+                "allow-opponent-color" => rules.allow_opponent_color = true,
+                "forbid-previous-color" => rules.forbid_previous_color = true,
+                "forbid-opponent-last-pick" => rules.forbid_opponent_last_pick = true,
+                "allow-pass" => rules.allow_pass = true,
+                _ => return Err(format!("unknown rule {name:?}")),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// What a player knows about the board when picking a color.
+pub struct MoveContext {
+    /// Colors of the cards touching the player territory, but not owned by them.
+    pub adjacent_tiles: BTreeSet<Tile>,
+    pub own_tile: Tile,
+    pub opponent_tile: Tile,
+    pub previous_tile: Option<Tile>,
+    pub opponent_last_pick: Option<Tile>,
+    pub num_color_ban_waivers: usize,
+}
+
+impl RuleSet {
+    pub fn playable_tiles(&self, context: &MoveContext) -> BTreeSet<Tile> {
+        let mut playable_tiles = context.adjacent_tiles.clone();
+        playable_tiles.remove(&Tile::Undef);
+        playable_tiles.remove(&context.own_tile);
+        if !self.allow_opponent_color && context.num_color_ban_waivers == 0 {
+            playable_tiles.remove(&context.opponent_tile);
+        }
+        if self.forbid_previous_color
+            && let Some(previous_tile) = &context.previous_tile
+        {
+            playable_tiles.remove(previous_tile);
+        }
+        if self.forbid_opponent_last_pick
+            && let Some(opponent_last_pick) = &context.opponent_last_pick
+        {
+            playable_tiles.remove(opponent_last_pick);
+        }
+        playable_tiles
+    }
+
+    /// Picking the opponent current color uses up a waiver when it is banned.
+    pub fn uses_color_ban_waiver(&self, tile: &Tile, opponent_tile: &Tile) -> bool {
+        !self.allow_opponent_color && tile == opponent_tile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> MoveContext {
+        MoveContext {
+            adjacent_tiles: BTreeSet::from([Tile::Red, Tile::Green, Tile::Blue, Tile::Yellow]),
+            own_tile: Tile::Red,
+            opponent_tile: Tile::Green,
+            previous_tile: Some(Tile::Blue),
+            opponent_last_pick: Some(Tile::Yellow),
+            num_color_ban_waivers: 0,
+        }
+    }
+
+    #[test]
+    fn standard_rules_ban_own_and_opponent_colors() {
+        let playable_tiles = RuleSet::default().playable_tiles(&context());
+        assert_eq!(playable_tiles, BTreeSet::from([Tile::Blue, Tile::Yellow]));
+    }
+
+    #[test]
+    fn only_adjacent_colors_are_playable() {
+        let mut context = context();
+        context.adjacent_tiles = BTreeSet::from([Tile::Undef, Tile::Red, Tile::Blue]);
+        let playable_tiles = RuleSet::default().playable_tiles(&context);
+        assert_eq!(playable_tiles, BTreeSet::from([Tile::Blue]));
+    }
+
+    #[test]
+    fn allow_opponent_color() {
+        let rules = RuleSet {
+            allow_opponent_color: true,
+            ..Default::default()
+        };
+        let playable_tiles = rules.playable_tiles(&context());
+        assert_eq!(
+            playable_tiles,
+            BTreeSet::from([Tile::Green, Tile::Blue, Tile::Yellow])
+        );
+        assert!(!rules.uses_color_ban_waiver(&Tile::Green, &Tile::Green));
+    }
+
+    #[test]
+    fn color_ban_waiver_lifts_the_ban() {
+        let mut context = context();
+        context.num_color_ban_waivers = 1;
+        let rules = RuleSet::default();
+        let playable_tiles = rules.playable_tiles(&context);
+        assert!(playable_tiles.contains(&Tile::Green));
+        assert!(rules.uses_color_ban_waiver(&Tile::Green, &Tile::Green));
+        assert!(!rules.uses_color_ban_waiver(&Tile::Blue, &Tile::Green));
+    }
+
+    #[test]
+    fn forbid_previous_color() {
+        let rules = RuleSet {
+            forbid_previous_color: true,
+            ..Default::default()
+        };
+        let playable_tiles = rules.playable_tiles(&context());
+        assert_eq!(playable_tiles, BTreeSet::from([Tile::Yellow]));
+
+        let mut context = context();
+        context.previous_tile = None;
+        let playable_tiles = rules.playable_tiles(&context);
+        assert_eq!(playable_tiles, BTreeSet::from([Tile::Blue, Tile::Yellow]));
+    }
+
+    #[test]
+    fn forbid_opponent_last_pick() {
+        let rules = RuleSet {
+            forbid_opponent_last_pick: true,
+            ..Default::default()
+        };
+        let playable_tiles = rules.playable_tiles(&context());
+        assert_eq!(playable_tiles, BTreeSet::from([Tile::Blue]));
+
+        let mut context = context();
+        context.opponent_last_pick = None;
+        let playable_tiles = rules.playable_tiles(&context);
+        assert_eq!(playable_tiles, BTreeSet::from([Tile::Blue, Tile::Yellow]));
+    }
+
+    #[test]
+    fn allow_pass_leaves_the_colors_alone() {
+        let rules = RuleSet {
+            allow_pass: true,
+            ..Default::default()
+        };
+        let playable_tiles = rules.playable_tiles(&context());
+        assert_eq!(
+            playable_tiles,
+            RuleSet::default().playable_tiles(&context())
+        );
+    }
+
+    #[test]
+    fn every_toggle_round_trips() {
+        let rules = RuleSet {
+            allow_opponent_color: true,
+            forbid_previous_color: true,
+            forbid_opponent_last_pick: true,
+            allow_pass: true,
+        };
+        assert_eq!(rules.to_string().parse::<RuleSet>(), Ok(rules));
+        assert_eq!(RuleSet::default().to_string(), "standard");
+        assert_eq!("standard".parse::<RuleSet>(), Ok(RuleSet::default()));
+        assert!("allow-everything".parse::<RuleSet>().is_err());
+    }
+}
@@ -0,0 +1,668 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::chess_clock::ChessClockResource;
+use super::game_record;
+use super::game_record::GameRecord;
+use super::game_record::GameRecordResource;
+use super::match_play;
+use super::match_play::MatchResource;
+use super::move_history::MoveHistoryResource;
+use super::replay_viewer::ReplayResource;
+use super::setup::ClockSetup;
+use super::setup::parse_seed;
+use super::utils;
+
+use std::collections::BTreeMap;
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveGameResource>();
+        app.add_systems(Startup, restore_match.before(utils::start_first_board));
+        app.add_systems(Update, (finish_resume, save_on_demand).chain());
+        app.add_systems(Last, save_on_exit.run_if(on_event::<AppExit>));
+    }
+}
+
+/// Bumped whenever the meaning of an existing tag changes. New tags get a
+/// default instead, so that older saves keep loading.
+const SAVE_VERSION: u32 = 1;
+
+/// An unfinished game, written as a game record of the current board with
+/// extra tags for the match, the clocks and the cards, e.g.
+///
+/// ```text
+/// [Version "1"]
+/// [MatchSeed "0xab28f3af"]
+/// [NumGames "3"]
+/// [PlayedGames "1"]
+/// [MatchWins "1-0"]
+/// [MatchCounts "52-46"]
+/// [Ranked "false"]
+/// [ClockOne "41.5"]
+/// [ClockTwo "38.2"]
+/// [ToMove "P2"]
+/// [Row "1R 1R .G 2B ..."]
+/// [Board "14x7"]
+/// ...
+///
+/// 1. R B 2. G
+/// ```
+///
+/// The moves are replayed on resume, the cards are checked against the result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedGame {
+    pub version: u32,
+    /// Current board, its seats and its moves so far.
+    pub record: GameRecord,
+    pub match_seed: u64,
+    pub num_games: usize,
+    pub num_played_games: usize,
+    pub player_to_wins: BTreeMap<Player, usize>,
+    pub player_to_total_counts: BTreeMap<Player, usize>,
+    pub player_one_profile: Option<String>,
+    pub player_two_profile: Option<String>,
+    pub ranked: bool,
+    pub fog_radius: Option<usize>,
+    pub clock: Option<ClockSetup>,
+    pub auto_move_on_timeout: bool,
+    pub player_to_remaining: BTreeMap<Player, f32>,
+    /// Missing when saved in the middle of a move.
+    pub player_to_move: Option<Player>,
+    /// Tile and owner of every card, row by row, empty when there is no board
+    /// to check against.
+    pub cards: Vec<(Tile, Player)>,
+}
+
+#[derive(Resource, Default)]
+struct SaveGameResource {
+    pending_resume: Option<SavedGame>,
+}
+
+fn write_pair<T: std::fmt::Display>(
+    ff: &mut std::fmt::Formatter,
+    key: &str,
+    player_to_values: &BTreeMap<Player, T>,
+    default: T,
+) -> std::fmt::Result {
+    writeln!(
+        ff,
+        "[{} \"{}-{}\"]",
+        key,
+        player_to_values.get(&Player::One).unwrap_or(&default),
+        player_to_values.get(&Player::Two).unwrap_or(&default),
+    )
+}
+
+fn parse_pair<T: std::str::FromStr>(value: &str) -> Result<BTreeMap<Player, T>, String> {
+    let parse = |value: &str| {
+        value
+            .parse::<T>()
+            .map_err(|_| format!("invalid value {value:?}"))
+    };
+    let (one, two) = value
+        .split_once('-')
+        .ok_or_else(|| format!("expected a pair, got {value:?}"))?;
+    Ok(BTreeMap::from([
+        (Player::One, parse(one)?),
+        (Player::Two, parse(two)?),
+    ]))
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("invalid {key} {value:?}: {err}"))
+}
+
+impl std::fmt::Display for SavedGame {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(ff, "[Version \"{}\"]", self.version)?;
+        writeln!(ff, "[MatchSeed \"{:#x}\"]", self.match_seed)?;
+        writeln!(ff, "[NumGames \"{}\"]", self.num_games)?;
+        writeln!(ff, "[PlayedGames \"{}\"]", self.num_played_games)?;
+        write_pair(ff, "MatchWins", &self.player_to_wins, 0)?;
+        write_pair(ff, "MatchCounts", &self.player_to_total_counts, 0)?;
+        if let Some(name) = &self.player_one_profile {
+            writeln!(ff, "[ProfileOne \"{}\"]", name)?;
+        }
+        if let Some(name) = &self.player_two_profile {
+            writeln!(ff, "[ProfileTwo \"{}\"]", name)?;
+        }
+        writeln!(ff, "[Ranked \"{}\"]", self.ranked)?;
+        if let Some(fog_radius) = self.fog_radius {
+            writeln!(ff, "[FogRadius \"{}\"]", fog_radius)?;
+        }
+        if let Some(clock) = &self.clock {
+            writeln!(ff, "[Clock \"{}\"]", clock)?;
+            writeln!(ff, "[AutoMoveOnTimeout \"{}\"]", self.auto_move_on_timeout)?;
+        }
+        for (player, remaining) in self.player_to_remaining.iter() {
+            let key = match player {
+                Player::One => "ClockOne",
+                Player::Two => "ClockTwo",
+                Player::Undef => unreachable!(),
+            };
+            writeln!(ff, "[{} \"{:.3}\"]", key, remaining)?;
+        }
+        if let Some(player) = &self.player_to_move {
+            writeln!(ff, "[ToMove \"{}\"]", game_record::player_name(player))?;
+        }
+        for row in self.cards.chunks(self.record.board_width) {
+            let cells: Vec<String> = row
+                .iter()
+                .map(|(tile, player)| {
+                    let owner = match player {
+                        Player::Undef => '.',
+                        Player::One => '1',
+                        Player::Two => '2',
+                    };
+                    format!("{}{}", owner, tile.to_letter())
+                })
+                .collect();
+            writeln!(ff, "[Row \"{}\"]", cells.join(" "))?;
+        }
+        write!(ff, "{}", self.record)
+    }
+}
+
+impl std::str::FromStr for SavedGame {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (record, other_tags) = GameRecord::parse_with_other_tags(value)?;
+        let mut saved = SavedGame {
+            version: 0,
+            match_seed: record.seed,
+            record,
+            num_games: 1,
+            num_played_games: 0,
+            player_to_wins: BTreeMap::new(),
+            player_to_total_counts: BTreeMap::new(),
+            player_one_profile: None,
+            player_two_profile: None,
+            ranked: false,
+            fog_radius: None,
+            clock: None,
+            auto_move_on_timeout: false,
+            player_to_remaining: BTreeMap::new(),
+            player_to_move: None,
+            cards: vec![],
+        };
+
+        for (key, tag_value) in other_tags {
+            let tag_value = tag_value.as_str();
+            match key.as_str() {
+                "Version" => saved.version = parse_value(&key, tag_value)?,
+                "MatchSeed" => saved.match_seed = parse_seed(tag_value)?,
+                "NumGames" => saved.num_games = parse_value(&key, tag_value)?,
+                "PlayedGames" => saved.num_played_games = parse_value(&key, tag_value)?,
+                "MatchWins" => saved.player_to_wins = parse_pair(tag_value)?,
+                "MatchCounts" => saved.player_to_total_counts = parse_pair(tag_value)?,
+                "ProfileOne" => saved.player_one_profile = Some(tag_value.into()),
+                "ProfileTwo" => saved.player_two_profile = Some(tag_value.into()),
+                "Ranked" => saved.ranked = parse_value(&key, tag_value)?,
+                "FogRadius" => saved.fog_radius = Some(parse_value(&key, tag_value)?),
+                "Clock" => saved.clock = Some(tag_value.parse()?),
+                "AutoMoveOnTimeout" => saved.auto_move_on_timeout = parse_value(&key, tag_value)?,
+                "ClockOne" => {
+                    let remaining = parse_value(&key, tag_value)?;
+                    saved.player_to_remaining.insert(Player::One, remaining);
+                }
+                "ClockTwo" => {
+                    let remaining = parse_value(&key, tag_value)?;
+                    saved.player_to_remaining.insert(Player::Two, remaining);
+                }
+                "ToMove" => saved.player_to_move = Some(game_record::parse_player_name(tag_value)?),
+                "Row" => {
+                    for cell in tag_value.split_whitespace() {
+                        let mut letters = cell.chars();
+                        let owner = match letters.next() {
+                            Some('.') => Player::Undef,
+                            Some('1') => Player::One,
+                            Some('2') => Player::Two,
+                            _ => return Err(format!("invalid card owner {cell:?}")),
+                        };
+                        let tile = match (letters.next(), letters.next()) {
+                            (Some(letter), None) => Tile::from_letter(letter),
+                            _ => None,
+                        };
+                        let tile = tile.ok_or_else(|| format!("invalid card tile {cell:?}"))?;
+                        saved.cards.push((tile, owner));
+                    }
+                }
+                _ => warn!("ignoring saved game tag {key:?}"),
+            }
+        }
+
+        if saved.version == 0 {
+            return Err("missing version, not a saved game".into());
+        }
+        if saved.version > SAVE_VERSION {
+            return Err(format!(
+                "saved with format version {}, this build reads up to {}",
+                saved.version, SAVE_VERSION
+            ));
+        }
+        let num_cards = saved.record.board_width * saved.record.board_height;
+        if !saved.cards.is_empty() && saved.cards.len() != num_cards {
+            return Err(format!(
+                "expected {} cards, got {}",
+                num_cards,
+                saved.cards.len()
+            ));
+        }
+        Ok(saved)
+    }
+}
+
+impl SavedGame {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| format!("can't read {path}: {err}"))?;
+        content.parse()
+    }
+
+    /// Set up the game so that it resumes where it was saved.
+    pub fn apply_to(&self, setup: &mut GameSetup) {
+        self.record.apply_to(setup, None);
+        setup.seed = self.match_seed;
+        setup.num_games = self.num_games;
+        setup.player_one = self.record.player_one;
+        setup.player_two = self.record.player_two;
+        setup.player_one_profile = self.player_one_profile.clone();
+        setup.player_two_profile = self.player_two_profile.clone();
+        setup.ranked = self.ranked;
+        setup.fog_radius = self.fog_radius;
+        setup.clock = self.clock;
+        setup.auto_move_on_timeout = self.auto_move_on_timeout;
+        setup.saved_game = Some(self.clone());
+    }
+}
+
+fn restore_match(
+    setup: Res<GameSetup>,
+    mut standing: ResMut<MatchResource>,
+    mut resume: ResMut<SaveGameResource>,
+) {
+    let Some(saved) = &setup.saved_game else {
+        return;
+    };
+    info!(
+        "resume board {} of {} with {} moves",
+        saved.num_played_games + 1,
+        saved.num_games,
+        saved.record.moves.len()
+    );
+    standing.num_played_games = saved.num_played_games;
+    standing.player_to_wins = saved.player_to_wins.clone();
+    standing.player_to_total_counts = saved.player_to_total_counts.clone();
+    resume.pending_resume = Some(saved.clone());
+}
+
+/// Once the saved moves are replayed, check the board against the saved cards
+/// and put back the clocks. A save that doesn't replay in full, or to other
+/// cards, is discarded and the match starts over.
+#[allow(clippy::too_many_arguments)]
+fn finish_resume(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    mut setup: ResMut<GameSetup>,
+    mut standing: ResMut<MatchResource>,
+    mut writer: EventWriter<StartBoard>,
+    history: Res<MoveHistoryResource>,
+    records: Res<GameRecordResource>,
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    mut clocks: ResMut<ChessClockResource>,
+    mut resume: ResMut<SaveGameResource>,
+) {
+    if resume.pending_resume.is_none() || !records.replay_moves.is_empty() {
+        return;
+    }
+    let is_settled = matches!(
+        state.get(),
+        BoardState::WaitingForMove(_) | BoardState::Victory(_)
+    ) && matches!(*next_state, NextState::Unchanged);
+    if !is_settled {
+        return;
+    }
+    let saved = resume.pending_resume.take().unwrap();
+
+    let num_mismatches = if saved.cards.is_empty() {
+        0
+    } else {
+        ui_cards
+            .iter()
+            .filter(|(ui_card, card)| {
+                let (tile, player) =
+                    &saved.cards[ui_card.row * saved.record.board_width + ui_card.column];
+                let back = board.card_to_backs.get(card).unwrap();
+                ui_card.tile != *tile || ui_backs.get(*back).unwrap().player != *player
+            })
+            .count()
+    };
+    let error = if history.moves.len() != saved.record.moves.len() {
+        Some(format!(
+            "saved game could only be replayed up to move {} of {}",
+            history.moves.len(),
+            saved.record.moves.len()
+        ))
+    } else if num_mismatches > 0 {
+        Some(format!("{num_mismatches} cards differ from the saved game"))
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        error!("{}, starting a new game", error);
+        if let Some(path) = &setup.save_path {
+            write_saved_game(path, None);
+        }
+        setup.saved_game = None;
+        setup.replay_moves.clear();
+        setup.replay_source = None;
+        *standing = MatchResource::default();
+        writer.write(StartBoard {
+            seed: match_play::board_seed(&setup, 0),
+            first_player: setup.first_player.clone(),
+        });
+        return;
+    }
+
+    for (player, remaining) in saved.player_to_remaining.iter() {
+        clocks
+            .player_to_remaining
+            .insert(player.clone(), *remaining);
+    }
+
+    if let Some(player) = &saved.player_to_move
+        && *state.get() != BoardState::WaitingForMove(player.clone())
+    {
+        warn!(
+            "saved game expected {:?} to move, got {:?}",
+            player,
+            state.get()
+        );
+    }
+}
+
+/// Snapshot of the game to resume, none once the match is over.
+#[allow(clippy::too_many_arguments)]
+pub fn make_saved_game(
+    ui_cards: &Query<(&UiCard, Entity)>,
+    ui_backs: &Query<&UiBack>,
+    board: &BoardResource,
+    setup: &GameSetup,
+    state: &BoardState,
+    history: &MoveHistoryResource,
+    standing: &MatchResource,
+    clocks: &ChessClockResource,
+    replay: &ReplayResource,
+) -> Option<SavedGame> {
+    let mut record = GameRecord {
+        seed: board.seed,
+        board_width: setup.board_width,
+        board_height: setup.board_height,
+        num_colors: setup.num_colors,
+        rules: setup.rules.clone(),
+        player_one: setup.player_one,
+        player_two: setup.player_two,
+        player_one_handicap: setup.player_one_handicap.clone(),
+        player_two_handicap: setup.player_two_handicap.clone(),
+        first_player: board.first_player.clone(),
+        moves: history
+            .moves
+            .iter()
+            .map(|record| record.tile.clone())
+            .collect(),
+        result: None,
+    };
+    let mut player_to_remaining = clocks.player_to_remaining.clone();
+    let mut player_to_move = None;
+    let mut cards = vec![];
+
+    match state {
+        BoardState::Init => return None,
+        BoardState::Victory(_) => {
+            if standing.winner.is_some() {
+                return None;
+            }
+            // between two boards of a match, resume on the next one
+            record.seed = match_play::board_seed(setup, standing.num_played_games);
+            record.first_player = standing.first_player();
+            record.moves.clear();
+            player_to_remaining.clear();
+        }
+        BoardState::WaitingForMove(player) => {
+            player_to_move = Some(player.clone());
+            cards = vec![(Tile::Undef, Player::Undef); setup.board_width * setup.board_height];
+            for (ui_card, card) in ui_cards.iter() {
+                let cell = match replay.live_position() {
+                    Some(snapshot) => snapshot.card(&card),
+                    None => {
+                        let back = board.card_to_backs.get(&card).unwrap();
+                        let player = ui_backs.get(*back).unwrap().player.clone();
+                        (ui_card.tile.clone(), player)
+                    }
+                };
+                cards[ui_card.row * setup.board_width + ui_card.column] = cell;
+            }
+        }
+        BoardState::PlayingMove(_, _) | BoardState::ResolvingMove(_) => {}
+    }
+
+    Some(SavedGame {
+        version: SAVE_VERSION,
+        record,
+        match_seed: setup.seed,
+        num_games: setup.num_games,
+        num_played_games: standing.num_played_games,
+        player_to_wins: standing.player_to_wins.clone(),
+        player_to_total_counts: standing.player_to_total_counts.clone(),
+        player_one_profile: setup.player_one_profile.clone(),
+        player_two_profile: setup.player_two_profile.clone(),
+        ranked: setup.ranked,
+        fog_radius: setup.fog_radius,
+        clock: setup.clock,
+        auto_move_on_timeout: setup.auto_move_on_timeout,
+        player_to_remaining,
+        player_to_move,
+        cards,
+    })
+}
+
+fn write_saved_game(path: &str, saved: Option<SavedGame>) {
+    match saved {
+        Some(saved) => {
+            if let Some(parent) = std::path::Path::new(path).parent()
+                && let Err(err) = std::fs::create_dir_all(parent)
+            {
+                error!("can't create {}: {}", parent.display(), err);
+                return;
+            }
+            match std::fs::write(path, saved.to_string()) {
+                Ok(()) => info!("game saved to {}", path),
+                Err(err) => error!("can't save game to {}: {}", path, err),
+            }
+        }
+        None => {
+            // a finished game has nothing left to resume
+            if std::fs::exists(path).unwrap_or(false) {
+                match std::fs::remove_file(path) {
+                    Ok(()) => info!("removed saved game {}", path),
+                    Err(err) => error!("can't remove saved game {}: {}", path, err),
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_on_demand(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    history: Res<MoveHistoryResource>,
+    standing: Res<MatchResource>,
+    clocks: Res<ChessClockResource>,
+    replay: Res<ReplayResource>,
+    resume: Res<SaveGameResource>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyS) {
+        return;
+    }
+    let Some(path) = &setup.save_path else {
+        warn!("no save file");
+        return;
+    };
+    if resume.pending_resume.is_some() {
+        warn!("still resuming the saved game");
+        return;
+    }
+    let saved = make_saved_game(
+        &ui_cards,
+        &ui_backs,
+        &board,
+        &setup,
+        state.get(),
+        &history,
+        &standing,
+        &clocks,
+        &replay,
+    );
+    if saved.is_none() {
+        info!("game over, nothing to save");
+        return;
+    }
+    write_saved_game(path, saved);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_on_exit(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    history: Res<MoveHistoryResource>,
+    standing: Res<MatchResource>,
+    clocks: Res<ChessClockResource>,
+    replay: Res<ReplayResource>,
+    resume: Res<SaveGameResource>,
+) {
+    let Some(path) = &setup.save_path else {
+        return;
+    };
+    // leaving before the saved game is back keeps the file as it was
+    if resume.pending_resume.is_some() {
+        return;
+    }
+    let saved = make_saved_game(
+        &ui_cards,
+        &ui_backs,
+        &board,
+        &setup,
+        state.get(),
+        &history,
+        &standing,
+        &clocks,
+        &replay,
+    );
+    write_saved_game(path, saved);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::setup::Controller;
+
+    fn saved_game() -> SavedGame {
+        let setup = GameSetup {
+            seed: 0x1234,
+            board_width: 6,
+            board_height: 3,
+            player_two: Controller::Computer,
+            replay_moves: vec![Tile::Red, Tile::Blue, Tile::Green],
+            ..GameSetup::default()
+        };
+        let cards = (0..18)
+            .map(|index| match index % 3 {
+                0 => (Tile::Red, Player::One),
+                1 => (Tile::Blue, Player::Undef),
+                _ => (Tile::Green, Player::Two),
+            })
+            .collect();
+        SavedGame {
+            version: SAVE_VERSION,
+            record: GameRecord::from_setup(&setup),
+            match_seed: 0xabcd,
+            num_games: 3,
+            num_played_games: 1,
+            player_to_wins: BTreeMap::from([(Player::One, 1), (Player::Two, 0)]),
+            player_to_total_counts: BTreeMap::from([(Player::One, 10), (Player::Two, 8)]),
+            player_one_profile: Some("alice".into()),
+            player_two_profile: None,
+            ranked: true,
+            fog_radius: Some(2),
+            clock: Some(ClockSetup::Fischer {
+                total: 300.0,
+                increment: 5.0,
+            }),
+            auto_move_on_timeout: true,
+            player_to_remaining: BTreeMap::from([(Player::One, 281.5), (Player::Two, 299.25)]),
+            player_to_move: Some(Player::Two),
+            cards,
+        }
+    }
+
+    #[test]
+    fn saved_game_round_trips() {
+        let saved = saved_game();
+        assert_eq!(saved.to_string().parse::<SavedGame>(), Ok(saved));
+    }
+
+    #[test]
+    fn saved_game_without_options_round_trips() {
+        let saved = SavedGame {
+            player_one_profile: None,
+            fog_radius: None,
+            clock: None,
+            auto_move_on_timeout: false,
+            player_to_remaining: BTreeMap::new(),
+            player_to_move: None,
+            cards: vec![],
+            ..saved_game()
+        };
+        assert_eq!(saved.to_string().parse::<SavedGame>(), Ok(saved));
+    }
+
+    #[test]
+    fn broken_saves_are_refused() {
+        let record = saved_game().record.to_string();
+        assert!(record.parse::<SavedGame>().is_err());
+        let newer = format!("[Version \"{}\"]\n{}", SAVE_VERSION + 1, record);
+        assert!(newer.parse::<SavedGame>().is_err());
+        let short = format!("[Version \"{SAVE_VERSION}\"]\n[Row \"1R .B\"]\n{record}");
+        assert!(short.parse::<SavedGame>().is_err());
+        let owner = format!("[Version \"{SAVE_VERSION}\"]\n[Row \"3R\"]\n{record}");
+        assert!(owner.parse::<SavedGame>().is_err());
+        let pair = format!("[Version \"{SAVE_VERSION}\"]\n[MatchWins \"1\"]\n{record}");
+        assert!(pair.parse::<SavedGame>().is_err());
+    }
+}
@@ -2,8 +2,11 @@ use bevy::prelude::*;
 
 use super::BoardResource;
 use super::BoardState;
+use super::GameSetup;
 use super::Tile;
+use super::setup::Controller;
 
+use super::setup::Preferences;
 use super::tile::TILE_COLOR_DATA;
 
 use std::collections::BTreeSet;
@@ -20,7 +23,9 @@ pub fn make(
     slicer: &TextureSlicer,
     parent: &mut ChildSpawnerCommands,
     tile: Tile,
+    is_available: bool,
 ) -> Entity {
+    let is_pass = tile == Tile::Undef;
     let ui_select = UiSelectMove {
         tile,
         is_playable: false,
@@ -40,6 +45,11 @@ pub fn make(
         margin: UiRect::all(Val::Px(0.0)),
         border: UiRect::all(Val::Px(0.0)),
         padding: UiRect::all(Val::Px(0.0)),
+        display: if is_available {
+            Display::Flex
+        } else {
+            Display::None
+        },
         ..default()
     },));
 
@@ -62,7 +72,7 @@ pub fn make(
         ));
 
         card.with_children(|parent| {
-            let _button = parent.spawn((
+            let mut button = parent.spawn((
                 ImageNode::from_atlas_image(
                     texture.clone(),
                     TextureAtlas {
@@ -78,9 +88,18 @@ pub fn make(
                     margin: UiRect::all(Val::Px(0.0)),
                     padding: UiRect::all(Val::Px(16.0)),
                     border: UiRect::all(Val::Px(0.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
                     ..default()
                 },
             ));
+            if is_pass {
+                button.with_child((
+                    Text::new("pass"),
+                    TextFont::from_font_size(14.0),
+                    TextColor(fg_color),
+                ));
+            }
         });
 
         card_entity = Some(card.id());
@@ -92,18 +111,26 @@ pub fn make(
 pub fn update(
     mut ui_selects: Query<&mut UiSelectMove>,
     board: Res<BoardResource>,
+    setup: Res<GameSetup>,
     state: Res<State<BoardState>>,
 ) {
-    assert!(board.select_cards.len() == 4);
+    assert!(board.select_cards.len() == 5);
 
-    let playable_tiles = if let BoardState::WaitingForMove(player) = state.get() {
+    let mut playable_tiles = if let BoardState::WaitingForMove(player) = state.get() {
         match board.player_to_playable_tiles.get(player) {
+            Some(_) if setup.controller(player) != Controller::Human => BTreeSet::new(),
             Some(playable_tiles) => playable_tiles.clone(),
             None => BTreeSet::new(),
         }
     } else {
         BTreeSet::new()
     };
+    if let BoardState::WaitingForMove(player) = state.get()
+        && setup.rules.allow_pass
+        && setup.controller(player) == Controller::Human
+    {
+        playable_tiles.insert(Tile::Undef);
+    }
 
     for select_card in board.select_cards.iter() {
         let mut select_card = ui_selects.get_mut(*select_card).unwrap();
@@ -141,7 +168,9 @@ pub fn animate(
     >,
     mut image_nodes: Query<&mut ImageNode>,
     time: Res<Time>,
+    preferences: Res<Preferences>,
 ) {
+    let tile_color_data = preferences.palette.tile_color_data();
     let time = time.elapsed().as_secs_f32();
     let strobe = Hsva::new(360.0 * time.fract(), 0.8, 1.0, 1.0);
     for (ui_select, interaction, children, mut border_color, mut back_color) in
@@ -149,8 +178,8 @@ pub fn animate(
     {
         let tile_index: usize = ui_select.tile.clone().into();
         let tile_index_: usize = if ui_select.is_playable { tile_index } else { 0 };
-        let (_, _, atlas_index) = TILE_COLOR_DATA[tile_index].clone();
-        let (bg_color, fg_color, _) = TILE_COLOR_DATA[tile_index_].clone();
+        let (_, _, atlas_index) = tile_color_data[tile_index];
+        let (bg_color, fg_color, _) = tile_color_data[tile_index_];
         let bg_color: Color = bg_color.into();
         let fg_color: Color =
             if ui_select.is_playable && matches!(interaction, Interaction::Hovered) {
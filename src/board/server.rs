@@ -0,0 +1,650 @@
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::StartBoard;
+use super::Tile;
+use super::card_and_back;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::chat::RateLimit;
+use super::chess_clock;
+use super::chess_clock::ChessClockResource;
+use super::game_record::GameRecord;
+use super::game_result;
+use super::game_result::GameResultResource;
+use super::lobby::GameOffer;
+use super::match_play;
+use super::match_play::MatchResource;
+use super::move_check;
+use super::move_check::MoveSource;
+use super::move_check::RejectedMove;
+use super::network;
+use super::network::Connection;
+use super::network::Message;
+use super::network::handshake;
+use super::network::opponent;
+use super::replay_viewer::ReplayResource;
+use super::setup::Controller;
+use super::utils;
+
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Pace of the headless app, moves only come over the network.
+const REFEREE_TICK: Duration = Duration::from_millis(10);
+
+/// Seconds a seat out of time has to tell of its flag fall.
+const FLAG_GRACE: f32 = 5.0;
+
+/// A client that said hello, waiting to be paired.
+pub struct Seat {
+    connection: Connection,
+    pub name: String,
+}
+
+impl Seat {
+    /// A waiting client has nothing to say until the game starts, anything
+    /// heard from it is a goodbye or a closed connection.
+    pub fn has_left(&self) -> bool {
+        !self.connection.poll().is_empty()
+    }
+}
+
+/// Greet a client of the dedicated server, from another instance with
+/// `--join` or from a browser.
+pub fn greet(stream: TcpStream) -> Result<Seat, String> {
+    let connection = Connection::accept(stream)?;
+    let name = handshake(&connection, None)?;
+    let name = name.unwrap_or(connection.peer.clone());
+    Ok(Seat { connection, name })
+}
+
+/// Run a whole match between two seats with no window, using the board
+/// systems of the game to resolve moves. Every line of the outcome is sent to
+/// `results`, returns once the match is over. Clocks run on the clients, a
+/// flag fall is relayed like a move. They run here as well, a seat that lets
+/// its time run out without saying so loses the match.
+pub fn referee(
+    game_id: usize,
+    mut setup: GameSetup,
+    seats: [Seat; 2],
+    results: mpsc::Sender<String>,
+) {
+    setup.player_one = Controller::Remote;
+    setup.player_two = Controller::Remote;
+    setup.fog_radius = None;
+    setup.save_path = None;
+    setup.record_path = None;
+
+    let [seat_one, seat_two] = seats;
+    // players don't rejoin a game of the server, the token is only handed out
+    let token = network::match_token();
+    for (seat, player) in [(&seat_one, Player::One), (&seat_two, Player::Two)] {
+        let start = Message::Start {
+            seat: player,
+            num_games: setup.num_games,
+            fog_radius: None,
+            clock: setup.clock,
+            token,
+            record: GameRecord::from_setup(&setup),
+        };
+        if let Err(err) = seat.connection.send(&start) {
+            let _ = results.send(format!("game {game_id}: can't start, {err}"));
+            return;
+        }
+    }
+    info!(
+        "game {} {} against {}",
+        game_id, seat_one.name, seat_two.name
+    );
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(REFEREE_TICK)));
+    app.add_plugins(StatesPlugin);
+    app.add_systems(
+        Startup,
+        (
+            populate_board,
+            card_and_back::compute_neighborhoods,
+            utils::start_first_board,
+        )
+            .chain(),
+    );
+    app.add_systems(
+        PreUpdate,
+        (
+            utils::start_board,
+            chess_clock::reset_clocks.run_if(on_event::<StartBoard>),
+            card_and_back::update_counts_and_playable_tiles,
+            receive_moves,
+            run_clocks,
+            forward_move,
+            card_and_back::play_and_resolve_move,
+            card_and_back::update_backs,
+        )
+            .chain(),
+    );
+    for player in [Player::One, Player::Two] {
+        app.add_systems(
+            OnEnter(BoardState::ResolvingMove(player)),
+            chess_clock::press_clock,
+        );
+    }
+    for winner in [Player::One, Player::Two, Player::Undef] {
+        app.add_systems(
+            OnEnter(BoardState::Victory(winner)),
+            report_result
+                .after(game_result::record_result)
+                .after(match_play::score_board),
+        );
+    }
+    app.add_systems(Last, say_goodbye.run_if(on_event::<AppExit>));
+
+    app.add_event::<StartBoard>();
+    app.init_resource::<BoardResource>();
+    app.init_resource::<ChessClockResource>();
+    app.init_resource::<ReplayResource>();
+    app.insert_resource(setup);
+    app.insert_resource(RefereeResource {
+        game_id,
+        player_to_seats: BTreeMap::from([(Player::One, seat_one), (Player::Two, seat_two)]),
+        player_to_pending_moves: BTreeMap::new(),
+        player_to_chat_limits: BTreeMap::new(),
+        claimed_hash: None,
+        results: Mutex::new(results),
+    });
+    app.init_state::<BoardState>();
+    app.add_plugins(match_play::MatchPlayPlugin);
+    app.add_plugins(game_result::GameResultPlugin);
+    app.run();
+}
+
+#[derive(Resource)]
+struct RefereeResource {
+    game_id: usize,
+    player_to_seats: BTreeMap<Player, Seat>,
+    /// Moves and flag falls sent ahead of their turn.
+    player_to_pending_moves: BTreeMap<Player, VecDeque<Message>>,
+    /// Chat is relayed to the other seat, within limits.
+    player_to_chat_limits: BTreeMap<Player, RateLimit>,
+    /// Board the mover claims to have after the move being resolved.
+    claimed_hash: Option<u64>,
+    results: Mutex<mpsc::Sender<String>>,
+}
+
+impl RefereeResource {
+    fn report(&self, line: String) {
+        info!("{}", line);
+        let _ = self.results.lock().unwrap().send(line);
+    }
+
+    /// End the match in favor of the other seat, telling both why.
+    fn forfeit(&self, player: &Player, reason: &str, exit: &mut EventWriter<AppExit>) {
+        let name = &self.player_to_seats[player].name;
+        let winner = &self.player_to_seats[&opponent(player)].name;
+        self.report(format!(
+            "game {}: {} forfeits ({}), {} wins",
+            self.game_id, name, reason, winner
+        ));
+        for seat in self.player_to_seats.values() {
+            let _ = seat
+                .connection
+                .send(&Message::Bye(Some(format!("{name} forfeits: {reason}"))));
+        }
+        exit.write(AppExit::Success);
+    }
+
+    /// Refuse a move of a seat, it goes to the results and loses the match.
+    fn reject(
+        &self,
+        board: &BoardResource,
+        player: &Player,
+        tile: &Tile,
+        reason: &str,
+        exit: &mut EventWriter<AppExit>,
+    ) {
+        let peer = self.player_to_seats[player].connection.peer.clone();
+        let rejected = RejectedMove::new(MoveSource::Peer(peer), board, player, tile, reason);
+        self.report(format!("game {}: {}", self.game_id, rejected));
+        self.forfeit(player, reason, exit);
+    }
+}
+
+/// Cards and backs without any of their looks, the referee only needs the
+/// tiles and owners.
+fn populate_board(mut commands: Commands, mut board: ResMut<BoardResource>, setup: Res<GameSetup>) {
+    for row in 0..setup.board_height {
+        for column in 0..setup.board_width {
+            let back_entity = commands
+                .spawn(UiBack {
+                    player: Player::Undef,
+                })
+                .id();
+            let card_entity = commands
+                .spawn(UiCard {
+                    tile: Tile::Undef,
+                    row,
+                    column,
+                })
+                .id();
+            board.card_to_backs.insert(card_entity, back_entity);
+            if row == 0 && column == 0 {
+                board.player_one_card = Some(card_entity);
+            }
+            if row + 1 == setup.board_height && column + 1 == setup.board_width {
+                board.player_two_card = Some(card_entity);
+            }
+        }
+    }
+}
+
+/// Read both seats and play the move of the seat whose turn it is, once
+/// checked against its playable colors. Leaving or cheating loses the match,
+/// a move out of turn or not playable is also written to the results.
+fn receive_moves(
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    mut next_state: ResMut<NextState<BoardState>>,
+    mut referee: ResMut<RefereeResource>,
+    mut exit: EventWriter<AppExit>,
+    time: Res<Time>,
+) {
+    for player in [Player::One, Player::Two] {
+        for message in referee.player_to_seats[&player].connection.poll() {
+            let reason = match message {
+                Ok(message @ (Message::Move { .. } | Message::Flag)) => {
+                    referee
+                        .player_to_pending_moves
+                        .entry(player.clone())
+                        .or_default()
+                        .push_back(message);
+                    continue;
+                }
+                Ok(message @ (Message::Chat(_) | Message::Emote(_))) => {
+                    // flooding is dropped, not worth a forfeit
+                    let limit = referee
+                        .player_to_chat_limits
+                        .entry(player.clone())
+                        .or_default();
+                    if limit.allow(time.elapsed_secs()) {
+                        let _ = referee.player_to_seats[&opponent(&player)]
+                            .connection
+                            .send(&message);
+                    }
+                    continue;
+                }
+                Ok(Message::Bye(_)) => "left the game".into(),
+                Ok(message) => format!("sent {message}"),
+                Err(err) => err,
+            };
+            referee.forfeit(&player, &reason, &mut exit);
+            return;
+        }
+    }
+
+    let BoardState::WaitingForMove(player) = state.get() else {
+        return;
+    };
+    // the other seat only moves once told about this one, not ahead of it
+    let other_player = opponent(player);
+    if let Some(Message::Move { tile, .. }) = referee
+        .player_to_pending_moves
+        .get(&other_player)
+        .and_then(|moves| moves.front())
+        && let Err(reason) =
+            move_check::check_move(&board, &setup, state.get(), &other_player, tile)
+    {
+        referee.reject(&board, &other_player, tile, &reason, &mut exit);
+        return;
+    }
+    let (tile, hash) = match referee
+        .player_to_pending_moves
+        .get_mut(player)
+        .and_then(|moves| moves.pop_front())
+    {
+        Some(Message::Move { tile, hash }) => (tile, hash),
+        Some(Message::Flag) => {
+            info!("game {} flag fall {:?}", referee.game_id, player);
+            let other_player = opponent(player);
+            if let Err(err) = referee.player_to_seats[&other_player]
+                .connection
+                .send(&Message::Flag)
+            {
+                referee.forfeit(&other_player, &err, &mut exit);
+                return;
+            }
+            next_state.set(BoardState::Victory(other_player));
+            return;
+        }
+        _ => return,
+    };
+    if let Err(reason) = move_check::check_move(&board, &setup, state.get(), player, &tile) {
+        referee.reject(&board, player, &tile, &reason, &mut exit);
+        return;
+    }
+    referee.claimed_hash = Some(hash);
+    next_state.set(BoardState::PlayingMove(player.clone(), tile));
+}
+
+/// Run the clock of the seat to move. Its own flag fall may still be on the
+/// way, past that the seat is taken to be stalling.
+fn run_clocks(
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    mut clocks: ResMut<ChessClockResource>,
+    referee: Res<RefereeResource>,
+    mut exit: EventWriter<AppExit>,
+    time: Res<Time>,
+) {
+    let BoardState::WaitingForMove(player) = state.get() else {
+        return;
+    };
+    if matches!(*next_state, NextState::Pending(_)) {
+        return;
+    }
+    let Some(remaining) = clocks.player_to_remaining.get_mut(player) else {
+        return;
+    };
+    *remaining -= time.delta_secs();
+    if *remaining > -FLAG_GRACE {
+        return;
+    }
+    clocks.flagged_player = Some(player.clone());
+    referee.forfeit(player, "ran out of time", &mut exit);
+}
+
+/// Once resolved, check the mover sees the same board and pass the move on
+/// to the other seat.
+fn forward_move(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    state: Res<State<BoardState>>,
+    mut referee: ResMut<RefereeResource>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let BoardState::ResolvingMove(player) = state.get() else {
+        return;
+    };
+    let Some(claimed_hash) = referee.claimed_hash.take() else {
+        return;
+    };
+    let hash = network::board_hash(&ui_cards, &ui_backs, &board);
+    if hash != claimed_hash {
+        let reason = format!(
+            "board differs after move {}, {:#018x} claimed and {:#018x} played",
+            board.num_resolved_moves + 1,
+            claimed_hash,
+            hash
+        );
+        let tile = board.player_to_last_picks.get(player).unwrap();
+        referee.reject(&board, player, tile, &reason, &mut exit);
+        return;
+    }
+    let tile = board.player_to_last_picks.get(player).unwrap().clone();
+    let other_player = opponent(player);
+    if let Err(err) = referee.player_to_seats[&other_player]
+        .connection
+        .send(&Message::Move { tile, hash })
+    {
+        referee.forfeit(&other_player, &err, &mut exit);
+    }
+}
+
+/// Log every board, and the match once it is decided.
+fn report_result(
+    game_results: Res<GameResultResource>,
+    standing: Res<MatchResource>,
+    referee: Res<RefereeResource>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if let Some(result) = game_results.results.last() {
+        referee.report(format!(
+            "game {} board {}: {}",
+            referee.game_id, standing.num_played_games, result
+        ));
+    }
+    let Some(winner) = &standing.winner else {
+        return;
+    };
+    let wins_one = standing.player_to_wins.get(&Player::One).unwrap_or(&0);
+    let wins_two = standing.player_to_wins.get(&Player::Two).unwrap_or(&0);
+    let name_one = &referee.player_to_seats[&Player::One].name;
+    let name_two = &referee.player_to_seats[&Player::Two].name;
+    let outcome = match winner {
+        Player::One => format!("{name_one} beats {name_two}"),
+        Player::Two => format!("{name_two} beats {name_one}"),
+        Player::Undef => format!("{name_one} and {name_two} draw"),
+    };
+    referee.report(format!(
+        "game {}: {} {}-{}",
+        referee.game_id, outcome, wins_one, wins_two
+    ));
+    exit.write(AppExit::Success);
+}
+
+fn say_goodbye(referee: Res<RefereeResource>) {
+    for seat in referee.player_to_seats.values() {
+        let _ = seat.connection.send(&Message::Bye(None));
+    }
+}
+
+/// Seed of the first board of a game of the server, each game gets its own.
+pub fn game_seed(base_seed: u64, game_id: usize) -> u64 {
+    base_seed.wrapping_add((game_id as u64).wrapping_mul(0x9e3779b97f4a7c15))
+}
+
+/// How often the lobby looks for new clients and what they said.
+const LOBBY_TICK: Duration = Duration::from_millis(50);
+
+/// A client of the lobby, until a game starts for it.
+struct LobbyClient {
+    seat: Seat,
+    /// Hears of every game opened and closed.
+    is_listing: bool,
+    /// Game opened by the client, waiting for an opponent.
+    open_game: Option<(usize, GameOffer)>,
+}
+
+/// Clients list the open games, open one with their own settings, join one
+/// or ask for any opponent. Each game is then refereed on its own thread.
+pub struct Lobby {
+    id_to_clients: BTreeMap<usize, LobbyClient>,
+    next_client_id: usize,
+    next_game_id: usize,
+    /// Settings of the games opened by a quick match.
+    default_offer: GameOffer,
+    base_seed: u64,
+    results: mpsc::Sender<String>,
+}
+
+impl Lobby {
+    pub fn new(default_offer: GameOffer, base_seed: u64, results: mpsc::Sender<String>) -> Self {
+        Self {
+            id_to_clients: BTreeMap::new(),
+            next_client_id: 0,
+            next_game_id: 1,
+            default_offer,
+            base_seed,
+            results,
+        }
+    }
+
+    /// Serve the greeted clients until there can't be any more.
+    pub fn run(mut self, seats: mpsc::Receiver<Seat>) {
+        loop {
+            match seats.recv_timeout(LOBBY_TICK) {
+                Ok(seat) => {
+                    info!("{} entered the lobby", seat.name);
+                    self.id_to_clients.insert(
+                        self.next_client_id,
+                        LobbyClient {
+                            seat,
+                            is_listing: false,
+                            open_game: None,
+                        },
+                    );
+                    self.next_client_id += 1;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            let mut received = vec![];
+            for (client_id, client) in self.id_to_clients.iter() {
+                for message in client.seat.connection.poll() {
+                    received.push((*client_id, message));
+                }
+            }
+            for (client_id, message) in received {
+                // a client may be gone to a game already
+                if self.id_to_clients.contains_key(&client_id) {
+                    self.handle(client_id, message);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, client_id: usize, message: Result<Message, String>) {
+        match message {
+            Ok(Message::List) => {
+                let open_games: Vec<Message> = self
+                    .id_to_clients
+                    .values()
+                    .filter_map(open_message)
+                    .collect();
+                let client = self.id_to_clients.get_mut(&client_id).unwrap();
+                client.is_listing = true;
+                for message in open_games {
+                    let _ = client.seat.connection.send(&message);
+                }
+            }
+            Ok(Message::Create(offer)) => match offer.check() {
+                Ok(()) => self.open_game(client_id, offer),
+                Err(err) => self.leave(client_id, Some(err)),
+            },
+            Ok(Message::Join(game_id)) => {
+                let host_id =
+                    self.id_to_clients
+                        .iter()
+                        .find_map(|(host_id, host)| match &host.open_game {
+                            Some((id, _)) if *id == game_id && *host_id != client_id => {
+                                Some(*host_id)
+                            }
+                            _ => None,
+                        });
+                match host_id {
+                    Some(host_id) => self.start_game(host_id, client_id),
+                    None => {
+                        let client = &self.id_to_clients[&client_id];
+                        let _ = client.seat.connection.send(&Message::Closed(game_id));
+                    }
+                }
+            }
+            Ok(Message::QuickMatch) => {
+                let oldest_game = self
+                    .id_to_clients
+                    .iter()
+                    .filter(|(host_id, _)| **host_id != client_id)
+                    .filter_map(|(host_id, host)| Some((host.open_game.as_ref()?.0, *host_id)))
+                    .min();
+                match oldest_game {
+                    Some((_, host_id)) => self.start_game(host_id, client_id),
+                    None => self.open_game(client_id, self.default_offer.clone()),
+                }
+            }
+            Ok(Message::Closed(game_id)) => self.close_game(client_id, game_id),
+            Ok(Message::Bye(_)) => self.leave(client_id, None),
+            Ok(message) => self.leave(client_id, Some(format!("unexpected message {message}"))),
+            Err(err) => {
+                warn!("lobby client dropped: {}", err);
+                self.leave(client_id, None);
+            }
+        }
+    }
+
+    fn open_game(&mut self, client_id: usize, offer: GameOffer) {
+        let client = self.id_to_clients.get_mut(&client_id).unwrap();
+        if client.open_game.is_some() {
+            return;
+        }
+        let game_id = self.next_game_id;
+        self.next_game_id += 1;
+        info!("{} opened game {} {}", client.seat.name, game_id, offer);
+        client.open_game = Some((game_id, offer));
+        let _ = client.seat.connection.send(&Message::Opened(game_id));
+        let message = open_message(client).unwrap();
+        self.broadcast(&message);
+    }
+
+    /// Give up the game a client opened, one already taken is left alone.
+    fn close_game(&mut self, client_id: usize, game_id: usize) {
+        let client = self.id_to_clients.get_mut(&client_id).unwrap();
+        if !matches!(client.open_game, Some((id, _)) if id == game_id) {
+            return;
+        }
+        info!("{} closed game {}", client.seat.name, game_id);
+        // the host hears of it too, to stop waiting
+        self.broadcast(&Message::Closed(game_id));
+        let client = self.id_to_clients.get_mut(&client_id).unwrap();
+        client.open_game = None;
+    }
+
+    /// Tell the clients in the lobby, those waiting in their own game too.
+    fn broadcast(&self, message: &Message) {
+        for client in self.id_to_clients.values() {
+            if client.is_listing || client.open_game.is_some() {
+                let _ = client.seat.connection.send(message);
+            }
+        }
+    }
+
+    /// Drop a client, saying why when it did something wrong.
+    fn leave(&mut self, client_id: usize, reason: Option<String>) {
+        let client = self.id_to_clients.remove(&client_id).unwrap();
+        info!("{} left the lobby", client.seat.name);
+        if let Some(reason) = reason {
+            warn!("{}: {}", client.seat.name, reason);
+            let _ = client.seat.connection.send(&Message::Bye(Some(reason)));
+        }
+        if let Some((game_id, _)) = client.open_game {
+            self.broadcast(&Message::Closed(game_id));
+        }
+    }
+
+    /// Seat the host of an open game against the client that took it.
+    fn start_game(&mut self, host_id: usize, guest_id: usize) {
+        let host = self.id_to_clients.remove(&host_id).unwrap();
+        let guest = self.id_to_clients.remove(&guest_id).unwrap();
+        let (game_id, offer) = host.open_game.unwrap();
+        self.broadcast(&Message::Closed(game_id));
+        if let Some((guest_game_id, _)) = guest.open_game {
+            self.broadcast(&Message::Closed(guest_game_id));
+        }
+
+        let setup = offer.to_setup(game_seed(self.base_seed, game_id));
+        let results = self.results.clone();
+        std::thread::spawn(move || {
+            referee(game_id, setup, [host.seat, guest.seat], results);
+        });
+    }
+}
+
+fn open_message(client: &LobbyClient) -> Option<Message> {
+    let (id, offer) = client.open_game.as_ref()?;
+    Some(Message::Open {
+        id: *id,
+        host: client.seat.name.clone(),
+        offer: offer.clone(),
+    })
+}
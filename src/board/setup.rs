@@ -0,0 +1,443 @@
+use bevy::prelude::*;
+
+use super::BOARD_HEIGHT;
+use super::BOARD_SEED;
+use super::BOARD_WIDTH;
+use super::Player;
+use super::Tile;
+use super::move_check::MoveSource;
+use super::puzzles::PuzzlePack;
+use super::rules::RuleSet;
+use super::save_game::SavedGame;
+use super::tile::Palette;
+use super::tile::TILE_COLORS;
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum Controller {
+    Human,
+    Computer,
+    /// Moves are posted by a program to the HTTP arena, see `--arena`.
+    Bot,
+    /// Moves come from another game instance over the network.
+    #[value(skip)]
+    Remote,
+    /// Moves come in turn files exchanged with the opponent, see
+    /// `--correspondence`.
+    #[value(skip)]
+    Mail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockSetup {
+    /// Total time per player in seconds, plus seconds added after each move.
+    Fischer { total: f32, increment: f32 },
+    /// Fixed time per move in seconds, unused time is lost.
+    PerMove { seconds: f32 },
+}
+
+impl std::fmt::Display for ClockSetup {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Fischer { total, increment } => write!(ff, "total={total},increment={increment}"),
+            Self::PerMove { seconds } => write!(ff, "per-move={seconds}"),
+        }
+    }
+}
+
+impl std::str::FromStr for ClockSetup {
+    type Err = String;
+
+    /// Parse either `total=300,increment=5` or `per-move=10`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut total = None;
+        let mut increment = 0.0;
+        let mut per_move = None;
+        for item in value.split(',').filter(|item| !item.is_empty()) {
+            let (key, seconds) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got {item:?}"))?;
+            let seconds: f32 = seconds
+                .parse()
+                .map_err(|err| format!("invalid seconds for {key:?}: {err}"))?;
+            match key {
+                "total" => total = Some(seconds),
+                "increment" => increment = seconds,
+                "per-move" => per_move = Some(seconds),
+                _ => return Err(format!("unknown clock setting {key:?}")),
+            }
+        }
+        let clock = match (total, per_move) {
+            (Some(total), None) => Self::Fischer { total, increment },
+            (None, Some(seconds)) => Self::PerMove { seconds },
+            _ => return Err(format!("expected either total or per-move, got {value:?}")),
+        };
+        clock.check()?;
+        Ok(clock)
+    }
+}
+
+impl ClockSetup {
+    /// Refuse clocks that would flag at once or never.
+    pub fn check(&self) -> Result<(), String> {
+        match *self {
+            Self::Fischer { total, increment } => {
+                check_clock_seconds(total, false)?;
+                check_clock_seconds(increment, true)?;
+            }
+            Self::PerMove { seconds } => {
+                check_clock_seconds(seconds, false)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Totals and times per move must be finite and positive, increments may
+/// also be zero.
+fn check_clock_seconds(seconds: f32, may_be_zero: bool) -> Result<f32, String> {
+    let is_valid = seconds.is_finite() && (seconds > 0.0 || may_be_zero && seconds == 0.0);
+    if !is_valid {
+        return Err(format!("invalid clock seconds {seconds}"));
+    }
+    Ok(seconds)
+}
+
+fn parse_clock_value(value: &str, may_be_zero: bool) -> Result<f32, String> {
+    let seconds = value
+        .parse()
+        .map_err(|err| format!("invalid seconds {value:?}: {err}"))?;
+    check_clock_seconds(seconds, may_be_zero)
+}
+
+/// Clock total or time per move, in seconds.
+pub fn parse_clock_seconds(value: &str) -> Result<f32, String> {
+    parse_clock_value(value, false)
+}
+
+/// Seconds added to the clock after each move.
+pub fn parse_clock_increment(value: &str) -> Result<f32, String> {
+    parse_clock_value(value, true)
+}
+
+/// Advantages given to one seat, set before the game starts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Handicap {
+    /// Tiles within this distance of the starting corner, and closer to it than
+    /// to the opponent corner, are owned from the start.
+    pub extra_territory: usize,
+    /// Moves played in a row on the first turn, on top of the regular one.
+    pub extra_moves: usize,
+    /// Times the player may pick the opponent current color.
+    pub color_ban_waivers: usize,
+}
+
+impl Handicap {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for Handicap {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            ff,
+            "territory={},moves={},waivers={}",
+            self.extra_territory, self.extra_moves, self.color_ban_waivers
+        )
+    }
+}
+
+impl std::str::FromStr for Handicap {
+    type Err = String;
+
+    /// Parse a comma separated list of `key=value`, e.g. `territory=2,moves=1`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut handicap = Self::default();
+        for item in value.split(',').filter(|item| !item.is_empty()) {
+            let (key, count) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got {item:?}"))?;
+            let count: usize = count
+                .parse()
+                .map_err(|err| format!("invalid count for {key:?}: {err}"))?;
+            match key {
+                "territory" => handicap.extra_territory = count,
+                "moves" => handicap.extra_moves = count,
+                "waivers" => handicap.color_ban_waivers = count,
+                _ => return Err(format!("unknown handicap {key:?}")),
+            }
+        }
+        if handicap.extra_territory > MAX_EXTRA_TERRITORY {
+            return Err(format!(
+                "extra territory is limited to {MAX_EXTRA_TERRITORY}"
+            ));
+        }
+        Ok(handicap)
+    }
+}
+
+const MAX_EXTRA_TERRITORY: usize = 6;
+
+/// A seed in decimal, or in hex after `0x` as the game records write it.
+pub fn parse_seed(value: &str) -> Result<u64, String> {
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|err| format!("invalid seed {value:?}: {err}"))
+}
+
+/// Bounds on the board, the player blocks need 6 columns and a board needs
+/// at least 3 colors to leave a choice.
+pub fn check_board(width: usize, height: usize, num_colors: usize) -> Result<(), String> {
+    if !(6..=30).contains(&width) || !(3..=15).contains(&height) {
+        return Err(format!("board {width}x{height} is not within 6x3 to 30x15"));
+    }
+    if !(3..=TILE_COLORS.len()).contains(&num_colors) {
+        return Err(format!(
+            "{num_colors} colors is not within 3 to {}",
+            TILE_COLORS.len()
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct GameSetup {
+    pub seed: u64,
+    pub board_width: usize,
+    pub board_height: usize,
+    /// Number of colors dealt on the board, the first ones of `TILE_COLORS`.
+    pub num_colors: usize,
+    /// Number of boards in a match, players take turns moving first.
+    pub num_games: usize,
+    pub player_one: Controller,
+    pub player_two: Controller,
+    pub player_one_handicap: Handicap,
+    pub player_two_handicap: Handicap,
+    /// Local profiles credited with the game result.
+    pub player_one_profile: Option<String>,
+    pub player_two_profile: Option<String>,
+    pub profiles_path: Option<PathBuf>,
+    /// Date of the daily challenge being played.
+    pub daily: Option<String>,
+    pub daily_path: Option<PathBuf>,
+    /// Pack being played in puzzle mode, and the puzzle on the board.
+    pub puzzle_pack: Option<PuzzlePack>,
+    pub puzzle_index: usize,
+    pub puzzles_path: Option<PathBuf>,
+    /// Last turn sent of every correspondence game.
+    pub correspondence_path: Option<PathBuf>,
+    /// Moves refused from another process are appended here.
+    pub rejected_path: Option<PathBuf>,
+    pub rules: RuleSet,
+    /// Ranked games can't be taken back.
+    pub ranked: bool,
+    pub first_player: Player,
+    /// Moves played automatically at the start of the first board.
+    pub replay_moves: Vec<Tile>,
+    /// Where the replayed moves were read from, named when one is refused.
+    pub replay_source: Option<MoveSource>,
+    /// Browse the replayed moves in the replay viewer.
+    pub open_replay_viewer: bool,
+    /// Where the game record is written once the game is over.
+    pub record_path: Option<String>,
+    /// Where the unfinished game is saved on exit or on demand.
+    pub save_path: Option<String>,
+    pub fog_radius: Option<usize>,
+    pub clock: Option<ClockSetup>,
+    pub auto_move_on_timeout: bool,
+    /// Game to pick up where it was left, once its moves are replayed.
+    pub saved_game: Option<SavedGame>,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        Self {
+            seed: BOARD_SEED,
+            board_width: BOARD_WIDTH,
+            board_height: BOARD_HEIGHT,
+            num_colors: 4,
+            num_games: 1,
+            player_one: Controller::Human,
+            player_two: Controller::Human,
+            player_one_handicap: Handicap::default(),
+            player_two_handicap: Handicap::default(),
+            player_one_profile: None,
+            player_two_profile: None,
+            profiles_path: None,
+            daily: None,
+            daily_path: None,
+            puzzle_pack: None,
+            puzzle_index: 0,
+            puzzles_path: None,
+            correspondence_path: None,
+            rejected_path: None,
+            rules: RuleSet::default(),
+            ranked: false,
+            first_player: Player::One,
+            replay_moves: vec![],
+            replay_source: None,
+            open_replay_viewer: false,
+            record_path: None,
+            save_path: None,
+            fog_radius: None,
+            clock: None,
+            auto_move_on_timeout: false,
+            saved_game: None,
+        }
+    }
+}
+
+impl GameSetup {
+    pub fn controller(&self, player: &Player) -> Controller {
+        match player {
+            Player::One => self.player_one,
+            Player::Two => self.player_two,
+            Player::Undef => unreachable!(),
+        }
+    }
+
+    pub fn handicap(&self, player: &Player) -> &Handicap {
+        match player {
+            Player::One => &self.player_one_handicap,
+            Player::Two => &self.player_two_handicap,
+            Player::Undef => unreachable!(),
+        }
+    }
+
+    pub fn profile(&self, player: &Player) -> Option<&String> {
+        match player {
+            Player::One => self.player_one_profile.as_ref(),
+            Player::Two => self.player_two_profile.as_ref(),
+            Player::Undef => unreachable!(),
+        }
+    }
+
+    /// Profile name of the seat, `P1` or `P2` without one.
+    pub fn player_name(&self, player: &Player) -> String {
+        match player {
+            Player::Undef => "??".into(),
+            Player::One => self.player_one_profile.clone().unwrap_or("P1".into()),
+            Player::Two => self.player_two_profile.clone().unwrap_or("P2".into()),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.player_one == Controller::Remote || self.player_two == Controller::Remote
+    }
+
+    /// Both seats play elsewhere, the local instance only follows along.
+    pub fn is_spectating(&self) -> bool {
+        self.player_one == Controller::Remote && self.player_two == Controller::Remote
+    }
+
+    pub fn is_correspondence(&self) -> bool {
+        self.player_one == Controller::Mail || self.player_two == Controller::Mail
+    }
+
+    pub fn is_hot_seat(&self) -> bool {
+        self.player_one == Controller::Human && self.player_two == Controller::Human
+    }
+}
+
+/// How the game looks and sounds to this player. The settings file keeps it
+/// between runs, the board only reads it, except for the muted players.
+#[derive(Resource, Debug, Clone)]
+pub struct Preferences {
+    pub palette: Palette,
+    pub ambience_volume: f32,
+    pub effects_volume: f32,
+    /// Profiles of online opponents whose chat is hidden.
+    pub muted_players: BTreeSet<String>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            palette: Palette::default(),
+            ambience_volume: 1.0,
+            effects_volume: 1.0,
+            muted_players: BTreeSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clocks_round_trip() {
+        for clock in [
+            ClockSetup::Fischer {
+                total: 300.0,
+                increment: 5.0,
+            },
+            ClockSetup::Fischer {
+                total: 90.5,
+                increment: 0.0,
+            },
+            ClockSetup::PerMove { seconds: 10.0 },
+        ] {
+            assert_eq!(clock.to_string().parse(), Ok(clock));
+        }
+        assert_eq!(
+            "total=60".parse(),
+            Ok(ClockSetup::Fischer {
+                total: 60.0,
+                increment: 0.0
+            })
+        );
+        assert!("total=60,per-move=5".parse::<ClockSetup>().is_err());
+        assert!("increment=5".parse::<ClockSetup>().is_err());
+        assert!("total=soon".parse::<ClockSetup>().is_err());
+        for value in [
+            "total=0",
+            "total=-60",
+            "total=inf",
+            "total=60,increment=-1",
+            "total=60,increment=NaN",
+            "per-move=0",
+            "per-move=NaN",
+        ] {
+            assert!(value.parse::<ClockSetup>().is_err(), "{value:?}");
+        }
+        assert!(parse_clock_increment("0").is_ok());
+        assert!(parse_clock_seconds("0").is_err());
+    }
+
+    #[test]
+    fn handicaps_round_trip() {
+        let handicap = Handicap {
+            extra_territory: 2,
+            extra_moves: 1,
+            color_ban_waivers: 3,
+        };
+        assert_eq!(handicap.to_string().parse(), Ok(handicap));
+        assert_eq!("".parse(), Ok(Handicap::default()));
+        assert!("moves=1,luck=2".parse::<Handicap>().is_err());
+        assert!("territory=7".parse::<Handicap>().is_err());
+    }
+
+    #[test]
+    fn seeds() {
+        assert_eq!(parse_seed("42"), Ok(42));
+        assert_eq!(parse_seed("0xab28f3af"), Ok(0xab28f3af));
+        assert!(parse_seed("ab28f3af").is_err());
+        assert!(parse_seed("0x").is_err());
+        assert!(parse_seed("-1").is_err());
+    }
+
+    #[test]
+    fn board_bounds() {
+        assert!(check_board(14, 7, 4).is_ok());
+        assert!(check_board(5, 7, 4).is_err());
+        assert!(check_board(14, 16, 4).is_err());
+        assert!(check_board(14, 7, 2).is_err());
+        assert!(check_board(14, 7, 5).is_err());
+    }
+}
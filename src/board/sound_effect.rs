@@ -4,6 +4,7 @@ use bevy::prelude::*;
 use super::BoardState;
 use super::Player;
 use super::select_move::UiSelectMove;
+use super::setup::Preferences;
 
 use log::info;
 
@@ -56,19 +57,22 @@ fn populate(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
-fn play_ding(mut commands: Commands, sfx: Res<SoundEffectResource>) {
+fn play_ding(mut commands: Commands, sfx: Res<SoundEffectResource>, preferences: Res<Preferences>) {
     info!("!!! ding !!!!");
-    commands.spawn((sfx.ding.clone(), PlaybackSettings::ONCE));
+    let volume = Volume::Linear(preferences.effects_volume);
+    commands.spawn((sfx.ding.clone(), PlaybackSettings::ONCE.with_volume(volume)));
 }
 
-fn play_yeah(mut commands: Commands, sfx: Res<SoundEffectResource>) {
+fn play_yeah(mut commands: Commands, sfx: Res<SoundEffectResource>, preferences: Res<Preferences>) {
     info!("!!! yeah !!!!");
-    commands.spawn((sfx.yeah.clone(), PlaybackSettings::ONCE));
+    let volume = Volume::Linear(preferences.effects_volume);
+    commands.spawn((sfx.yeah.clone(), PlaybackSettings::ONCE.with_volume(volume)));
 }
 
 fn mix_ambience(
     ui_selects: Query<(&UiSelectMove, &Interaction), With<Button>>,
     mut sfx: ResMut<SoundEffectResource>,
+    preferences: Res<Preferences>,
     mut ambiance_aa: Single<&mut AudioSink, (With<SoundAmbianceAA>, Without<SoundAmbianceBB>)>,
     mut ambiance_bb: Single<&mut AudioSink, (Without<SoundAmbianceAA>, With<SoundAmbianceBB>)>,
 ) {
@@ -89,8 +93,10 @@ fn mix_ambience(
         alpha
     };
 
-    ambiance_aa.set_volume(Volume::Decibels(6.0) * Volume::Linear(1.0 - current_ambiance_mix));
-    ambiance_bb.set_volume(Volume::Decibels(0.0) * Volume::Linear(current_ambiance_mix));
+    let volume = Volume::Linear(preferences.ambience_volume);
+    ambiance_aa
+        .set_volume(volume * Volume::Decibels(6.0) * Volume::Linear(1.0 - current_ambiance_mix));
+    ambiance_bb.set_volume(volume * Volume::Decibels(0.0) * Volume::Linear(current_ambiance_mix));
 
     sfx.ambiance_mix = current_ambiance_mix;
 }
@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::chess_clock::ChessClockResource;
+use super::match_play::MatchResource;
+use super::move_history::MoveHistoryResource;
+use super::network;
+use super::network::Connection;
+use super::network::Message;
+use super::network::handshake;
+use super::replay_viewer::ReplayResource;
+use super::save_game;
+
+use std::net::TcpListener;
+use std::sync::Mutex;
+use std::sync::mpsc;
+
+pub struct SpectatorsPlugin;
+
+impl Plugin for SpectatorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpectatorsResource>();
+        app.add_systems(Update, (welcome_spectators, poll_spectators).chain());
+        app.add_systems(OnEnter(BoardState::Victory(Player::One)), broadcast_flag);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Two)), broadcast_flag);
+        app.add_systems(OnEnter(BoardState::Victory(Player::Undef)), broadcast_flag);
+        app.add_systems(Last, say_goodbye.run_if(on_event::<AppExit>));
+    }
+}
+
+struct Spectator {
+    connection: Connection,
+    name: String,
+}
+
+/// Read-only followers of the local match. They get the match as it stands
+/// when they arrive, as a saved game, then every move played.
+#[derive(Resource, Default)]
+pub struct SpectatorsResource {
+    /// Spectators that said hello, waiting for the board to settle.
+    arrivals: Option<Mutex<mpsc::Receiver<Spectator>>>,
+    spectators: Vec<Spectator>,
+}
+
+impl SpectatorsResource {
+    pub fn is_publishing(&self) -> bool {
+        self.arrivals.is_some()
+    }
+
+    fn broadcast(&mut self, message: &Message) {
+        self.spectators
+            .retain(|spectator| match spectator.connection.send(message) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("spectator {} dropped: {}", spectator.name, err);
+                    false
+                }
+            });
+    }
+}
+
+/// Let spectators connect on the given port, from other instances with
+/// `--watch` or from browsers. Each one is greeted on its own thread.
+pub fn publish(setup: &GameSetup, port: u16) -> Result<SpectatorsResource, String> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|err| format!("can't listen on {port}: {err}"))?;
+    eprintln!("publishing the match on port {port}");
+    let name = setup.player_one_profile.clone();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("can't accept a spectator: {}", err);
+                    continue;
+                }
+            };
+            let name = name.clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let greeted = Connection::accept(stream).and_then(|connection| {
+                    let name = handshake(&connection, name)?;
+                    Ok((connection, name))
+                });
+                match greeted {
+                    Ok((connection, name)) => {
+                        let name = name.unwrap_or(connection.peer.clone());
+                        let _ = sender.send(Spectator { connection, name });
+                    }
+                    Err(err) => warn!("spectator turned away: {}", err),
+                }
+            });
+        }
+    });
+    Ok(SpectatorsResource {
+        arrivals: Some(Mutex::new(receiver)),
+        spectators: vec![],
+    })
+}
+
+/// Hand the match to the spectators that arrived, once no move is underway
+/// so that every later move reaches them.
+#[allow(clippy::too_many_arguments)]
+fn welcome_spectators(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    setup: Res<GameSetup>,
+    state: Res<State<BoardState>>,
+    next_state: Res<NextState<BoardState>>,
+    history: Res<MoveHistoryResource>,
+    standing: Res<MatchResource>,
+    clocks: Res<ChessClockResource>,
+    replay: Res<ReplayResource>,
+    mut spectators: ResMut<SpectatorsResource>,
+) {
+    let is_settled = matches!(
+        state.get(),
+        BoardState::WaitingForMove(_) | BoardState::Victory(_)
+    ) && matches!(*next_state, NextState::Unchanged);
+    if !is_settled {
+        return;
+    }
+    let arrivals: Vec<Spectator> = match &spectators.arrivals {
+        Some(arrivals) => arrivals.lock().unwrap().try_iter().collect(),
+        None => return,
+    };
+    if arrivals.is_empty() {
+        return;
+    }
+
+    let saved = save_game::make_saved_game(
+        &ui_cards,
+        &ui_backs,
+        &board,
+        &setup,
+        state.get(),
+        &history,
+        &standing,
+        &clocks,
+        &replay,
+    );
+    for spectator in arrivals {
+        let message = match &saved {
+            Some(saved) => Message::Watch(Box::new(saved.clone())),
+            None => Message::Bye(None),
+        };
+        match spectator.connection.send(&message) {
+            Ok(()) if saved.is_some() => {
+                info!("{} is watching", spectator.name);
+                spectators.spectators.push(spectator);
+            }
+            Ok(()) => info!("{} came after the match", spectator.name),
+            Err(err) => warn!("spectator {} dropped: {}", spectator.name, err),
+        }
+    }
+}
+
+/// Spectators only ever say goodbye, anything else they send is ignored.
+fn poll_spectators(mut spectators: ResMut<SpectatorsResource>) {
+    spectators.spectators.retain(|spectator| {
+        for message in spectator.connection.poll() {
+            match message {
+                Ok(Message::Bye(_)) => {
+                    info!("{} stopped watching", spectator.name);
+                    return false;
+                }
+                Ok(message) => warn!(
+                    "spectator {} can't play, ignoring {}",
+                    spectator.name, message
+                ),
+                Err(err) => {
+                    warn!("spectator {} dropped: {}", spectator.name, err);
+                    return false;
+                }
+            }
+        }
+        true
+    });
+}
+
+/// Pass on every move once resolved, whoever played it.
+pub fn broadcast_move(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    state: Res<State<BoardState>>,
+    mut spectators: ResMut<SpectatorsResource>,
+) {
+    let BoardState::ResolvingMove(player) = state.get() else {
+        return;
+    };
+    if spectators.spectators.is_empty() {
+        return;
+    }
+    spectators.broadcast(&Message::Move {
+        tile: board.player_to_last_picks.get(player).unwrap().clone(),
+        hash: network::board_hash(&ui_cards, &ui_backs, &board),
+    });
+}
+
+/// A lost flag ends the board without a move.
+fn broadcast_flag(clocks: Res<ChessClockResource>, mut spectators: ResMut<SpectatorsResource>) {
+    if clocks.flagged_player.is_some() {
+        spectators.broadcast(&Message::Flag);
+    }
+}
+
+fn say_goodbye(mut spectators: ResMut<SpectatorsResource>) {
+    spectators.broadcast(&Message::Bye(None));
+}
@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+
+use super::BOARD_BLOCK;
+use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
+use super::Player;
+use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::fog_of_war::FogOfWarResource;
+use super::player::PLAYER_COLOR_DATA;
+use super::setup::Preferences;
+use super::tile::Palette;
+
+pub struct SvgExportPlugin;
+
+impl Plugin for SvgExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, export_board);
+    }
+}
+
+const CARD_SIZE: f32 = 64.0;
+const CARD_RADIUS: f32 = 8.0;
+const OUTLINE_WIDTH: f32 = 2.0;
+const TEXT_HEIGHT: f32 = 32.0;
+
+/// What the board shows, enough to draw it without the renderer.
+pub struct BoardPicture {
+    pub width: usize,
+    pub height: usize,
+    /// Tile and owner of every card, row by row.
+    pub cards: Vec<(Tile, Player)>,
+    pub scores: String,
+    pub turn: String,
+}
+
+impl BoardPicture {
+    fn owner(&self, row: isize, column: isize) -> Option<&Player> {
+        if row < 0 || column < 0 || row as usize >= self.height || column as usize >= self.width {
+            return None;
+        }
+        Some(&self.cards[row as usize * self.width + column as usize].1)
+    }
+}
+
+/// Text with the characters that mean something in XML written as entities,
+/// profile names can hold any of them.
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+/// Draw the cards with the palette colors and outline each territory like
+/// `animate_backs`, with the scores above and the turn below.
+pub fn render_svg(picture: &BoardPicture, palette: Palette) -> String {
+    let tile_color_data = palette.tile_color_data();
+    let board_width = picture.width as f32 * BOARD_BLOCK;
+    let board_height = picture.height as f32 * BOARD_BLOCK;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = board_width,
+        h = board_height + 2.0 * TEXT_HEIGHT,
+    );
+    svg += &format!(
+        "<text x=\"4\" y=\"{}\" font-family=\"monospace\" font-size=\"20\">{}</text>\n",
+        TEXT_HEIGHT - 8.0,
+        escape_xml(&picture.scores)
+    );
+
+    for (index, (tile, player)) in picture.cards.iter().enumerate() {
+        let (row, column) = (index / picture.width, index % picture.width);
+        let xx = column as f32 * BOARD_BLOCK;
+        let yy = TEXT_HEIGHT + row as f32 * BOARD_BLOCK;
+
+        let player_index: usize = player.clone().into();
+        let (back_color, outline_color) = PLAYER_COLOR_DATA[player_index];
+        if *player != Player::Undef {
+            svg += &format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                xx,
+                yy,
+                BOARD_BLOCK,
+                BOARD_BLOCK,
+                back_color.to_hex()
+            );
+        }
+
+        let tile_index: usize = tile.clone().into();
+        let (bg_color, fg_color, _) = tile_color_data[tile_index];
+        let margin = (BOARD_BLOCK - CARD_SIZE) / 2.0;
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+            xx + margin,
+            yy + margin,
+            CARD_SIZE,
+            CARD_SIZE,
+            CARD_RADIUS,
+            bg_color.to_hex(),
+            fg_color.to_hex(),
+            OUTLINE_WIDTH
+        );
+
+        // outline the sides facing another owner or the edge of the board
+        if *player == Player::Undef {
+            continue;
+        }
+        let (row, column) = (row as isize, column as isize);
+        let half = OUTLINE_WIDTH / 2.0;
+        let sides = [
+            (
+                (row - 1, column),
+                (xx, yy + half, xx + BOARD_BLOCK, yy + half),
+            ),
+            (
+                (row + 1, column),
+                (
+                    xx,
+                    yy + BOARD_BLOCK - half,
+                    xx + BOARD_BLOCK,
+                    yy + BOARD_BLOCK - half,
+                ),
+            ),
+            (
+                (row, column - 1),
+                (xx + half, yy, xx + half, yy + BOARD_BLOCK),
+            ),
+            (
+                (row, column + 1),
+                (
+                    xx + BOARD_BLOCK - half,
+                    yy,
+                    xx + BOARD_BLOCK - half,
+                    yy + BOARD_BLOCK,
+                ),
+            ),
+        ];
+        for ((next_row, next_column), (x1, y1, x2, y2)) in sides {
+            if picture.owner(next_row, next_column) == Some(player) {
+                continue;
+            }
+            svg += &format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\"/>\n",
+                x1,
+                y1,
+                x2,
+                y2,
+                outline_color.to_hex(),
+                OUTLINE_WIDTH
+            );
+        }
+    }
+
+    svg += &format!(
+        "<text x=\"4\" y=\"{}\" font-family=\"monospace\" font-size=\"20\">{}</text>\n",
+        TEXT_HEIGHT + board_height + TEXT_HEIGHT - 8.0,
+        escape_xml(&picture.turn)
+    );
+    svg += "</svg>\n";
+    svg
+}
+
+/// `E` writes the board as shown to `board-<seed>-<moves>.svg`, cards in the
+/// fog included.
+#[allow(clippy::too_many_arguments)]
+fn export_board(
+    ui_cards: Query<(&UiCard, Entity)>,
+    ui_backs: Query<&UiBack>,
+    board: Res<BoardResource>,
+    fog: Res<FogOfWarResource>,
+    setup: Res<GameSetup>,
+    preferences: Res<Preferences>,
+    state: Res<State<BoardState>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyE) {
+        return;
+    }
+
+    let mut cards = vec![(Tile::Undef, Player::Undef); setup.board_width * setup.board_height];
+    for (ui_card, card) in ui_cards.iter() {
+        // hidden cards look blank and unowned, as on screen
+        if fog.hidden_cards.contains(&card) {
+            continue;
+        }
+        let back = board.card_to_backs.get(&card).unwrap();
+        let player = ui_backs.get(*back).unwrap().player.clone();
+        cards[ui_card.row * setup.board_width + ui_card.column] = (ui_card.tile.clone(), player);
+    }
+
+    let count = |player: &Player| *board.player_to_counts.get(player).unwrap_or(&0);
+    let scores = format!(
+        "{} {} - {} {} - left {}",
+        setup.player_name(&Player::One),
+        count(&Player::One),
+        setup.player_name(&Player::Two),
+        count(&Player::Two),
+        count(&Player::Undef),
+    );
+    let turn = match state.get() {
+        BoardState::Init => "".into(),
+        BoardState::WaitingForMove(player)
+        | BoardState::PlayingMove(player, _)
+        | BoardState::ResolvingMove(player) => format!("{} turn", setup.player_name(player)),
+        BoardState::Victory(Player::Undef) => "Draw".into(),
+        BoardState::Victory(player) => format!("{} wins", setup.player_name(player)),
+    };
+    let picture = BoardPicture {
+        width: setup.board_width,
+        height: setup.board_height,
+        cards,
+        scores,
+        turn,
+    };
+
+    let path = format!("board-{:x}-{}.svg", board.seed, board.num_resolved_moves);
+    match std::fs::write(&path, render_svg(&picture, preferences.palette)) {
+        Ok(()) => info!("board exported to {}", path),
+        Err(err) => error!("can't export board to {}: {}", path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_escaped() {
+        let picture = BoardPicture {
+            width: 1,
+            height: 1,
+            cards: vec![(Tile::Red, Player::One)],
+            scores: "<b>&co 3 - \"x\" 2".into(),
+            turn: "a<b turn".into(),
+        };
+        let svg = render_svg(&picture, Palette::Classic);
+        assert!(svg.contains(">&lt;b&gt;&amp;co 3 - &quot;x&quot; 2</text>"));
+        assert!(svg.contains(">a&lt;b turn</text>"));
+    }
+}
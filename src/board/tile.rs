@@ -1,9 +1,7 @@
 use bevy::color::Srgba;
 use bevy::color::palettes::css::*;
 
-use super::BOARD_HEIGHT;
-use super::BOARD_SEED;
-use super::BOARD_WIDTH;
+use super::GameSetup;
 
 pub const TILE_COLOR_DATA: &[(Srgba, Srgba, usize)] = &[
     (LIGHT_GREY, BLACK, 26),
@@ -13,6 +11,50 @@ pub const TILE_COLOR_DATA: &[(Srgba, Srgba, usize)] = &[
     (LIGHT_YELLOW, ORANGE, 23),
 ];
 
+/// Okabe-Ito colors, told apart with the common color vision deficiencies.
+pub const COLOR_BLIND_TILE_COLOR_DATA: &[(Srgba, Srgba, usize)] = &[
+    (LIGHT_GREY, BLACK, 26),
+    (
+        Srgba::rgb(0.98, 0.82, 0.72),
+        Srgba::rgb(0.835, 0.369, 0.0),
+        25,
+    ),
+    (
+        Srgba::rgb(0.70, 0.90, 0.84),
+        Srgba::rgb(0.0, 0.62, 0.451),
+        0,
+    ),
+    (
+        Srgba::rgb(0.72, 0.85, 0.95),
+        Srgba::rgb(0.0, 0.447, 0.698),
+        27,
+    ),
+    (
+        Srgba::rgb(0.98, 0.96, 0.75),
+        Srgba::rgb(0.60, 0.55, 0.05),
+        23,
+    ),
+];
+
+/// Colors in the order they are dealt, boards with fewer colors use the first ones.
+pub const TILE_COLORS: [Tile; 4] = [Tile::Red, Tile::Green, Tile::Blue, Tile::Yellow];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Palette {
+    #[default]
+    Classic,
+    ColorBlind,
+}
+
+impl Palette {
+    pub fn tile_color_data(&self) -> &'static [(Srgba, Srgba, usize)] {
+        match self {
+            Palette::Classic => TILE_COLOR_DATA,
+            Palette::ColorBlind => COLOR_BLIND_TILE_COLOR_DATA,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Tile {
     Undef,
@@ -35,30 +77,75 @@ impl Into<usize> for Tile {
 }
 
 impl Tile {
-    pub fn from_row_and_column(row: usize, column: usize) -> Self {
-        let ii = row * (BOARD_HEIGHT - 1 - row);
-        let jj = column * (BOARD_WIDTH - 1 - column);
-        let mut seed = BOARD_SEED;
+    /// Single letter used in game records, a pass is written `-`.
+    pub fn to_letter(&self) -> char {
+        match self {
+            Tile::Undef => '-',
+            Tile::Red => 'R',
+            Tile::Green => 'G',
+            Tile::Blue => 'B',
+            Tile::Yellow => 'Y',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            '-' => Some(Tile::Undef),
+            'R' => Some(Tile::Red),
+            'G' => Some(Tile::Green),
+            'B' => Some(Tile::Blue),
+            'Y' => Some(Tile::Yellow),
+            _ => None,
+        }
+    }
+
+    pub fn from_row_and_column(
+        row: usize,
+        column: usize,
+        setup: &GameSetup,
+        mut seed: u64,
+    ) -> Self {
+        // 64 bits on every platform so that the web build deals the same boards
+        let ii = (row * (setup.board_height - 1 - row)) as u64;
+        let jj = (column * (setup.board_width - 1 - column)) as u64;
         for _ in 0..16 {
-            seed ^= ii + 0x9e3779b9 + (seed << 6) + (seed >> 2);
-            seed ^= jj + 0x9e3779b9 + (seed << 6) + (seed >> 2);
+            seed ^= ii
+                .wrapping_add(0x9e3779b9)
+                .wrapping_add(seed << 6)
+                .wrapping_add(seed >> 2);
+            seed ^= jj
+                .wrapping_add(0x9e3779b9)
+                .wrapping_add(seed << 6)
+                .wrapping_add(seed >> 2);
         }
-        if column * 2 < BOARD_WIDTH {
-            match seed % (TILE_COLOR_DATA.len() - 1) {
-                0 => Tile::Red,
-                1 => Tile::Green,
-                2 => Tile::Blue,
-                3 => Tile::Yellow,
-                _ => unreachable!(),
-            }
+        // the right half mirrors the left one with shifted colors
+        let num_colors = setup.num_colors;
+        let index = (seed % num_colors as u64) as usize;
+        let index = if column * 2 < setup.board_width {
+            index
         } else {
-            match seed % (TILE_COLOR_DATA.len() - 1) {
-                2 => Tile::Red,
-                3 => Tile::Green,
-                0 => Tile::Blue,
-                1 => Tile::Yellow,
-                _ => unreachable!(),
-            }
+            (index + num_colors / 2) % num_colors
+        };
+        TILE_COLORS[index].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letters_round_trip() {
+        for tile in [
+            Tile::Undef,
+            Tile::Red,
+            Tile::Green,
+            Tile::Blue,
+            Tile::Yellow,
+        ] {
+            assert_eq!(Tile::from_letter(tile.to_letter()), Some(tile));
         }
+        assert_eq!(Tile::from_letter('r'), None);
+        assert_eq!(Tile::from_letter('X'), None);
     }
 }
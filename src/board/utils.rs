@@ -1,20 +1,34 @@
 use bevy::prelude::*;
 
 use super::BoardResource;
+use super::BoardState;
+use super::GameSetup;
 use super::Player;
+use super::StartBoard;
 use super::Tile;
+use super::card_and_back::UiBack;
+use super::card_and_back::UiCard;
+use super::handicap;
+use super::match_play;
+use super::match_play::MatchResource;
 
 use super::card_and_back;
 use super::player_block;
 use super::select_move;
 
-use super::BOARD_HEIGHT;
-use super::BOARD_WIDTH;
+use super::tile::TILE_COLORS;
+
+use std::path::PathBuf;
+
+/// Root of the cards, player blocks and color picks.
+#[derive(Component)]
+pub struct BoardDiv;
 
 pub fn populate_board(
     mut commands: Commands,
     mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
     mut board: ResMut<BoardResource>,
+    setup: Res<GameSetup>,
     asset_server: Res<AssetServer>,
 ) {
     let texture_border = asset_server.load("textures/border_sheet.png");
@@ -33,14 +47,17 @@ pub fn populate_board(
         max_corner_scale: 1.0,
     };
 
-    let mut body_frame = commands.spawn(Node {
-        width: Val::Percent(100.0),
-        height: Val::Percent(100.0),
-        flex_direction: FlexDirection::Column,
-        align_items: AlignItems::Center,
-        justify_content: JustifyContent::Center,
-        ..default()
-    });
+    let mut body_frame = commands.spawn((
+        BoardDiv,
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+    ));
 
     body_frame.with_children(|parent| {
         player_block::make_pair(
@@ -49,9 +66,10 @@ pub fn populate_board(
             parent,
             Player::One,
             Player::Undef,
+            &setup,
             true,
         );
-        for row in 0..BOARD_HEIGHT {
+        for row in 0..setup.board_height {
             parent
                 .spawn(Node {
                     flex_direction: FlexDirection::Row,
@@ -60,8 +78,8 @@ pub fn populate_board(
                     ..default()
                 })
                 .with_children(|parent| {
-                    for column in 0..BOARD_WIDTH {
-                        let tile = Tile::from_row_and_column(row, column);
+                    for column in 0..setup.board_width {
+                        let tile = Tile::from_row_and_column(row, column, &setup, setup.seed);
                         let (card_entity, back_entity) = card_and_back::make_pair(
                             &texture_border,
                             &atlas_layout_border,
@@ -75,7 +93,7 @@ pub fn populate_board(
                         if row == 0 && column == 0 {
                             board.player_one_card = Some(card_entity);
                         }
-                        if row + 1 == BOARD_HEIGHT && column + 1 == BOARD_WIDTH {
+                        if row + 1 == setup.board_height && column + 1 == setup.board_width {
                             board.player_two_card = Some(card_entity);
                         }
                     }
@@ -87,6 +105,7 @@ pub fn populate_board(
             parent,
             Player::Undef,
             Player::Two,
+            &setup,
             false,
         );
         parent
@@ -100,15 +119,145 @@ pub fn populate_board(
             })
             .with_children(|parent| {
                 board.select_cards.clear();
-                for tile in [Tile::Red, Tile::Green, Tile::Blue, Tile::Yellow] {
+                for tile in [
+                    Tile::Red,
+                    Tile::Green,
+                    Tile::Blue,
+                    Tile::Yellow,
+                    Tile::Undef,
+                ] {
+                    let is_available = match tile {
+                        Tile::Undef => setup.rules.allow_pass,
+                        _ => TILE_COLORS[..setup.num_colors].contains(&tile),
+                    };
                     board.select_cards.push(select_move::make(
                         &texture_border,
                         &atlas_layout_border,
                         &slicer,
                         parent,
                         tile,
+                        is_available,
                     ));
                 }
             });
     });
 }
+
+/// Deal the board again once the game setup changed after startup, when a
+/// game of the lobby starts.
+pub fn rebuild_board(world: &mut World) {
+    let board_divs: Vec<Entity> = world
+        .query_filtered::<Entity, With<BoardDiv>>()
+        .iter(world)
+        .collect();
+    for board_div in board_divs {
+        world.entity_mut(board_div).despawn();
+    }
+    *world.resource_mut::<BoardResource>() = BoardResource::default();
+    *world.resource_mut::<MatchResource>() = MatchResource::default();
+    let _ = world.run_system_cached(populate_board);
+    let _ = world.run_system_cached(card_and_back::compute_neighborhoods);
+    let _ = world.run_system_cached(start_first_board);
+}
+
+pub fn start_first_board(
+    mut writer: EventWriter<StartBoard>,
+    setup: Res<GameSetup>,
+    standing: Res<MatchResource>,
+) {
+    // a resumed match picks up at its current board
+    writer.write(StartBoard {
+        seed: match_play::board_seed(&setup, standing.num_played_games),
+        first_player: setup.first_player.clone(),
+    });
+}
+
+pub fn start_board(
+    mut events: EventReader<StartBoard>,
+    mut ui_cards: Query<&mut UiCard>,
+    mut ui_backs: Query<&mut UiBack>,
+    mut board: ResMut<BoardResource>,
+    setup: Res<GameSetup>,
+    mut next_state: ResMut<NextState<BoardState>>,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+    info!("start board {:#x} {:?}", event.seed, event.first_player);
+
+    for mut ui_card in ui_cards.iter_mut() {
+        ui_card.tile = Tile::from_row_and_column(ui_card.row, ui_card.column, &setup, event.seed);
+    }
+    for mut ui_back in ui_backs.iter_mut() {
+        ui_back.player = Player::Undef;
+    }
+
+    board.seed = event.seed;
+    board.first_player = event.first_player.clone();
+    board.player_to_counts.clear();
+    board.player_to_playable_tiles.clear();
+    board.player_to_previous_tiles.clear();
+    board.player_to_last_picks.clear();
+    board.num_consecutive_passes = 0;
+    board.num_resolved_moves = 0;
+    handicap::apply_handicaps(&mut ui_cards, &mut board, &setup);
+
+    next_state.set(BoardState::WaitingForMove(event.first_player.clone()));
+}
+
+/// A file of the user config directory the game keeps up to date, e.g. the
+/// profiles or the puzzle progress.
+#[derive(Default)]
+pub struct ConfigFile {
+    /// What the file holds, for the log.
+    what: &'static str,
+    /// Cleared when the file can't be read, so that it isn't overwritten.
+    path: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Read the file with `parse`, a missing file holds the default.
+    pub fn load<T: Default>(
+        what: &'static str,
+        path: &Option<PathBuf>,
+        parse: impl Fn(&str) -> (T, Vec<String>),
+    ) -> (Self, T) {
+        let mut file = Self {
+            what,
+            path: path.clone(),
+        };
+        let Some(path) = path else {
+            return (file, T::default());
+        };
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                let (value, errors) = parse(&content);
+                for err in errors {
+                    warn!("{} {}: {}", what, path.display(), err);
+                }
+                (file, value)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (file, T::default()),
+            Err(err) => {
+                error!("can't read {} {}: {}", what, path.display(), err);
+                file.path = None;
+                (file, T::default())
+            }
+        }
+    }
+
+    pub fn store(&self, content: &str) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(parent) = path.parent()
+            && let Err(err) = std::fs::create_dir_all(parent)
+        {
+            error!("can't create {}: {}", parent.display(), err);
+            return;
+        }
+        if let Err(err) = std::fs::write(path, content) {
+            error!("can't write {} to {}: {}", self.what, path.display(), err);
+        }
+    }
+}
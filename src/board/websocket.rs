@@ -0,0 +1,788 @@
+//! JSON messages over WebSocket, so that the web build can play online
+
+use super::game_record::parse_player_name;
+use super::game_record::player_name;
+use super::network::Message;
+use super::network::parse_hash;
+use super::network::parse_tile;
+use super::network::parse_token;
+
+use std::collections::BTreeMap;
+
+/// Flat JSON values, enough for the messages.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::from('"');
+    for cc in value.chars() {
+        match cc {
+            '"' => escaped += "\\\"",
+            '\\' => escaped += "\\\\",
+            '\n' => escaped += "\\n",
+            '\r' => escaped += "\\r",
+            '\t' => escaped += "\\t",
+            cc if cc.is_control() => escaped += &format!("\\u{:04x}", cc as u32),
+            cc => escaped.push(cc),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Write a flat object, keys in the given order.
+pub fn format_json_object(fields: &[(&str, JsonValue)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                JsonValue::Null => "null".into(),
+                JsonValue::Bool(value) => value.to_string(),
+                JsonValue::Number(value) => value.to_string(),
+                JsonValue::String(value) => json_string(value),
+            };
+            format!("{}:{}", json_string(key), value)
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Parse an object whose values are strings, numbers, booleans or null.
+pub fn parse_json_object(text: &str) -> Result<BTreeMap<String, JsonValue>, String> {
+    let mut chars = text.trim().chars().peekable();
+    let mut fields = BTreeMap::new();
+
+    let skip_spaces = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.peek().is_some_and(|cc| cc.is_whitespace()) {
+            chars.next();
+        }
+    };
+    let parse_string =
+        |chars: &mut std::iter::Peekable<std::str::Chars>| -> Result<String, String> {
+            if chars.next() != Some('"') {
+                return Err("expected a string".into());
+            }
+            let mut value = String::new();
+            loop {
+                match chars.next().ok_or("unterminated string")? {
+                    '"' => return Ok(value),
+                    '\\' => match chars.next().ok_or("unterminated string")? {
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        't' => value.push('\t'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'u' => {
+                            let hex: String = chars.by_ref().take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16)
+                                .map_err(|err| format!("invalid escape {hex:?}: {err}"))?;
+                            value.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        cc => value.push(cc),
+                    },
+                    cc => value.push(cc),
+                }
+            }
+        };
+
+    if chars.next() != Some('{') {
+        return Err("expected an object".into());
+    }
+    skip_spaces(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_spaces(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_spaces(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("expected ':' after {key:?}"));
+            }
+            skip_spaces(&mut chars);
+            let value = match chars.peek() {
+                Some('"') => JsonValue::String(parse_string(&mut chars)?),
+                Some(_) => {
+                    let mut word = String::new();
+                    while let Some(cc) = chars.peek()
+                        && !matches!(cc, ',' | '}')
+                        && !cc.is_whitespace()
+                    {
+                        word.push(*cc);
+                        chars.next();
+                    }
+                    match word.as_str() {
+                        "null" => JsonValue::Null,
+                        "true" => JsonValue::Bool(true),
+                        "false" => JsonValue::Bool(false),
+                        _ => JsonValue::Number(
+                            word.parse()
+                                .map_err(|err| format!("invalid value {word:?}: {err}"))?,
+                        ),
+                    }
+                }
+                None => return Err("unterminated object".into()),
+            };
+            fields.insert(key, value);
+            skip_spaces(&mut chars);
+            match chars.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err("expected ',' or '}'".into()),
+            }
+        }
+    }
+    skip_spaces(&mut chars);
+    if chars.next().is_some() {
+        return Err("trailing characters after the object".into());
+    }
+    Ok(fields)
+}
+
+pub fn get_string<'a>(
+    fields: &'a BTreeMap<String, JsonValue>,
+    key: &str,
+) -> Result<Option<&'a String>, String> {
+    match fields.get(key) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::String(value)) => Ok(Some(value)),
+        Some(value) => Err(format!("expected a string for {key:?}, got {value:?}")),
+    }
+}
+
+pub fn get_count(fields: &BTreeMap<String, JsonValue>, key: &str) -> Result<Option<usize>, String> {
+    match fields.get(key) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(JsonValue::Number(value)) if *value >= 0.0 && value.fract() == 0.0 => {
+            Ok(Some(*value as usize))
+        }
+        Some(value) => Err(format!("expected a count for {key:?}, got {value:?}")),
+    }
+}
+
+/// The same messages as the line protocol, one object per text frame, e.g.
+///
+/// ```text
+/// {"type":"hello","version":1,"name":"alice"}
+/// {"type":"start","seat":"P2","num_games":1,"fog_radius":null,"clock":null,"token":"0x9d2c5680e41b3f77","record":"[Board \"14x7\"]\n..."}
+/// {"type":"move","tile":"R","hash":"0x3b9f6a2c81d4e057"}
+/// {"type":"resume","num_moves":12}
+/// {"type":"rejoin","token":"0x9d2c5680e41b3f77"}
+/// {"type":"flag"}
+/// {"type":"bye","reason":null}
+/// {"type":"watch","saved":"[Version \"1\"]\n..."}
+/// {"type":"chat","text":"nice opening"}
+/// {"type":"emote","text":"gg"}
+/// {"type":"list"}
+/// {"type":"open","id":3,"host":"alice","offer":"14x7 4 1 standard -"}
+/// {"type":"closed","id":3}
+/// {"type":"opened","id":3}
+/// {"type":"create","offer":"14x7 4 1 standard -"}
+/// {"type":"join","id":3}
+/// {"type":"quick"}
+/// ```
+impl Message {
+    pub fn to_json(&self) -> String {
+        let text = |value: &str| JsonValue::String(value.into());
+        let count = |value: usize| JsonValue::Number(value as f64);
+        match self {
+            Self::Hello { version, name } => format_json_object(&[
+                ("type", text("hello")),
+                ("version", count(*version as usize)),
+                ("name", name.as_deref().map(text).unwrap_or(JsonValue::Null)),
+            ]),
+            Self::Start {
+                seat,
+                num_games,
+                fog_radius,
+                clock,
+                token,
+                record,
+            } => format_json_object(&[
+                ("type", text("start")),
+                ("seat", text(player_name(seat))),
+                ("num_games", count(*num_games)),
+                (
+                    "fog_radius",
+                    fog_radius.map(count).unwrap_or(JsonValue::Null),
+                ),
+                (
+                    "clock",
+                    clock
+                        .map(|clock| text(&clock.to_string()))
+                        .unwrap_or(JsonValue::Null),
+                ),
+                ("token", text(&format!("{token:#018x}"))),
+                ("record", text(&record.to_string())),
+            ]),
+            Self::Move { tile, hash } => format_json_object(&[
+                ("type", text("move")),
+                ("tile", text(&tile.to_letter().to_string())),
+                // as text, JSON numbers don't hold 64 bits
+                ("hash", text(&format!("{hash:#018x}"))),
+            ]),
+            Self::Resume(num_moves) => {
+                format_json_object(&[("type", text("resume")), ("num_moves", count(*num_moves))])
+            }
+            Self::Rejoin(token) => format_json_object(&[
+                ("type", text("rejoin")),
+                ("token", text(&format!("{token:#018x}"))),
+            ]),
+            Self::Flag => format_json_object(&[("type", text("flag"))]),
+            Self::Bye(reason) => format_json_object(&[
+                ("type", text("bye")),
+                (
+                    "reason",
+                    reason.as_deref().map(text).unwrap_or(JsonValue::Null),
+                ),
+            ]),
+            Self::Watch(saved) => {
+                format_json_object(&[("type", text("watch")), ("saved", text(&saved.to_string()))])
+            }
+            Self::Chat(value) => {
+                format_json_object(&[("type", text("chat")), ("text", text(value))])
+            }
+            Self::Emote(value) => {
+                format_json_object(&[("type", text("emote")), ("text", text(value))])
+            }
+            Self::List => format_json_object(&[("type", text("list"))]),
+            Self::Open { id, host, offer } => format_json_object(&[
+                ("type", text("open")),
+                ("id", count(*id)),
+                ("host", text(host)),
+                ("offer", text(&offer.to_string())),
+            ]),
+            Self::Closed(id) => format_json_object(&[("type", text("closed")), ("id", count(*id))]),
+            Self::Opened(id) => format_json_object(&[("type", text("opened")), ("id", count(*id))]),
+            Self::Create(offer) => format_json_object(&[
+                ("type", text("create")),
+                ("offer", text(&offer.to_string())),
+            ]),
+            Self::Join(id) => format_json_object(&[("type", text("join")), ("id", count(*id))]),
+            Self::QuickMatch => format_json_object(&[("type", text("quick"))]),
+        }
+    }
+
+    pub fn from_json(value: &str) -> Result<Self, String> {
+        let fields = parse_json_object(value)?;
+        let kind = get_string(&fields, "type")?.ok_or("missing message type")?;
+        match kind.as_str() {
+            "hello" => Ok(Self::Hello {
+                version: get_count(&fields, "version")?
+                    .ok_or("missing version")?
+                    .try_into()
+                    .map_err(|_| "version out of range")?,
+                name: get_string(&fields, "name")?.cloned(),
+            }),
+            "start" => Ok(Self::Start {
+                seat: parse_player_name(get_string(&fields, "seat")?.ok_or("missing seat")?)?,
+                num_games: get_count(&fields, "num_games")?.unwrap_or(1),
+                fog_radius: get_count(&fields, "fog_radius")?,
+                clock: get_string(&fields, "clock")?
+                    .map(|clock| clock.parse())
+                    .transpose()?,
+                token: parse_token(get_string(&fields, "token")?.ok_or("missing token")?)?,
+                record: get_string(&fields, "record")?
+                    .ok_or("missing record")?
+                    .parse()?,
+            }),
+            "move" => Ok(Self::Move {
+                tile: parse_tile(get_string(&fields, "tile")?.ok_or("missing tile")?)?,
+                hash: parse_hash(get_string(&fields, "hash")?.ok_or("missing hash")?)?,
+            }),
+            "resume" => Ok(Self::Resume(
+                get_count(&fields, "num_moves")?.ok_or("missing number of moves")?,
+            )),
+            "rejoin" => Ok(Self::Rejoin(parse_token(
+                get_string(&fields, "token")?.ok_or("missing token")?,
+            )?)),
+            "flag" => Ok(Self::Flag),
+            "bye" => Ok(Self::Bye(get_string(&fields, "reason")?.cloned())),
+            "watch" => Ok(Self::Watch(Box::new(
+                get_string(&fields, "saved")?
+                    .ok_or("missing saved game")?
+                    .parse()?,
+            ))),
+            "chat" => Ok(Self::Chat(
+                get_string(&fields, "text")?.ok_or("missing text")?.clone(),
+            )),
+            "emote" => Ok(Self::Emote(
+                get_string(&fields, "text")?.ok_or("missing text")?.clone(),
+            )),
+            "list" => Ok(Self::List),
+            "open" => Ok(Self::Open {
+                id: get_count(&fields, "id")?.ok_or("missing game id")?,
+                host: get_string(&fields, "host")?.ok_or("missing host")?.clone(),
+                offer: get_string(&fields, "offer")?
+                    .ok_or("missing game offer")?
+                    .parse()?,
+            }),
+            "closed" => Ok(Self::Closed(
+                get_count(&fields, "id")?.ok_or("missing game id")?,
+            )),
+            "opened" => Ok(Self::Opened(
+                get_count(&fields, "id")?.ok_or("missing game id")?,
+            )),
+            "create" => Ok(Self::Create(
+                get_string(&fields, "offer")?
+                    .ok_or("missing game offer")?
+                    .parse()?,
+            )),
+            "join" => Ok(Self::Join(
+                get_count(&fields, "id")?.ok_or("missing game id")?,
+            )),
+            "quick" => Ok(Self::QuickMatch),
+            _ => Err(format!("unknown message {kind:?}")),
+        }
+    }
+}
+
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xa;
+
+/// Frames, and messages put together from frames, larger than this are
+/// refused, messages are small.
+const MAX_PAYLOAD_LEN: u64 = 1 << 20;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut hh: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut ww = [0u32; 80];
+        for (index, word) in chunk.chunks(4).enumerate() {
+            ww[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            ww[index] =
+                (ww[index - 3] ^ ww[index - 8] ^ ww[index - 14] ^ ww[index - 16]).rotate_left(1);
+        }
+        let [mut aa, mut bb, mut cc, mut dd, mut ee] = hh;
+        for (index, word) in ww.iter().enumerate() {
+            let (ff, kk) = match index {
+                0..=19 => ((bb & cc) | (!bb & dd), 0x5a827999),
+                20..=39 => (bb ^ cc ^ dd, 0x6ed9eba1),
+                40..=59 => ((bb & cc) | (bb & dd) | (cc & dd), 0x8f1bbcdc),
+                _ => (bb ^ cc ^ dd, 0xca62c1d6),
+            };
+            let temp = aa
+                .rotate_left(5)
+                .wrapping_add(ff)
+                .wrapping_add(ee)
+                .wrapping_add(kk)
+                .wrapping_add(*word);
+            ee = dd;
+            dd = cc;
+            cc = bb.rotate_left(30);
+            bb = aa;
+            aa = temp;
+        }
+        for (value, added) in hh.iter_mut().zip([aa, bb, cc, dd, ee]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (index, value) in hh.iter().enumerate() {
+        digest[index * 4..index * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Value of `Sec-WebSocket-Accept` answering the key of the client.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(
+        format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes(),
+    ))
+}
+
+/// Read the HTTP upgrade request byte by byte, so that nothing past it is
+/// taken from the socket, and answer it.
+pub fn accept(
+    reader: &mut impl std::io::Read,
+    writer: &mut impl std::io::Write,
+) -> Result<(), String> {
+    let mut request = vec![];
+    let mut byte = [0u8];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() > 8192 {
+            return Err("request too large".into());
+        }
+        reader
+            .read_exact(&mut byte)
+            .map_err(|err| format!("can't read request: {err}"))?;
+        request.push(byte[0]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let key = request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-key"))
+        .map(|(_, value)| value.trim().to_string())
+        .ok_or("not a WebSocket request")?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    );
+    writer
+        .write_all(response.as_bytes())
+        .map_err(|err| format!("can't answer request: {err}"))
+}
+
+/// Read one frame, unmasking what the client sends, giving back whether it
+/// is the last of a message along with its opcode and payload.
+pub fn read_frame(reader: &mut impl std::io::Read) -> Result<(bool, u8, Vec<u8>), String> {
+    let mut header = [0u8; 2];
+    reader
+        .read_exact(&mut header)
+        .map_err(|err| format!("can't read frame: {err}"))?;
+    let is_final = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    let is_masked = header[1] & 0x80 != 0;
+
+    let mut read_bytes = |count: usize| -> Result<Vec<u8>, String> {
+        let mut bytes = vec![0u8; count];
+        reader
+            .read_exact(&mut bytes)
+            .map_err(|err| format!("can't read frame: {err}"))?;
+        Ok(bytes)
+    };
+    let payload_len = match header[1] & 0x7f {
+        126 => u16::from_be_bytes(read_bytes(2)?.try_into().unwrap()) as u64,
+        127 => u64::from_be_bytes(read_bytes(8)?.try_into().unwrap()),
+        len => len as u64,
+    };
+    if payload_len > MAX_PAYLOAD_LEN {
+        return Err(format!("frame of {payload_len} bytes is too large"));
+    }
+    let mask = if is_masked {
+        read_bytes(4)?
+    } else {
+        vec![0; 4]
+    };
+    let mut payload = read_bytes(payload_len as usize)?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+    Ok((is_final, opcode, payload))
+}
+
+/// Write a single unmasked frame, as a server does.
+pub fn write_frame(
+    writer: &mut impl std::io::Write,
+    opcode: u8,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+/// Read messages until the socket closes, answering pings along the way.
+pub fn read_messages(
+    reader: &mut impl std::io::Read,
+    mut reply: impl FnMut(u8, &[u8]),
+    mut received: impl FnMut(Result<Message, String>) -> bool,
+) {
+    let mut message = vec![];
+    loop {
+        let (is_final, opcode, payload) = match read_frame(reader) {
+            Ok(frame) => frame,
+            Err(err) => {
+                received(Err(err));
+                return;
+            }
+        };
+        match opcode {
+            OPCODE_TEXT | OPCODE_CONTINUATION => {
+                let message_len = (message.len() + payload.len()) as u64;
+                if message_len > MAX_PAYLOAD_LEN {
+                    received(Err(format!("message of {message_len} bytes is too large")));
+                    return;
+                }
+                message.extend_from_slice(&payload);
+                if !is_final {
+                    continue;
+                }
+                let text = String::from_utf8_lossy(&message).to_string();
+                message.clear();
+                let parsed = Message::from_json(&text);
+                let is_err = parsed.is_err();
+                if !received(parsed) || is_err {
+                    return;
+                }
+            }
+            OPCODE_PING => reply(OPCODE_PONG, &payload),
+            OPCODE_PONG => {}
+            OPCODE_CLOSE => {
+                reply(OPCODE_CLOSE, &[]);
+                received(Err("connection closed".into()));
+                return;
+            }
+            _ => {
+                received(Err(format!("unexpected frame {opcode:#x}")));
+                return;
+            }
+        }
+    }
+}
+
+/// Browser side, the socket is driven by JavaScript callbacks.
+#[cfg(target_family = "wasm")]
+pub mod browser {
+    use super::super::network::Message;
+
+    use std::sync::mpsc;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen::closure::Closure;
+
+    thread_local! {
+        static SOCKET: std::cell::RefCell<Option<web_sys::WebSocket>> =
+            const { std::cell::RefCell::new(None) };
+    }
+
+    /// Address given with `?join=ws://host:port` in the page address.
+    pub fn join_address() -> Option<String> {
+        let search = web_sys::window()?.location().search().ok()?;
+        web_sys::UrlSearchParams::new_with_str(&search)
+            .ok()?
+            .get("join")
+    }
+
+    pub fn send(message: &Message) -> Result<(), String> {
+        SOCKET.with(|socket| match &*socket.borrow() {
+            Some(socket) => socket
+                .send_with_str(&message.to_json())
+                .map_err(|err| format!("can't send: {err:?}")),
+            None => Err("not connected".into()),
+        })
+    }
+
+    /// Open the socket, messages are handed to the receiver as they arrive.
+    pub async fn connect(address: &str) -> Result<mpsc::Receiver<Result<Message, String>>, String> {
+        let socket = web_sys::WebSocket::new(address)
+            .map_err(|err| format!("can't connect to {address}: {err:?}"))?;
+        let (sender, receiver) = mpsc::channel();
+
+        let on_message_sender = sender.clone();
+        let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(
+            move |event: web_sys::MessageEvent| {
+                let message = match event.data().as_string() {
+                    Some(text) => Message::from_json(&text),
+                    None => Err("expected a text message".into()),
+                };
+                let _ = on_message_sender.send(message);
+            },
+        );
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+
+        let on_close =
+            Closure::<dyn FnMut(web_sys::CloseEvent)>::new(move |_: web_sys::CloseEvent| {
+                let _ = sender.send(Err("connection closed".into()));
+            });
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        on_close.forget();
+
+        // wait for the socket to open
+        while socket.ready_state() == web_sys::WebSocket::CONNECTING {
+            sleep(50).await;
+        }
+        if socket.ready_state() != web_sys::WebSocket::OPEN {
+            return Err(format!("can't connect to {address}"));
+        }
+        SOCKET.with(|cell| *cell.borrow_mut() = Some(socket));
+        Ok(receiver)
+    }
+
+    pub async fn sleep(milliseconds: i32) {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            if let Some(window) = web_sys::window() {
+                let _ = window
+                    .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, milliseconds);
+            }
+        });
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::network::tests::every_message;
+    use super::*;
+
+    /// A frame as a browser sends it, masked.
+    fn client_frame(is_final: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        assert!(payload.len() < 126);
+        let mut frame = vec![if is_final { 0x80 } else { 0 } | opcode];
+        frame.push(0x80 | payload.len() as u8);
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            payload
+                .iter()
+                .enumerate()
+                .map(|(index, byte)| byte ^ mask[index % 4]),
+        );
+        frame
+    }
+
+    /// Messages and replies out of `read_messages` until the stream ends.
+    fn read_all(stream: &[u8]) -> (Vec<Result<Message, String>>, Vec<(u8, Vec<u8>)>) {
+        let mut replies = vec![];
+        let mut messages = vec![];
+        read_messages(
+            &mut &stream[..],
+            |opcode, payload| replies.push((opcode, payload.to_vec())),
+            |message| {
+                messages.push(message);
+                true
+            },
+        );
+        (messages, replies)
+    }
+
+    #[test]
+    fn hashes_and_encodings() {
+        assert_eq!(
+            sha1(b"abc"),
+            [
+                0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50,
+                0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d
+            ]
+        );
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        // the example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for message in every_message() {
+            let json = message.to_json();
+            assert!(!json.contains('\n'), "{json:?}");
+            assert_eq!(Message::from_json(&json), Ok(message));
+        }
+    }
+
+    #[test]
+    fn broken_messages_are_refused() {
+        for json in [
+            "",
+            "{}",
+            "{\"type\":\"shout\"}",
+            "{\"type\":\"hello\"}",
+            "{\"type\":\"hello\",\"version\":4294967296}",
+            "{\"type\":\"hello\",\"version\":-1}",
+            "{\"type\":\"move\",\"tile\":\"R\"}",
+            "{\"type\":\"join\",\"id\":\"3\"}",
+        ] {
+            assert!(Message::from_json(json).is_err(), "{json:?}");
+        }
+    }
+
+    #[test]
+    fn masked_and_fragmented_frames() {
+        let flag = Message::Flag.to_json();
+        let chat = Message::Chat("well played".into()).to_json();
+        let (first, second) = chat.split_at(chat.len() / 2);
+        let mut stream = client_frame(true, OPCODE_TEXT, flag.as_bytes());
+        stream.extend(client_frame(false, OPCODE_TEXT, first.as_bytes()));
+        stream.extend(client_frame(true, OPCODE_PING, b"ping"));
+        stream.extend(client_frame(true, OPCODE_CONTINUATION, second.as_bytes()));
+        stream.extend(client_frame(true, OPCODE_CLOSE, b""));
+
+        let (messages, replies) = read_all(&stream);
+        assert_eq!(
+            messages,
+            vec![
+                Ok(Message::Flag),
+                Ok(Message::Chat("well played".into())),
+                Err("connection closed".into()),
+            ]
+        );
+        assert_eq!(
+            replies,
+            vec![(OPCODE_PONG, b"ping".to_vec()), (OPCODE_CLOSE, vec![])]
+        );
+    }
+
+    #[test]
+    fn endless_fragments_are_refused() {
+        let fragment = vec![b' '; MAX_PAYLOAD_LEN as usize / 2 + 1];
+        let mut stream = vec![];
+        for opcode in [OPCODE_TEXT, OPCODE_CONTINUATION] {
+            let start = stream.len();
+            write_frame(&mut stream, opcode, &fragment).unwrap();
+            // not the final fragment
+            stream[start] &= 0x7f;
+        }
+        let (messages, _) = read_all(&stream);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].as_ref().unwrap_err().contains("too large"));
+    }
+
+    #[test]
+    fn server_frames_read_back() {
+        let mut stream = vec![];
+        let long_chat = Message::Chat("x".repeat(200));
+        write_frame(&mut stream, OPCODE_TEXT, long_chat.to_json().as_bytes()).unwrap();
+        let (messages, _) = read_all(&stream);
+        assert_eq!(messages[0], Ok(long_chat));
+        assert!(messages[1].is_err());
+    }
+}
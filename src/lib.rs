@@ -0,0 +1,4 @@
+//! board game, shared by the game and the dedicated server
+
+pub mod board;
+pub mod settings;
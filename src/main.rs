@@ -1,11 +1,382 @@
 //! board game
 
-mod board;
 mod ui;
 
 use bevy::prelude::*;
+use blocks::board;
+use blocks::settings;
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Seed of the first board
+    #[arg(long, value_parser = board::parse_seed)]
+    seed: Option<u64>,
+
+    /// Play a best of N boards match, players take turns moving first
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    num_games: u16,
+
+    /// Who plays the first seat, instead of the one from the settings
+    #[arg(long, value_enum)]
+    player_one: Option<board::Controller>,
+
+    /// Who plays the second seat, instead of the one from the settings
+    #[arg(long, value_enum)]
+    player_two: Option<board::Controller>,
+
+    /// Local profile credited with the results of the first seat
+    #[arg(long, value_parser = board::parse_profile_name)]
+    profile_one: Option<String>,
+
+    /// Local profile credited with the results of the second seat
+    #[arg(long, value_parser = board::parse_profile_name)]
+    profile_two: Option<String>,
+
+    /// Handicap for the first seat, e.g. `territory=2,moves=1,waivers=3`
+    #[arg(long, default_value = "")]
+    handicap_one: board::Handicap,
+
+    /// Handicap for the second seat, e.g. `territory=2,moves=1,waivers=3`
+    #[arg(long, default_value = "")]
+    handicap_two: board::Handicap,
+
+    /// Allow picking the opponent current color
+    #[arg(long)]
+    allow_opponent_color: bool,
+
+    /// Forbid picking the color a player had before their last move
+    #[arg(long)]
+    forbid_previous_color: bool,
+
+    /// Forbid picking the color the opponent picked on their last turn
+    #[arg(long)]
+    forbid_opponent_last_pick: bool,
+
+    /// Allow passing instead of picking a color
+    #[arg(long)]
+    allow_pass: bool,
+
+    /// Ranked game, moves can't be taken back
+    #[arg(long)]
+    ranked: bool,
+
+    /// Write the game record to this file once the game is over
+    #[arg(long)]
+    record: Option<String>,
+
+    /// Replay the moves of a game record file
+    #[arg(long)]
+    load_record: Option<String>,
+
+    /// Only replay this many moves of the loaded game record
+    #[arg(long, requires = "load_record")]
+    load_position: Option<usize>,
+
+    /// Browse the loaded game record in the replay viewer
+    #[arg(long, requires = "load_record")]
+    replay: bool,
+
+    /// Where the unfinished game is saved on exit and with `S`, and resumed from,
+    /// defaults to `blocks/blocks.save` in the user config directory
+    #[arg(long)]
+    save_file: Option<String>,
+
+    /// Start a new game instead of resuming the saved one
+    #[arg(long)]
+    new_game: bool,
+
+    /// Wait for an opponent on this port and play them over the network
+    #[arg(long, conflicts_with_all = ["join", "load_record", "daily", "puzzles"])]
+    host: Option<u16>,
+
+    /// Play against the game hosted at this address, e.g. `192.168.1.20:7878`
+    #[arg(long, conflicts_with_all = ["seed", "load_record", "daily", "puzzles"])]
+    join: Option<String>,
+
+    /// Pick or open a game in the lobby of a `blocks-server --lobby` at this
+    /// address, the games opened use the board, rules and clock given here
+    #[arg(long, conflicts_with_all = ["host", "join", "watch", "seed", "load_record", "daily", "puzzles"])]
+    lobby: Option<String>,
+
+    /// Let bots play the seats set to `bot` through an HTTP/JSON API on this
+    /// port of localhost, see `GET /game`
+    #[arg(long, conflicts_with_all = ["host", "join", "watch", "lobby", "daily", "puzzles"])]
+    arena: Option<u16>,
+
+    /// Play one turn of a correspondence game: open the turn file the
+    /// opponent sent, or start a new game if it doesn't exist, and write the
+    /// file back once the move is played
+    #[arg(long, conflicts_with_all = ["host", "join", "watch", "lobby", "arena", "daily", "puzzles", "load_record"])]
+    correspondence: Option<String>,
+
+    /// Let spectators follow the match from this port, read-only
+    #[arg(long, conflicts_with_all = ["puzzles", "watch"])]
+    publish: Option<u16>,
+
+    /// Follow the match published at this address, e.g. `192.168.1.20:7879`
+    #[arg(long, conflicts_with_all = ["host", "join", "seed", "load_record", "daily", "puzzles"])]
+    watch: Option<String>,
+
+    /// Play the puzzles of a pack, picking up at the first one not solved yet
+    #[arg(long, conflicts_with_all = ["seed", "load_record", "daily"])]
+    puzzles: Option<String>,
+
+    /// Puzzle of the pack to start with, counted from 1
+    #[arg(long, requires = "puzzles")]
+    puzzle: Option<usize>,
+
+    /// Play the board of the day against the computer, results go to the
+    /// daily leaderboard
+    #[arg(long, conflicts_with_all = ["seed", "load_record"])]
+    daily: bool,
+
+    /// Settings file, defaults to `blocks/settings.cfg` in the user config directory
+    #[arg(long)]
+    settings: Option<std::path::PathBuf>,
+
+    /// Only reveal tiles within this distance of each player territory
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    fog_radius: Option<u16>,
+
+    /// Total clock time per player, in seconds
+    #[arg(long, conflicts_with = "clock_per_move", value_parser = board::parse_clock_seconds)]
+    clock_total: Option<f32>,
+
+    /// Seconds added to the clock after each move
+    #[arg(long, default_value_t = 0.0, requires = "clock_total", value_parser = board::parse_clock_increment)]
+    clock_increment: f32,
+
+    /// Fixed clock time per move, in seconds
+    #[arg(long, value_parser = board::parse_clock_seconds)]
+    clock_per_move: Option<f32>,
+
+    /// Play an automatic move instead of losing when the clock runs out
+    #[arg(long)]
+    auto_move_on_timeout: bool,
+}
 
 fn main() {
+    let args = Args::parse();
+
+    let clock = match (args.clock_total, args.clock_per_move) {
+        (Some(total), _) => Some(board::ClockSetup::Fischer {
+            total,
+            increment: args.clock_increment,
+        }),
+        (None, Some(seconds)) => Some(board::ClockSetup::PerMove { seconds }),
+        (None, None) => None,
+    };
+
+    let settings_path = args
+        .settings
+        .clone()
+        .or_else(|| settings::config_path("settings.cfg"));
+    let settings = settings::Settings::load(&settings_path);
+    let save_file = args.save_file.clone().or_else(|| {
+        settings::config_path("blocks.save").map(|path| path.to_string_lossy().into_owned())
+    });
+
+    let default_setup = board::GameSetup::default();
+    let mut setup = board::GameSetup {
+        seed: args.seed.unwrap_or(default_setup.seed),
+        board_width: settings.board_width,
+        board_height: settings.board_height,
+        num_colors: settings.num_colors,
+        num_games: args.num_games as usize,
+        player_one: args.player_one.unwrap_or(settings.player_one),
+        player_two: args.player_two.unwrap_or(settings.player_two),
+        player_one_handicap: args.handicap_one,
+        player_two_handicap: args.handicap_two,
+        player_one_profile: args.profile_one,
+        player_two_profile: args.profile_two,
+        profiles_path: settings::config_path("profiles.cfg"),
+        daily_path: settings::config_path("daily.cfg"),
+        puzzles_path: settings::config_path("puzzles.cfg"),
+        correspondence_path: settings::config_path("correspondence.cfg"),
+        rejected_path: settings::config_path("rejected.log"),
+        rules: board::RuleSet {
+            allow_opponent_color: args.allow_opponent_color,
+            forbid_previous_color: args.forbid_previous_color,
+            forbid_opponent_last_pick: args.forbid_opponent_last_pick,
+            allow_pass: args.allow_pass,
+        },
+        ranked: args.ranked,
+        fog_radius: args.fog_radius.map(|radius| radius as usize),
+        clock,
+        auto_move_on_timeout: args.auto_move_on_timeout,
+        record_path: args.record,
+        save_path: save_file.clone(),
+        ..default_setup
+    };
+
+    // the daily challenge is a fresh game that leaves the saved one alone
+    if args.daily {
+        let Some(date) = board::today() else {
+            eprintln!("the daily challenge needs the local date");
+            std::process::exit(1);
+        };
+        board::setup_daily(&mut setup, date);
+    }
+
+    if let Some(path) = &args.puzzles {
+        let started = board::PuzzlePack::load(path)
+            .and_then(|pack| board::start_pack(&mut setup, pack, args.puzzle));
+        if let Err(err) = started {
+            eprintln!("can't start puzzles: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let network = match (args.host, &args.join, &args.watch) {
+        (Some(port), _, _) => Some(board::host(&mut setup, port)),
+        (_, Some(address), _) => Some(board::join(&mut setup, address)),
+        (_, _, Some(address)) => Some(board::watch(&mut setup, address)),
+        _ => None,
+    };
+    let network = match network {
+        Some(Ok(network)) => Some(network),
+        Some(Err(err)) => {
+            eprintln!("can't start the online game: {}", err);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let lobby = args
+        .lobby
+        .as_ref()
+        .map(|address| match board::enter_lobby(&mut setup, address) {
+            Ok(lobby) => lobby,
+            Err(err) => {
+                eprintln!("can't enter the lobby: {}", err);
+                std::process::exit(1);
+            }
+        });
+
+    // an explicit record wins over the saved game
+    let can_resume = !args.new_game
+        && !args.daily
+        && args.puzzles.is_none()
+        && network.is_none()
+        && lobby.is_none()
+        && args.arena.is_none()
+        && args.correspondence.is_none()
+        && args.load_record.is_none();
+    if can_resume
+        && let Some(save_file) = &save_file
+        && std::path::Path::new(save_file).exists()
+    {
+        match board::SavedGame::load(save_file) {
+            Ok(saved) => {
+                eprintln!(
+                    "resuming the game saved in {}, pass --new-game to start over",
+                    save_file
+                );
+                saved.apply_to(&mut setup);
+                setup.replay_source = Some(board::MoveSource::File(save_file.clone()));
+            }
+            Err(err) => eprintln!("can't resume saved game, starting a new one: {}", err),
+        }
+    }
+
+    if let Some(path) = &args.load_record {
+        match board::GameRecord::load(path) {
+            Ok(record) => {
+                record.apply_to(&mut setup, args.load_position);
+                setup.replay_source = Some(board::MoveSource::File(path.clone()));
+                setup.open_replay_viewer = args.replay;
+            }
+            Err(err) => {
+                eprintln!("can't load game record: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let correspondence =
+        args.correspondence
+            .as_ref()
+            .map(|path| match board::open_turn_file(&mut setup, path) {
+                Ok(correspondence) => correspondence,
+                Err(err) => {
+                    eprintln!("can't open the turn file: {}", err);
+                    std::process::exit(1);
+                }
+            });
+
+    let is_bot = |controller| controller == board::Controller::Bot;
+    if args.arena.is_none() && (is_bot(setup.player_one) || is_bot(setup.player_two)) {
+        eprintln!("bot seats play through the arena, pass --arena PORT");
+        std::process::exit(1);
+    }
+    let arena = args
+        .arena
+        .map(|port| match board::open_arena(&mut setup, port) {
+            Ok(arena) => arena,
+            Err(err) => {
+                eprintln!("can't open the arena: {}", err);
+                std::process::exit(1);
+            }
+        });
+
+    let spectators = args.publish.map(|port| match board::publish(&setup, port) {
+        Ok(spectators) => spectators,
+        Err(err) => {
+            eprintln!("can't publish the match: {}", err);
+            std::process::exit(1);
+        }
+    });
+
+    // the web build joins once the socket is open, the game starts after
+    #[cfg(target_family = "wasm")]
+    if let Some(address) = board::join_address() {
+        wasm_bindgen_futures::spawn_local(async move {
+            let network = match board::join_from_browser(&mut setup, &address).await {
+                Ok(network) => Some(network),
+                Err(err) => {
+                    error!("can't join {}: {}", address, err);
+                    None
+                }
+            };
+            run(
+                setup,
+                settings,
+                settings_path,
+                network,
+                spectators,
+                lobby,
+                arena,
+                correspondence,
+            );
+        });
+        return;
+    }
+
+    run(
+        setup,
+        settings,
+        settings_path,
+        network,
+        spectators,
+        lobby,
+        arena,
+        correspondence,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run(
+    setup: board::GameSetup,
+    settings: settings::Settings,
+    settings_path: Option<std::path::PathBuf>,
+    network: Option<board::NetworkResource>,
+    spectators: Option<board::SpectatorsResource>,
+    lobby: Option<board::LobbyResource>,
+    arena: Option<board::ArenaResource>,
+    correspondence: Option<board::CorrespondenceResource>,
+) {
     let mut app = App::new();
 
     app.add_systems(Startup, |mut commands: Commands| {
@@ -18,7 +389,32 @@ fn main() {
         ));
     });
 
-    app.add_plugins(DefaultPlugins);
+    app.insert_resource(setup);
+    if let Some(network) = network {
+        app.insert_resource(network);
+    }
+    if let Some(spectators) = spectators {
+        app.insert_resource(spectators);
+    }
+    if let Some(lobby) = lobby {
+        app.insert_resource(lobby);
+    }
+    if let Some(arena) = arena {
+        app.insert_resource(arena);
+    }
+    if let Some(correspondence) = correspondence {
+        app.insert_resource(correspondence);
+    }
+
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(settings.window()),
+        ..default()
+    }));
+    app.insert_resource(settings.preferences());
+    app.insert_resource(settings);
+    app.add_plugins(settings::SettingsPlugin {
+        path: settings_path,
+    });
     app.add_plugins(board::BoardPlugin);
     // app.add_plugins(ui::UiPlugin);
 
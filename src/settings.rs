@@ -0,0 +1,406 @@
+//! user settings kept between runs
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy::window::WindowPosition;
+
+use crate::board;
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+pub struct SettingsPlugin {
+    pub path: Option<PathBuf>,
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>();
+        app.insert_resource(SettingsFile {
+            path: self.path.clone(),
+            written: None,
+            changed_since: None,
+        });
+        app.add_systems(
+            Update,
+            (
+                adjust_settings,
+                sync_preferences,
+                track_window,
+                write_settings,
+            )
+                .chain(),
+        );
+        app.add_systems(Last, write_settings_on_exit.run_if(on_event::<AppExit>));
+    }
+}
+
+/// Changes are written once they settle, e.g. at the end of a window drag.
+const WRITE_DELAY: f32 = 1.0;
+const VOLUME_STEP: f32 = 0.1;
+const MAX_VOLUME: f32 = 2.0;
+
+/// Preferences read from the settings file, e.g.
+///
+/// ```text
+/// board_width = 14
+/// board_height = 7
+/// num_colors = 4
+/// player_one = human
+/// player_two = computer
+/// ambience_volume = 1
+/// effects_volume = 0.5
+/// palette = color-blind
+/// window_width = 1280
+/// window_height = 720
+/// window_x = 100
+/// window_y = 80
+/// muted = bob
+/// ```
+///
+/// Board size, colors and seats apply to the next game. `muted` is repeated
+/// for each online opponent whose chat is hidden.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub board_width: usize,
+    pub board_height: usize,
+    pub num_colors: usize,
+    pub player_one: board::Controller,
+    pub player_two: board::Controller,
+    pub ambience_volume: f32,
+    pub effects_volume: f32,
+    pub palette: board::Palette,
+    pub window_width: f32,
+    pub window_height: f32,
+    /// Left to the system when missing.
+    pub window_position: Option<IVec2>,
+    pub muted_players: BTreeSet<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let setup = board::GameSetup::default();
+        let preferences = board::Preferences::default();
+        Self {
+            board_width: setup.board_width,
+            board_height: setup.board_height,
+            num_colors: setup.num_colors,
+            player_one: setup.player_one,
+            player_two: setup.player_two,
+            ambience_volume: preferences.ambience_volume,
+            effects_volume: preferences.effects_volume,
+            palette: preferences.palette,
+            window_width: 1280.0,
+            window_height: 720.0,
+            window_position: None,
+            muted_players: preferences.muted_players,
+        }
+    }
+}
+
+impl std::fmt::Display for Settings {
+    fn fmt(&self, ff: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(ff, "board_width = {}", self.board_width)?;
+        writeln!(ff, "board_height = {}", self.board_height)?;
+        writeln!(ff, "num_colors = {}", self.num_colors)?;
+        writeln!(ff, "player_one = {}", value_name(&self.player_one))?;
+        writeln!(ff, "player_two = {}", value_name(&self.player_two))?;
+        writeln!(ff, "ambience_volume = {}", self.ambience_volume)?;
+        writeln!(ff, "effects_volume = {}", self.effects_volume)?;
+        writeln!(ff, "palette = {}", value_name(&self.palette))?;
+        writeln!(ff, "window_width = {}", self.window_width)?;
+        writeln!(ff, "window_height = {}", self.window_height)?;
+        if let Some(position) = self.window_position {
+            writeln!(ff, "window_x = {}", position.x)?;
+            writeln!(ff, "window_y = {}", position.y)?;
+        }
+        for name in self.muted_players.iter() {
+            writeln!(ff, "muted = {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+fn value_name<T: clap::ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("invalid {key} {value:?}: {err}"))
+}
+
+fn parse_volume(key: &str, value: &str) -> Result<f32, String> {
+    let volume: f32 = parse_value(key, value)?;
+    if !(0.0..=MAX_VOLUME).contains(&volume) {
+        return Err(format!("{key} {volume} is not within 0 to {MAX_VOLUME}"));
+    }
+    Ok(volume)
+}
+
+impl Settings {
+    /// Parse `key = value` lines, a bad line keeps the default and is reported
+    /// along with the others.
+    pub fn parse(content: &str) -> (Self, Vec<String>) {
+        let mut settings = Self::default();
+        let mut errors = vec![];
+        let mut window_x = None;
+        let mut window_y = None;
+
+        for line in content.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                errors.push(format!("expected key = value, got {line:?}"));
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let parsed = match key {
+                "board_width" => parse_value(key, value).map(|width| settings.board_width = width),
+                "board_height" => {
+                    parse_value(key, value).map(|height| settings.board_height = height)
+                }
+                "num_colors" => parse_value(key, value).map(|count| settings.num_colors = count),
+                "player_one" => clap::ValueEnum::from_str(value, true)
+                    .map(|controller| settings.player_one = controller),
+                "player_two" => clap::ValueEnum::from_str(value, true)
+                    .map(|controller| settings.player_two = controller),
+                "ambience_volume" => {
+                    parse_volume(key, value).map(|volume| settings.ambience_volume = volume)
+                }
+                "effects_volume" => {
+                    parse_volume(key, value).map(|volume| settings.effects_volume = volume)
+                }
+                "palette" => {
+                    clap::ValueEnum::from_str(value, true).map(|palette| settings.palette = palette)
+                }
+                "window_width" => {
+                    parse_value(key, value).map(|width| settings.window_width = width)
+                }
+                "window_height" => {
+                    parse_value(key, value).map(|height| settings.window_height = height)
+                }
+                "window_x" => parse_value(key, value).map(|x| window_x = Some(x)),
+                "window_y" => parse_value(key, value).map(|y| window_y = Some(y)),
+                "muted" if !value.is_empty() => {
+                    settings.muted_players.insert(value.into());
+                    Ok(())
+                }
+                _ => Err(format!("unknown setting {key:?}")),
+            };
+            if let Err(err) = parsed {
+                errors.push(err);
+            }
+        }
+
+        let default_settings = Self::default();
+        if let Err(err) = board::check_board(
+            settings.board_width,
+            settings.board_height,
+            settings.num_colors,
+        ) {
+            errors.push(err);
+            settings.board_width = default_settings.board_width;
+            settings.board_height = default_settings.board_height;
+            settings.num_colors = default_settings.num_colors;
+        }
+        if settings.window_width < 200.0 || settings.window_height < 200.0 {
+            errors.push("window is too small".into());
+            settings.window_width = default_settings.window_width;
+            settings.window_height = default_settings.window_height;
+        }
+        if let (Some(x), Some(y)) = (window_x, window_y) {
+            settings.window_position = Some(IVec2::new(x, y));
+        }
+
+        (settings, errors)
+    }
+
+    /// Defaults when the file is missing, every problem is reported on stderr
+    /// as the logger isn't up yet.
+    pub fn load(path: &Option<PathBuf>) -> Self {
+        let Some(path) = path else {
+            return Self::default();
+        };
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                eprintln!("can't read settings {}: {}", path.display(), err);
+                return Self::default();
+            }
+        };
+        let (settings, errors) = Self::parse(&content);
+        for err in errors {
+            eprintln!("settings {}: {}", path.display(), err);
+        }
+        settings
+    }
+
+    /// What the board gets to see of the settings.
+    pub fn preferences(&self) -> board::Preferences {
+        board::Preferences {
+            palette: self.palette,
+            ambience_volume: self.ambience_volume,
+            effects_volume: self.effects_volume,
+            muted_players: self.muted_players.clone(),
+        }
+    }
+
+    pub fn window(&self) -> Window {
+        Window {
+            resolution: (self.window_width, self.window_height).into(),
+            position: match self.window_position {
+                Some(position) => WindowPosition::At(position),
+                None => WindowPosition::Automatic,
+            },
+            ..default()
+        }
+    }
+}
+
+/// File in the `blocks` folder of the user config directory, none on the web.
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    let env_path = |key: &str| std::env::var_os(key).map(PathBuf::from);
+    let config_dir = if cfg!(target_family = "wasm") {
+        None
+    } else if cfg!(target_os = "windows") {
+        env_path("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_path("XDG_CONFIG_HOME").or_else(|| env_path("HOME").map(|home| home.join(".config")))
+    };
+    config_dir.map(|config_dir| config_dir.join("blocks").join(file_name))
+}
+
+#[derive(Resource)]
+struct SettingsFile {
+    path: Option<PathBuf>,
+    written: Option<Settings>,
+    changed_since: Option<f32>,
+}
+
+impl SettingsFile {
+    fn write(&mut self, settings: &Settings) {
+        self.written = Some(settings.clone());
+        self.changed_since = None;
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(parent) = path.parent()
+            && let Err(err) = std::fs::create_dir_all(parent)
+        {
+            error!("can't create {}: {}", parent.display(), err);
+            return;
+        }
+        match std::fs::write(path, settings.to_string()) {
+            Ok(()) => info!("settings written to {}", path.display()),
+            Err(err) => error!("can't write settings to {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// `T` switches the palette, `-` and `=` set the effects volume, `[` and `]`
+/// the ambience volume.
+fn adjust_settings(mut settings: ResMut<Settings>, keyboard: Res<ButtonInput<KeyCode>>) {
+    if keyboard.just_pressed(KeyCode::KeyT) {
+        settings.palette = match settings.palette {
+            board::Palette::Classic => board::Palette::ColorBlind,
+            board::Palette::ColorBlind => board::Palette::Classic,
+        };
+        info!("palette {:?}", settings.palette);
+    }
+
+    let step = |volume: f32, delta: f32| {
+        let volume = (volume + delta).clamp(0.0, MAX_VOLUME);
+        (volume / VOLUME_STEP).round() * VOLUME_STEP
+    };
+    if keyboard.just_pressed(KeyCode::Minus) {
+        settings.effects_volume = step(settings.effects_volume, -VOLUME_STEP);
+        info!("effects volume {}", settings.effects_volume);
+    }
+    if keyboard.just_pressed(KeyCode::Equal) {
+        settings.effects_volume = step(settings.effects_volume, VOLUME_STEP);
+        info!("effects volume {}", settings.effects_volume);
+    }
+    if keyboard.just_pressed(KeyCode::BracketLeft) {
+        settings.ambience_volume = step(settings.ambience_volume, -VOLUME_STEP);
+        info!("ambience volume {}", settings.ambience_volume);
+    }
+    if keyboard.just_pressed(KeyCode::BracketRight) {
+        settings.ambience_volume = step(settings.ambience_volume, VOLUME_STEP);
+        info!("ambience volume {}", settings.ambience_volume);
+    }
+}
+
+/// The palette and volumes go to the board, the players muted from the chat
+/// come back to be written. Only differences are copied, so that neither side
+/// looks changed every frame.
+fn sync_preferences(mut settings: ResMut<Settings>, mut preferences: ResMut<board::Preferences>) {
+    if preferences.muted_players != settings.muted_players {
+        settings.muted_players = preferences.muted_players.clone();
+    }
+    if preferences.palette != settings.palette
+        || preferences.ambience_volume != settings.ambience_volume
+        || preferences.effects_volume != settings.effects_volume
+    {
+        preferences.palette = settings.palette;
+        preferences.ambience_volume = settings.ambience_volume;
+        preferences.effects_volume = settings.effects_volume;
+    }
+}
+
+fn track_window(
+    window: Option<Single<&Window, With<PrimaryWindow>>>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(window) = window else {
+        return;
+    };
+    let (width, height) = (window.resolution.width(), window.resolution.height());
+    if settings.window_width != width || settings.window_height != height {
+        settings.window_width = width;
+        settings.window_height = height;
+    }
+    if let WindowPosition::At(position) = window.position
+        && settings.window_position != Some(position)
+    {
+        settings.window_position = Some(position);
+    }
+}
+
+fn write_settings(settings: Res<Settings>, mut file: ResMut<SettingsFile>, time: Res<Time>) {
+    // the settings as loaded don't need writing back
+    let Some(written) = &file.written else {
+        file.written = Some(settings.clone());
+        return;
+    };
+    if *written == *settings {
+        file.changed_since = None;
+        return;
+    }
+    let now = time.elapsed().as_secs_f32();
+    let since = *file.changed_since.get_or_insert(now);
+    if now - since >= WRITE_DELAY {
+        file.write(&settings);
+    }
+}
+
+fn write_settings_on_exit(settings: Res<Settings>, mut file: ResMut<SettingsFile>) {
+    if file
+        .written
+        .as_ref()
+        .is_some_and(|written| *written != *settings)
+    {
+        file.write(&settings);
+    }
+}